@@ -1,11 +1,13 @@
 import React from 'react'
 import Classnames from 'classnames'
+import {getPasskey} from './webauthn'
 
 
 export default class LoginForm extends React.Component {
     constructor(props) {
         super(props)
         this.handleLogin = this.handleLogin.bind(this)
+        this.handlePasskey = this.handlePasskey.bind(this)
         this.abortRequests = this.abortRequests.bind(this)
         this.state = {
             error: null,
@@ -75,6 +77,43 @@ export default class LoginForm extends React.Component {
 
     }
 
+    // second factor or passwordless login, depending on whether a password was accepted already
+    handlePasskey() {
+        if (this.state.mask)
+            return
+
+        this.abortRequests()
+        this.setState({
+            error: null,
+            mask: true
+        })
+        const error = function (error) {
+            this.serverRequest = null
+            this.setState({
+                error: error.toString(),
+                mask: false
+            })
+        }.bind(this)
+
+        this.serverRequest = KeePass4Web.fetch('passkey_login_start', {
+            success: function (options) {
+                getPasskey(options).then(function (response) {
+                    this.serverRequest = KeePass4Web.fetch('passkey_login_finish', {
+                        json: response,
+                        success: function (data) {
+                            KeePass4Web.setCSRFToken(data.csrf_token)
+                            KeePass4Web.setSettings(data.settings)
+                            this.serverRequest = null
+                            this.authRequest = KeePass4Web.checkAuth.call(this)
+                        }.bind(this),
+                        error: error,
+                    })
+                }.bind(this)).catch(error)
+            }.bind(this),
+            error: error,
+        })
+    }
+
     componentDidMount() {
         // default 10 minutes
         this.timerId = setInterval(function () {
@@ -72,6 +72,9 @@ class NavBar extends React.Component {
             dropdown = (
                 <ul className="dropdown-menu">
                     <li><a id="logout">Logout</a></li>
+                    <li><Link to="/tokens">Access Tokens</Link></li>
+                    <li><Link to="/two_factor">Two-Factor Authentication</Link></li>
+                    <li><Link to="/passkeys">Passkeys</Link></li>
                     <li role="separator" className="divider"></li>
                     <li><a id="closeDB" style={closeDbHidden ? {visibility: 'hidden'} : {}}>Close Database</a></li>
                 </ul>
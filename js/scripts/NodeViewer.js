@@ -55,7 +55,8 @@ class NodeViewer extends React.Component {
                 name: name
             },
             success: function (data) {
-                this.setHide(target, false, name, data)
+                // show the resolved value of references, the raw one on hover
+                this.setHide(target, false, name, data.resolved != null ? data.resolved : data.value)
 
                 // hide password after X seconds
                 setTimeout(this.PWTimeout.bind(this, target, name), this.props.timeoutSec)
@@ -88,18 +89,61 @@ class NodeViewer extends React.Component {
                 name: name
             },
             success: function (data) {
-                this.copyHandler(data, event)
+                this.copyHandler(data.resolved != null ? data.resolved : data.value, event)
             }.bind(this),
             error: KeePass4Web.error.bind(this),
         })
         this.setHide(target, true, name)
     }
 
+    strengthHandler(name, event) {
+        let entry = this.props.entry
+
+        this.serverRequest = KeePass4Web.fetch('get_password_strength', {
+            method: 'GET',
+            data: {
+                entry_id: entry.id,
+                name: name
+            },
+            success: function (data) {
+                entry.strength = data
+                this.forceUpdate()
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    OTPHandler(event) {
+        event.persist()
+        let entry = this.props.entry
+
+        this.serverRequest = KeePass4Web.fetch('get_totp', {
+            json: {
+                id: entry.id,
+            },
+            success: function (data) {
+                entry.otp_code = data.code
+                entry.otp_remaining = data.remaining
+                this.forceUpdate()
+                this.copyHandler(data.code, event)
+
+                // hide code once it expired, HOTP codes after the usual timeout
+                let timeout = data.remaining ? data.remaining * 1000 : this.props.timeoutSec
+                setTimeout(function () {
+                    entry.otp_code = null
+                    entry.otp_remaining = null
+                    this.forceUpdate()
+                }.bind(this), timeout)
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
     downloadHandler(filename, event) {
         // mostly taken from http://stackoverflow.com/questions/16086162/handle-file-download-from-ajax-post
         // with adjustments
         let xhr = new XMLHttpRequest()
-        xhr.open('GET', 'get_file', true)
+        xhr.open('GET', 'api/v1/get_file?entry_id=' + encodeURIComponent(this.props.entry.id) + '&filename=' + encodeURIComponent(filename), true)
         xhr.responseType = 'arraybuffer'
         xhr.setRequestHeader('X-CSRF-Token', KeePass4Web.getCSRFToken())
         xhr.onload = function () {
@@ -144,12 +188,25 @@ class NodeViewer extends React.Component {
                 KeePass4Web.error(xhr, null, xhr.responseText)
             }
         }
-        xhr.setRequestHeader('Content-type', 'application/x-www-form-urlencoded; charset=UTF-8')
         xhr.setRequestHeader('X-Requested-With', 'XMLHttpRequest')
 
         KeePass4Web.restartTimer(true)
 
-        xhr.send('id=' + encodeURIComponent(this.props.entry.id) + '&filename=' + encodeURIComponent(filename))
+        xhr.send()
+    }
+
+    resolvedValue(name, value) {
+        let resolved = this.props.entry.resolved
+        if (!resolved || !resolved.hasOwnProperty(name))
+            return value
+        return <span title={value}>{resolved[name]}</span>
+    }
+
+    resolvedCopyValue(name, value) {
+        let resolved = this.props.entry.resolved
+        if (!resolved || !resolved.hasOwnProperty(name))
+            return value
+        return resolved[name]
     }
 
     PWTimeout(target, name) {
@@ -192,7 +249,7 @@ class NodeViewer extends React.Component {
                             {
                                 entry.protected && entry.protected.hasOwnProperty(string) ?
                                     (strings[string] == null ? '******' : strings[string])
-                                    : strings[string]
+                                    : this.resolvedValue(string, strings[string])
                             }
                         </td>
                         {entry.protected && entry.protected.hasOwnProperty(string) ?
@@ -234,7 +291,7 @@ class NodeViewer extends React.Component {
                 files.push(
                     <tr key={file}>
                         <td colSpan="2" className="kp-wrap">
-                            {file}
+                            {file} <span className="text-muted">({binary[file]} bytes)</span>
                         </td>
                         <td>
                             <button
@@ -268,7 +325,7 @@ class NodeViewer extends React.Component {
             <div className={classes}>
                 <div className="panel-heading">
                     {icon}
-                    {entry.title}
+                    {this.resolvedValue('Title', entry.title)}
                 </div>
                 <div className="panel-body">
                     <table className="table table-hover table-condensed kp-table">
@@ -283,11 +340,11 @@ class NodeViewer extends React.Component {
                                 Username
                             </td>
                             <td className="kp-wrap">
-                                {entry.username}
+                                {this.resolvedValue('UserName', entry.username)}
                             </td>
                             <td>
                                 <button
-                                    onClick={this.copyHandler.bind(this, entry.username)}
+                                    onClick={this.copyHandler.bind(this, this.resolvedCopyValue('UserName', entry.username))}
                                     type="button"
                                     className="btn btn-default btn-sm"
                                 >
@@ -301,6 +358,13 @@ class NodeViewer extends React.Component {
                             </td>
                             <td className="kp-wrap">
                                 {entry.password == null ? '******' : entry.password}
+                                {entry.strength ?
+                                    <div className="text-muted">
+                                        {['Very weak', 'Weak', 'Fair', 'Strong', 'Very strong'][entry.strength.score]}
+                                        {' (offline cracking: ' + entry.strength.crack_times.offline_slow_hash + ')'}
+                                        {entry.strength.warning ? ' - ' + entry.strength.warning : null}
+                                    </div>
+                                    : null}
                             </td>
                             <td>
                                 <div className="btn-group" role="group">
@@ -318,15 +382,42 @@ class NodeViewer extends React.Component {
                                     >
                                         <span className="glyphicon glyphicon-copy"></span>
                                     </button>
+                                    <button
+                                        onClick={this.strengthHandler.bind(this, 'password')}
+                                        type="button"
+                                        className="btn btn-default btn-sm"
+                                    >
+                                        <span className="glyphicon glyphicon-signal"></span>
+                                    </button>
                                 </div>
                             </td>
                         </tr>
+                        {entry.otp ?
+                            <tr>
+                                <td className="kp-wrap">
+                                    OTP
+                                </td>
+                                <td className="kp-wrap">
+                                    {entry.otp_code == null ? '******' : entry.otp_code}
+                                    {entry.otp_remaining ? <span className="text-muted"> ({entry.otp_remaining}s)</span> : null}
+                                </td>
+                                <td>
+                                    <button
+                                        onClick={this.OTPHandler.bind(this)}
+                                        type="button"
+                                        className="btn btn-default btn-sm"
+                                    >
+                                        <span className="glyphicon glyphicon-time"></span>
+                                    </button>
+                                </td>
+                            </tr>
+                            : null}
                         <tr>
                             <td>
                                 URL
                             </td>
                             <td className="kp-wrap">
-                                <a href={entry.url} target="_blank" rel="noopener noreferrer">{entry.url}</a>
+                                <a href={this.resolvedCopyValue('URL', entry.url)} target="_blank" rel="noopener noreferrer">{this.resolvedValue('URL', entry.url)}</a>
                             </td>
                             <td>
                             </td>
@@ -336,7 +427,7 @@ class NodeViewer extends React.Component {
                                 Notes
                             </td>
                             <td className="kp-wrap-comment">
-                                {entry.notes}
+                                {this.resolvedValue('Notes', entry.notes)}
                             </td>
                             <td>
                             </td>
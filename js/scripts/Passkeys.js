@@ -0,0 +1,166 @@
+import React from 'react'
+import {Link} from "react-router-dom"
+import NavBar from './NavBar'
+import Alert from './Alert'
+import withNavigateHook from './nagivateHook'
+import {createPasskey, getPasskey, isSupported} from './webauthn'
+
+class Passkeys extends React.Component {
+    constructor(props) {
+        super(props)
+        this.state = {
+            status: null,
+            error: false,
+        }
+        this.handleRegister = this.handleRegister.bind(this)
+    }
+
+    componentDidMount() {
+        this.load()
+    }
+
+    componentWillUnmount() {
+        if (this.serverRequest)
+            this.serverRequest.abort()
+    }
+
+    load() {
+        this.serverRequest = KeePass4Web.fetch('passkeys', {
+            method: 'GET',
+            success: function (data) {
+                this.setState({
+                    status: data,
+                })
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    handleRegister(event) {
+        event.preventDefault()
+
+        const name = this.refs.name.value
+        this.serverRequest = KeePass4Web.fetch('passkey_register_start', {
+            success: function (options) {
+                createPasskey(options, name).then(function (response) {
+                    this.serverRequest = KeePass4Web.fetch('passkey_register_finish', {
+                        json: response,
+                        success: function () {
+                            this.refs.name.value = ''
+                            this.setState({
+                                error: false,
+                            })
+                            this.load()
+                        }.bind(this),
+                        error: KeePass4Web.error.bind(this),
+                    })
+                }.bind(this)).catch(KeePass4Web.error.bind(this))
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    handleRemove(id) {
+        if (!confirm('Remove this passkey? It can no longer be used to log in.'))
+            return
+
+        // confirmed with one of the passkeys, or a code from the authenticator app if none is at hand
+        this.serverRequest = KeePass4Web.fetch('passkey_verify_start', {
+            success: function (options) {
+                getPasskey(options).then(function (assertion) {
+                    this.remove({id: id, assertion: assertion})
+                }.bind(this)).catch(function () {
+                    const code = prompt('Authenticator app or recovery code')
+                    if (code)
+                        this.remove({id: id, code: code})
+                }.bind(this))
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    remove(params) {
+        this.serverRequest = KeePass4Web.fetch('passkey_remove', {
+            json: params,
+            success: function () {
+                this.setState({
+                    error: false,
+                })
+                this.load()
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    render() {
+        const status = this.state.status
+        let content
+        if (!status) {
+            content = null
+        } else if (!status.enabled) {
+            content = <p>Passkeys are not enabled on this server.</p>
+        } else {
+            let rows = status.passkeys.map(passkey => (
+                <tr key={passkey.id}>
+                    <td>{passkey.name}</td>
+                    <td>{passkey.created}</td>
+                    <td>{passkey.last_used || 'Never'}</td>
+                    <td>
+                        <button className="btn btn-danger btn-xs" onClick={this.handleRemove.bind(this, passkey.id)}>
+                            Remove
+                        </button>
+                    </td>
+                </tr>
+            ))
+
+            let register
+            if (isSupported()) {
+                register = (
+                    <form className="form-inline" onSubmit={this.handleRegister}>
+                        <input className="form-control" type="text" ref="name" placeholder="Name" required/>
+                        <button className="btn btn-success" type="submit">Register</button>
+                    </form>
+                )
+            } else {
+                register = <p>This browser doesn't support passkeys.</p>
+            }
+
+            content = (
+                <div>
+                    <p>
+                        Passkeys can be used instead of a code from an authenticator app after entering the password.
+                        {status.passwordless ? ' They can also be used to log in without a password.' : ''}
+                    </p>
+                    {register}
+                    <table className="table table-condensed">
+                        <thead>
+                        <tr>
+                            <th>Name</th>
+                            <th>Created</th>
+                            <th>Last Used</th>
+                            <th></th>
+                        </tr>
+                        </thead>
+                        <tbody>
+                        {rows}
+                        </tbody>
+                    </table>
+                </div>
+            )
+        }
+
+        return (
+            <div>
+                <NavBar/>
+                <div className="container">
+                    <h4>Passkeys</h4>
+                    {content}
+                    <Alert error={this.state.error}/>
+                    <Link to="/keepass">Back</Link>
+                </div>
+            </div>
+        )
+    }
+}
+
+export default withNavigateHook(Passkeys)
@@ -0,0 +1,153 @@
+import React from 'react'
+import {Link} from "react-router-dom"
+import NavBar from './NavBar'
+import Alert from './Alert'
+import withNavigateHook from './nagivateHook'
+
+class Tokens extends React.Component {
+    constructor(props) {
+        super(props)
+        this.state = {
+            tokens: [],
+            created: null,
+            error: false,
+        }
+        this.handleCreate = this.handleCreate.bind(this)
+    }
+
+    componentDidMount() {
+        this.load()
+    }
+
+    componentWillUnmount() {
+        if (this.serverRequest)
+            this.serverRequest.abort()
+    }
+
+    load() {
+        this.serverRequest = KeePass4Web.fetch('tokens', {
+            method: 'GET',
+            success: function (data) {
+                this.setState({
+                    tokens: data,
+                })
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    handleCreate(event) {
+        event.preventDefault()
+
+        let data = {
+            name: this.refs.name.value,
+            read_only: this.refs.read_only.checked,
+        }
+        // empty for tokens that don't expire
+        if (this.refs.expires_in.value)
+            data.expires_in = this.refs.expires_in.value
+
+        this.serverRequest = KeePass4Web.fetch('create_token', {
+            data: data,
+            success: function (data) {
+                this.refs.name.value = ''
+                this.setState({
+                    created: data.token,
+                    error: false,
+                })
+                this.load()
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    handleRevoke(id) {
+        if (!confirm('Revoke this token? Applications using it will lose access.'))
+            return
+
+        this.serverRequest = KeePass4Web.fetch('revoke_token', {
+            data: {
+                id: id,
+            },
+            success: function () {
+                this.load()
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    render() {
+        let created
+        if (this.state.created) {
+            created = (
+                <div className="alert alert-success">
+                    Copy the new token now, it won't be shown again:
+                    <input className="form-control" type="text" readOnly value={this.state.created}
+                           onFocus={event => event.target.select()}/>
+                </div>
+            )
+        }
+
+        let rows = this.state.tokens.map(token => (
+            <tr key={token.id}>
+                <td>{token.name}</td>
+                <td>{token.read_only ? 'Read-only' : 'Full'}</td>
+                <td>{token.created}</td>
+                <td>{token.expires || 'Never'}</td>
+                <td>
+                    <button className="btn btn-danger btn-xs" onClick={this.handleRevoke.bind(this, token.id)}>
+                        Revoke
+                    </button>
+                </td>
+            </tr>
+        ))
+
+        return (
+            <div>
+                <NavBar/>
+                <div className="container">
+                    <h4>Personal Access Tokens</h4>
+                    <p>
+                        Tokens are sent as <code>Authorization: Bearer</code> header to the API.
+                        Each token opens the database in its own session.
+                    </p>
+                    {created}
+                    <form className="form-inline" onSubmit={this.handleCreate}>
+                        <input className="form-control" type="text" ref="name" placeholder="Name" required/>
+                        <select className="form-control" ref="expires_in" defaultValue="30days">
+                            <option value="7days">7 days</option>
+                            <option value="30days">30 days</option>
+                            <option value="90days">90 days</option>
+                            <option value="1year">1 year</option>
+                            <option value="">Never</option>
+                        </select>
+                        <div className="checkbox">
+                            <label>
+                                <input type="checkbox" ref="read_only" defaultChecked/> Read-only
+                            </label>
+                        </div>
+                        <button className="btn btn-success" type="submit">Create</button>
+                    </form>
+                    <Alert error={this.state.error}/>
+                    <table className="table table-condensed">
+                        <thead>
+                        <tr>
+                            <th>Name</th>
+                            <th>Scope</th>
+                            <th>Created</th>
+                            <th>Expires</th>
+                            <th></th>
+                        </tr>
+                        </thead>
+                        <tbody>
+                        {rows}
+                        </tbody>
+                    </table>
+                    <Link to="/keepass">Back</Link>
+                </div>
+            </div>
+        )
+    }
+}
+
+export default withNavigateHook(Tokens)
@@ -0,0 +1,161 @@
+import React from 'react'
+import {Link} from "react-router-dom"
+import NavBar from './NavBar'
+import Alert from './Alert'
+import withNavigateHook from './nagivateHook'
+
+class TwoFactor extends React.Component {
+    constructor(props) {
+        super(props)
+        this.state = {
+            status: null,
+            enrollment: null,
+            recoveryCodes: null,
+            error: false,
+        }
+        this.handleEnroll = this.handleEnroll.bind(this)
+        this.handleConfirm = this.handleConfirm.bind(this)
+        this.handleDisable = this.handleDisable.bind(this)
+    }
+
+    componentDidMount() {
+        this.load()
+    }
+
+    componentWillUnmount() {
+        if (this.serverRequest)
+            this.serverRequest.abort()
+    }
+
+    load() {
+        this.serverRequest = KeePass4Web.fetch('two_factor', {
+            method: 'GET',
+            success: function (data) {
+                this.setState({
+                    status: data,
+                })
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    handleEnroll() {
+        this.serverRequest = KeePass4Web.fetch('two_factor_enroll', {
+            success: function (data) {
+                this.setState({
+                    enrollment: data,
+                    error: false,
+                })
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    handleConfirm(event) {
+        event.preventDefault()
+
+        this.serverRequest = KeePass4Web.fetch('two_factor_confirm', {
+            data: {
+                code: this.refs.code.value,
+            },
+            success: function (data) {
+                this.setState({
+                    enrollment: null,
+                    recoveryCodes: data.recovery_codes,
+                    error: false,
+                })
+                this.load()
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    handleDisable(event) {
+        event.preventDefault()
+
+        this.serverRequest = KeePass4Web.fetch('two_factor_disable', {
+            data: {
+                code: this.refs.code.value,
+            },
+            success: function () {
+                this.setState({
+                    recoveryCodes: null,
+                    error: false,
+                })
+                this.load()
+            }.bind(this),
+            error: KeePass4Web.error.bind(this),
+        })
+    }
+
+    render() {
+        const status = this.state.status
+        let content
+        if (!status) {
+            content = null
+        } else if (!status.enabled) {
+            content = <p>Two-factor authentication is not enabled on this server.</p>
+        } else if (this.state.enrollment) {
+            content = (
+                <form onSubmit={this.handleConfirm}>
+                    <p>Add this account to your authenticator app, then enter the current code.</p>
+                    <p><a href={this.state.enrollment.uri}>Open in authenticator app</a></p>
+                    <input className="form-control" type="text" readOnly value={this.state.enrollment.secret}
+                           onFocus={event => event.target.select()}/>
+                    <input className="form-control" type="text" ref="code" autoComplete="one-time-code"
+                           placeholder="Code" required/>
+                    <button className="btn btn-success" type="submit">Confirm</button>
+                </form>
+            )
+        } else if (status.enrolled) {
+            let disable
+            if (!status.required) {
+                disable = (
+                    <form className="form-inline" onSubmit={this.handleDisable}>
+                        <input className="form-control" type="text" ref="code" autoComplete="one-time-code"
+                               placeholder="Code or recovery code" required/>
+                        <button className="btn btn-danger" type="submit">Disable</button>
+                    </form>
+                )
+            }
+            content = (
+                <div>
+                    <p>Two-factor authentication is enabled, {status.recovery_codes} recovery codes left.</p>
+                    {disable}
+                </div>
+            )
+        } else {
+            content = (
+                <div>
+                    <p>Protect your login with codes from an authenticator app.</p>
+                    <button className="btn btn-success" onClick={this.handleEnroll}>Enable</button>
+                </div>
+            )
+        }
+
+        let recoveryCodes
+        if (this.state.recoveryCodes) {
+            recoveryCodes = (
+                <div className="alert alert-success">
+                    Store these recovery codes in a safe place, they won't be shown again:
+                    <pre>{this.state.recoveryCodes.join('\n')}</pre>
+                </div>
+            )
+        }
+
+        return (
+            <div>
+                <NavBar/>
+                <div className="container">
+                    <h4>Two-Factor Authentication</h4>
+                    {recoveryCodes}
+                    {content}
+                    <Alert error={this.state.error}/>
+                    <Link to="/keepass">Back</Link>
+                </div>
+            </div>
+        )
+    }
+}
+
+export default withNavigateHook(TwoFactor)
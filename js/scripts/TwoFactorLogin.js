@@ -0,0 +1,136 @@
+import React from 'react'
+import LoginForm from './LoginForm'
+import NavBar from './NavBar'
+import Alert from './Alert'
+import Info from './Info'
+import {isSupported} from './webauthn'
+import withNavigateHook from './nagivateHook'
+
+class TwoFactorLogin extends LoginForm {
+    constructor() {
+        super()
+        this.url = 'two_factor_login'
+        this.handleCode = this.handleCode.bind(this)
+        this.handleContinue = this.handleContinue.bind(this)
+        this.state.recoveryCodes = null
+    }
+
+    handleCode(event) {
+        event.preventDefault()
+        if (this.state.mask)
+            return
+
+        this.abortRequests()
+        this.setState({
+            error: null,
+            mask: true
+        })
+        this.serverRequest = KeePass4Web.fetch(this.url, {
+            data: this.transformRefs(this.refs),
+            success: function (data) {
+                KeePass4Web.setCSRFToken(data.csrf_token)
+                KeePass4Web.setSettings(data.settings)
+                this.serverRequest = null
+
+                // recovery codes are only shown once, after enrolling
+                if (data.recovery_codes) {
+                    this.setState({
+                        recoveryCodes: data.recovery_codes,
+                        mask: false
+                    })
+                    return
+                }
+                this.handleContinue()
+            }.bind(this),
+            error: function (error) {
+                this.serverRequest = null
+                this.setState({
+                    error: error.toString(),
+                    mask: false
+                })
+                // too many attempts or expired, start over
+                if (error.status === 429)
+                    this.authRequest = KeePass4Web.checkAuth.call(this, {info: error.toString()})
+            }.bind(this),
+        })
+    }
+
+    handleContinue() {
+        this.authRequest = KeePass4Web.checkAuth.call(this)
+    }
+
+    render() {
+        const state = this.props.location.state || {}
+        const enroll = state.two_factor && state.two_factor.enroll
+
+        let content
+        if (this.state.recoveryCodes) {
+            content = (
+                <div className="kp-login-inner">
+                    <h4>Recovery Codes</h4>
+                    <p>Store these codes in a safe place. Each code can be used once instead of a code from your
+                        authenticator app. They won't be shown again.</p>
+                    <pre>{this.state.recoveryCodes.join('\n')}</pre>
+                    <button className="btn btn-block btn-lg btn-success" onClick={this.handleContinue}>Continue</button>
+                </div>
+            )
+        } else {
+            let enrollment
+            if (enroll) {
+                enrollment = (
+                    <div>
+                        <p>Add this account to your authenticator app, then enter the current code to finish
+                            the setup.</p>
+                        <p><a href={enroll.uri}>Open in authenticator app</a></p>
+                        <input className="form-control" type="text" readOnly value={enroll.secret}
+                               onFocus={event => event.target.select()}/>
+                    </div>
+                )
+            }
+
+            let code, passkey
+            // users with only a passkey registered don't have codes
+            if (enroll || (state.two_factor && state.two_factor.totp)) {
+                code = (
+                    <div>
+                        <input className="form-control user" type="text" ref="code" autoComplete="one-time-code"
+                               placeholder={enroll ? 'Code' : 'Code or recovery code'} required="required"
+                               autoFocus="autoFocus"/>
+                        <button className="btn btn-block btn-lg btn-success" type="submit">Verify</button>
+                    </div>
+                )
+            }
+            if (state.two_factor && state.two_factor.passkey && isSupported()) {
+                passkey = (
+                    <button className="btn btn-block btn-default" type="button" onClick={this.handlePasskey}>
+                        Use a passkey
+                    </button>
+                )
+            }
+
+            content = (
+                <form className="kp-login-inner" onSubmit={this.handleCode}>
+                    <h4>{enroll ? 'Set Up Two-Factor Authentication' : 'Two-Factor Authentication'}</h4>
+                    {enrollment}
+                    {code}
+                    {passkey}
+                    <Alert error={this.state.error}/>
+                    <Info info={state.info}/>
+                </form>
+            )
+        }
+
+        return (
+            <div>
+                <NavBar/>
+                <div className="container">
+                    <div className={this.classes()}>
+                        {content}
+                    </div>
+                </div>
+            </div>
+        )
+    }
+}
+
+export default withNavigateHook(TwoFactorLogin)
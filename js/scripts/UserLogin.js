@@ -3,6 +3,7 @@ import LoginForm from './LoginForm'
 import NavBar from './NavBar'
 import Alert from './Alert'
 import Info from './Info'
+import {isSupported} from './webauthn'
 
 import withNavigateHook from './nagivateHook'
 
@@ -18,6 +19,16 @@ class UserLogin extends LoginForm {
     }
 
     render() {
+        const state = this.props.location.state || {}
+        let passkey
+        if (state.passkey && isSupported()) {
+            passkey = (
+                <button className="btn btn-block btn-default" type="button" onClick={this.handlePasskey}>
+                    Sign in with a passkey
+                </button>
+            )
+        }
+
         return (
             <div>
                 <NavBar/>
@@ -32,8 +43,9 @@ class UserLogin extends LoginForm {
                                    placeholder="Password" required="required"
                                    autoFocus={this.state.error ? 'autoFocus' : ''}/>
                             <button className="btn btn-block btn-lg btn-success" type="submit">Login</button>
+                            {passkey}
                             <Alert error={this.state.error}/>
-                            <Info info={this.props.location.state && this.props.location.state.info}/>
+                            <Info info={state.info}/>
                         </form>
                     </div>
                 </div>
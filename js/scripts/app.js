@@ -10,6 +10,10 @@ import UserLogin from './UserLogin'
 import BackendLogin from './BackendLogin'
 import DBLogin from './DBLogin'
 import CallbackUserAuth from './CallbackUserAuth'
+import Tokens from './Tokens'
+import TwoFactorLogin from './TwoFactorLogin'
+import TwoFactor from './TwoFactor'
+import Passkeys from './Passkeys'
 import HTTPError from "./HTTPError"
 
 // global namespace
@@ -39,8 +43,16 @@ KeePass4Web.checkAuth = function (state) {
             if (!authData) {
                 KeePass4Web.clearStorage()
                 this.props.navigate('/user_login', {state: state, redirect: true})
+            } else if (authData.two_factor) {
+                // password accepted, second factor pending
+                if (!state) state = {}
+                state.two_factor = authData.two_factor
+                this.props.navigate('/two_factor_login', {state: state, redirect: true})
             } else if (authData.user) {
                 let user = authData.user
+                // passwordless login offered alongside the login mask
+                if (!state) state = {}
+                state.passkey = authData.passkey
                 if (user.type === 'redirect') {
                     window.location = user.url
                     // stopping javascript execution to prevent redirect loop
@@ -89,7 +101,11 @@ KeePass4Web.fetch = function (url, conf) {
     KeePass4Web.restartTimer(true)
 
 
-    if (conf.data) {
+    // nested data, e.g. passkey responses
+    if (conf.json) {
+        conf.headers['Content-Type'] = 'application/json'
+        conf.body = JSON.stringify(conf.json)
+    } else if (conf.data) {
         let params = new URLSearchParams(Object.entries(conf.data)).toString();
         if (conf.method === "GET") {
             url = `${url}?${params}`
@@ -199,6 +215,10 @@ ReactDOM.render(
             <Route path="/backend_login" Component={BackendLogin}/>
             <Route path="/db_login" Component={DBLogin}/>
             <Route path="/callback_user_auth" Component={CallbackUserAuth}/>
+            <Route path="/two_factor_login" Component={TwoFactorLogin}/>
+            <Route path="/tokens" Component={Tokens}/>
+            <Route path="/two_factor" Component={TwoFactor}/>
+            <Route path="/passkeys" Component={Passkeys}/>
         </Routes>
     </BrowserRouter>,
     document.getElementById('app-content')
@@ -0,0 +1,53 @@
+// Conversion between the JSON options/responses of the server (base64url) and the WebAuthn browser API (ArrayBuffer)
+
+function decode(value) {
+    const base64 = value.replace(/-/g, '+').replace(/_/g, '/')
+    return Uint8Array.from(atob(base64), c => c.charCodeAt(0)).buffer
+}
+
+function encode(buffer) {
+    return btoa(String.fromCharCode(...new Uint8Array(buffer)))
+        .replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '')
+}
+
+function credentials(list) {
+    return (list || []).map(credential => ({...credential, id: decode(credential.id)}))
+}
+
+export function isSupported() {
+    return typeof window.PublicKeyCredential !== 'undefined'
+}
+
+export async function createPasskey(options, name) {
+    const credential = await navigator.credentials.create({
+        publicKey: {
+            ...options,
+            challenge: decode(options.challenge),
+            user: {...options.user, id: decode(options.user.id)},
+            excludeCredentials: credentials(options.excludeCredentials),
+        },
+    })
+
+    return {
+        name: name,
+        client_data_json: encode(credential.response.clientDataJSON),
+        attestation_object: encode(credential.response.attestationObject),
+    }
+}
+
+export async function getPasskey(options) {
+    const credential = await navigator.credentials.get({
+        publicKey: {
+            ...options,
+            challenge: decode(options.challenge),
+            allowCredentials: credentials(options.allowCredentials),
+        },
+    })
+
+    return {
+        id: encode(credential.rawId),
+        client_data_json: encode(credential.response.clientDataJSON),
+        authenticator_data: encode(credential.response.authenticatorData),
+        signature: encode(credential.response.signature),
+    }
+}
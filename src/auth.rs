@@ -1,35 +1,67 @@
 use std::future::{ready, Ready};
+use std::rc::Rc;
 
 use actix_session::SessionExt;
 use actix_web::{body::EitherBody, dev::{self, Service, ServiceRequest, ServiceResponse, Transform}, Error, HttpRequest, HttpResponse};
+use actix_web::http::{header, Method};
 use actix_web::web::Data;
 use anyhow::{bail, Result};
 use constant_time_eq::constant_time_eq;
 use futures_util::future::LocalBoxFuture;
-use log::error;
+use log::{error, info};
 use rand::distributions::{Alphanumeric, DistString};
 use rand::thread_rng;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::json;
-use zeroize::ZeroizeOnDrop;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::auth_backend;
-use crate::auth_backend::{AuthCache, LoginType, SESSION_KEY_AUTH_STATE};
+use crate::auth_backend::{AuthCache, LoginType, SESSION_KEY_AUTH_STATE, UserInfo};
+use crate::auth_backend::header::{Header, UntrustedProxyError};
 use crate::auth_backend::LoginType::Redirect;
+use crate::config::backend;
 use crate::config::config::Config;
 use crate::server::route::{API_PATH, util};
 use crate::session::AuthSession;
+use crate::token::{InvalidTokenError, TokenStore};
+use crate::two_factor::PendingLogin;
 
 pub(crate) const SESSION_KEY_USER: &str = "user";
 pub(crate) const SESSION_KEY_CSRF: &str = "csrf";
+// id of the personal access token the session was created with
+pub(crate) const SESSION_KEY_TOKEN: &str = "token";
+// set for sessions of read-only tokens, the database is never saved in them
+pub(crate) const SESSION_KEY_READ_ONLY: &str = "read_only";
+// user who still has to pass the second factor
+pub(crate) const SESSION_KEY_TWO_FACTOR: &str = "two_factor";
 
 pub(crate) const SESSION_USER_UNKNOWN: &str = "unknown";
 
 pub(crate) const CSRF_HEADER: &str = "X-CSRF-Token";
 
-pub(crate) const ROUTE_USER_LOGIN: &str = "/user_login";
 pub(crate) const ROUTE_ICON: &str = "/icon";
 
+// routes that log the user in, allowed without a session
+const LOGIN_ROUTES: [&str; 4] = ["/user_login", "/two_factor_login", "/passkey_login_start", "/passkey_login_finish"];
+
+// state changing routes still allowed for read-only tokens, they don't modify the database
+const READ_ONLY_ROUTES: [&str; 3] = ["/db_login", "/close_db", "/logout"];
+// tokens can't be used to manage tokens, the second factor or passkeys
+const TOKEN_ROUTES: [&str; 12] = [
+    "/tokens",
+    "/create_token",
+    "/revoke_token",
+    "/two_factor",
+    "/two_factor_enroll",
+    "/two_factor_confirm",
+    "/two_factor_disable",
+    "/passkeys",
+    "/passkey_register_start",
+    "/passkey_register_finish",
+    "/passkey_verify_start",
+    "/passkey_remove",
+];
+
 
 #[derive(Deserialize, ZeroizeOnDrop)]
 pub struct UserLogin {
@@ -51,6 +83,23 @@ pub struct DbLogin {
     pub key: Option<Box<[u8]>>,
 }
 
+// Database credentials as kept alongside the cached database,
+// required to seal the database again when saving changes to the backend
+#[derive(Clone, Default, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct DbCredentials {
+    pub password: Option<String>,
+    pub key: Option<Box<[u8]>>,
+}
+
+impl From<&DbLogin> for DbCredentials {
+    fn from(params: &DbLogin) -> Self {
+        Self {
+            password: params.password.clone(),
+            key: params.key.clone(),
+        }
+    }
+}
+
 fn empty_string_is_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
     where D: Deserializer<'de>,
 {
@@ -77,7 +126,7 @@ pub struct CheckAuth;
 
 impl<S, B> Transform<S, ServiceRequest> for CheckAuth
     where
-        S: Service<ServiceRequest, Response=ServiceResponse<B>, Error=Error>,
+        S: Service<ServiceRequest, Response=ServiceResponse<B>, Error=Error> + 'static,
         S::Future: 'static,
         B: 'static,
 {
@@ -88,17 +137,17 @@ impl<S, B> Transform<S, ServiceRequest> for CheckAuth
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(CheckAuthMiddleware { service }))
+        ready(Ok(CheckAuthMiddleware { service: Rc::new(service) }))
     }
 }
 
 pub struct CheckAuthMiddleware<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for CheckAuthMiddleware<S>
     where
-        S: Service<ServiceRequest, Response=ServiceResponse<B>, Error=Error>,
+        S: Service<ServiceRequest, Response=ServiceResponse<B>, Error=Error> + 'static,
         S::Future: 'static,
         B: 'static,
 {
@@ -109,14 +158,52 @@ impl<S, B> Service<ServiceRequest> for CheckAuthMiddleware<S>
     dev::forward_ready!(service);
 
     fn call(&self, request: ServiceRequest) -> Self::Future {
+        // requests that didn't pass the reverse proxy are rejected, whether they carry a token or not
+        if let Err(err) = check_peer(&request) {
+            info!("{}: {:?}", err, request.peer_addr());
+            let (request, _) = request.into_parts();
+
+            let response = HttpResponse::Forbidden().json(json!(
+               {
+                   "success": false,
+                   "message": "request not from a trusted proxy",
+               }
+            )).map_into_right_body();
+
+            return Box::pin(async { Ok(ServiceResponse::new(request, response)) });
+        }
+
+        // personal access tokens replace the session login and csrf token.
+        // Without tokens enabled, bearer tokens are left alone, e.g. the one a reverse proxy passes on
+        let tokens = request.app_data::<Data<Config>>().is_some_and(|config| config.tokens.enabled());
+        if tokens && request.path().starts_with(format!("{}/", API_PATH).as_str()) {
+            if let Some(token) = bearer_token(&request) {
+                return Box::pin(check_token(self.service.clone(), request, token));
+            }
+        }
+
         // TODO: don't return unauthorized on session backend error, but the actual error
         // Saves the user from some weird redirects
-        if !request.get_session().is_authorized() {
-            if request.path().starts_with(format!("{}/", API_PATH).as_str())
-                && request.path() != format!("{}{}", API_PATH, ROUTE_USER_LOGIN).as_str()
-            {
+        // sessions created by a token are only valid together with the token
+        let session = request.get_session();
+        check_request_user(&request);
+        if !session.is_authorized() || session.get_key::<String>(SESSION_KEY_TOKEN).is_some() {
+            let route = request.path().strip_prefix(API_PATH).unwrap_or_default();
+            if request.path().starts_with(format!("{}/", API_PATH).as_str()) && !LOGIN_ROUTES.contains(&route) {
+                // the password was accepted already, only the second factor is missing
+                let two_factor = session.get_key::<PendingLogin>(SESSION_KEY_TWO_FACTOR)
+                    .filter(|pending| !pending.is_expired())
+                    .map(|pending| json!({
+                        "enroll": pending.enrollment.clone(),
+                        "totp": pending.totp,
+                        "passkey": pending.passkey,
+                    }));
+                // offer logging in with a passkey instead of the password
+                let passkey = request.app_data::<Data<Config>>()
+                    .is_some_and(|config| config.webauthn.enabled() && config.webauthn.passwordless);
+
                 let (request, _) = request.into_parts();
-                return Box::pin(async {
+                return Box::pin(async move {
                     let resp = match get_login_type(&request).await {
                         Ok(login_type) => {
                             HttpResponse::Unauthorized().json(json!(
@@ -125,6 +212,8 @@ impl<S, B> Service<ServiceRequest> for CheckAuthMiddleware<S>
                                    "message": "unauthorized",
                                    "data": {
                                        "user": login_type,
+                                       "two_factor": two_factor,
+                                       "passkey": passkey,
                                    }
                                }
                             )).map_into_right_body()
@@ -169,6 +258,120 @@ impl<S, B> Service<ServiceRequest> for CheckAuthMiddleware<S>
     }
 }
 
+async fn check_token<S, B>(service: Rc<S>, request: ServiceRequest, token: String) -> Result<ServiceResponse<EitherBody<B>>, Error>
+    where
+        S: Service<ServiceRequest, Response=ServiceResponse<B>, Error=Error>,
+{
+    let respond = |request: ServiceRequest, response: HttpResponse| {
+        let (request, _) = request.into_parts();
+        Ok(ServiceResponse::new(request, response.map_into_right_body()))
+    };
+    let error = |message: &str| json!(
+        {
+            "success": false,
+            "message": message,
+        }
+    );
+
+    let (config, store) = match (request.app_data::<Data<Config>>(), request.app_data::<Data<TokenStore>>()) {
+        (Some(config), Some(store)) => (config.clone(), store.clone()),
+        _ => {
+            error!("token login: config or token store not found");
+            return respond(request, HttpResponse::InternalServerError().json(error("failed to verify token")));
+        }
+    };
+
+    let (token, user_info) = match store.verify(&config.tokens, &token).await {
+        Ok(v) => v,
+        Err(err) if err.downcast_ref::<InvalidTokenError>().is_some() => {
+            info!("token login: {}", err);
+            return respond(request, HttpResponse::Unauthorized().json(error("invalid token")));
+        }
+        Err(err) => {
+            error!("token login: {}", err);
+            return respond(request, HttpResponse::InternalServerError().json(error("failed to verify token")));
+        }
+    };
+
+    // tokens keep the user as of their creation, the user might have been disabled since
+    match auth_backend::new(&config).user_enabled(&user_info.id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            info!("{}: token '{}' of disabled user", user_info.id, token.id);
+            return respond(request, HttpResponse::Unauthorized().json(error("invalid token")));
+        }
+        Err(err) => {
+            error!("token login from '{}': {}", user_info.id, err);
+            return respond(request, HttpResponse::InternalServerError().json(error("failed to verify token")));
+        }
+    }
+
+    let route = request.path().strip_prefix(API_PATH).unwrap_or_default();
+    if TOKEN_ROUTES.contains(&route) {
+        info!("{}: token '{}' used for token management", user_info.id, token.id);
+        return respond(request, HttpResponse::Forbidden().json(error("not allowed with a token")));
+    }
+    if token.read_only
+        && ![Method::GET, Method::HEAD].contains(request.method())
+        && !READ_ONLY_ROUTES.contains(&route) {
+        info!("{}: read-only token '{}' used for {}", user_info.id, token.id, route);
+        return respond(request, HttpResponse::Forbidden().json(error("token is read-only")));
+    }
+
+    // every token gets its own session and database unlock, the database of another session is never reused
+    let session = request.get_session();
+    let token_id = token.id.to_string();
+    let result = match session.get_key::<String>(SESSION_KEY_TOKEN) {
+        Some(id) if id == token_id => Ok(()),
+        _ => {
+            session.clear();
+            session.insert(SESSION_KEY_TOKEN, token_id)
+        }
+    }.and_then(|_| session.insert(SESSION_KEY_READ_ONLY, token.read_only))
+        .and_then(|_| session.insert(SESSION_KEY_USER, &user_info));
+    if let Err(err) = result {
+        error!("token login from '{}': {}", user_info.id, err);
+        return respond(request, HttpResponse::InternalServerError().json(error("failed to set session")));
+    }
+
+    service.call(request).await.map(ServiceResponse::map_into_left_body)
+}
+
+// With the header backend, only the reverse proxies may connect
+fn check_peer(request: &ServiceRequest) -> Result<(), UntrustedProxyError> {
+    match request.app_data::<Data<Config>>() {
+        Some(config) if matches!(config.auth_backend, backend::AuthBackend::Header) => {
+            Header::new(config).check_peer(request.request())
+        }
+        _ => Ok(()),
+    }
+}
+
+// With the header and client certificate backends, every request carries the user.
+// Sessions of a user the request doesn't authenticate (anymore) are dropped
+fn check_request_user(request: &ServiceRequest) {
+    let Some(config) = request.app_data::<Data<Config>>() else {
+        return;
+    };
+    if !matches!(config.auth_backend, backend::AuthBackend::Header | backend::AuthBackend::ClientCert) {
+        return;
+    }
+
+    let session = request.get_session();
+    match auth_backend::new(config).request_login(request.request()) {
+        Ok(Some(user_info)) if session.get_key::<UserInfo>(SESSION_KEY_USER).is_none_or(|user| user.id == user_info.id) => {}
+        _ => session.purge(),
+    }
+}
+
+fn bearer_token(request: &ServiceRequest) -> Option<String> {
+    let value = request.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+
+    Some(token.trim().to_string())
+        .filter(|token| scheme.eq_ignore_ascii_case("bearer") && !token.is_empty())
+}
+
 async fn get_login_type(request: &HttpRequest) -> Result<LoginType> {
     let config = match request.app_data::<Data<Config>>() {
         Some(c) => c,
@@ -1,14 +1,18 @@
 use std::any::Any;
 
+use actix_web::HttpRequest;
 use anyhow::{bail, Result};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use crate::auth_backend::client_cert::ClientCert;
+use crate::auth_backend::header::Header;
 use crate::auth_backend::htpasswd::Htpasswd;
 use crate::auth_backend::ldap::Ldap;
 use crate::auth_backend::none::None;
 use crate::auth_backend::oidc::Oidc;
+use crate::auth_backend::sql::Sql;
 use crate::auth_backend::test::Test;
 use crate::config::backend;
 use crate::config::config::Config;
@@ -18,13 +22,16 @@ pub mod ldap;
 pub mod none;
 pub mod oidc;
 pub mod htpasswd;
+pub mod sql;
+pub mod header;
+pub mod client_cert;
 
 pub const SESSION_KEY_AUTH_STATE: &str = "auth_state";
 pub const ROUTE_CALLBACK_USER_AUTH: &str = "/callback_user_auth";
 
 pub type AuthCache = Box<dyn Any + Send + Sync>;
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct UserInfo {
     pub id: String,
     pub name: String,
@@ -71,6 +78,18 @@ pub trait AuthBackend: Send + Sync {
         bail!("login method not supported")
     }
 
+    // User authenticated by the request itself, e.g. by headers of a reverse proxy.
+    // None if the backend doesn't support it
+    fn request_login(&self, _request: &HttpRequest) -> Result<Option<UserInfo>> {
+        Ok(Option::None)
+    }
+
+    // Whether a user may still log in without the backend, e.g. with a personal access token or a passkey.
+    // Backends that can't tell without the user's credentials allow it
+    async fn user_enabled(&self, _user_id: &str) -> Result<bool> {
+        Ok(true)
+    }
+
     async fn callback(&self, _from_session: String, _cache: &AuthCache, _params: serde_json::Value, _host: &str) -> Result<UserInfo> {
         bail!("login method not supported")
     }
@@ -83,5 +102,8 @@ pub fn new(config: &Config) -> Box<dyn AuthBackend> {
         backend::AuthBackend::Ldap => Box::new(Ldap::new(config)),
         backend::AuthBackend::Oidc => Box::new(Oidc::new(config)),
         backend::AuthBackend::Htpasswd => Box::new(Htpasswd::new(config)),
+        backend::AuthBackend::Sql => Box::new(Sql::new(config)),
+        backend::AuthBackend::Header => Box::new(Header::new(config)),
+        backend::AuthBackend::ClientCert => Box::new(ClientCert::new(config)),
     }
 }
@@ -0,0 +1,192 @@
+use actix_web::HttpRequest;
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+use crate::auth_backend::{AuthBackend, AuthCache, LoginType, UserInfo};
+use crate::config::client_cert::{self, Field, Rule};
+use crate::config::config::Config;
+use crate::server::tls::ClientCertificate;
+
+// Users identified by the TLS client certificate, verified against the configured CA during the handshake
+pub struct ClientCert {
+    pub(crate) config: client_cert::ClientCert,
+    client_ca: bool,
+}
+
+impl ClientCert {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.client_cert.clone(),
+            client_ca: config.tls.client_ca.is_some(),
+        }
+    }
+
+    fn user_from_cert(&self, der: &[u8]) -> Result<UserInfo> {
+        let (_, cert) = X509Certificate::from_der(der)
+            .map_err(|err| anyhow!("invalid client certificate: {}", err))?;
+
+        let cn = cert.subject().iter_common_name().next().and_then(|cn| cn.as_str().ok());
+        let mut emails: Vec<&str> = vec![];
+        if let Ok(Some(san)) = cert.subject_alternative_name() {
+            for name in &san.value.general_names {
+                if let GeneralName::RFC822Name(email) = name {
+                    emails.push(email);
+                }
+            }
+        }
+        emails.extend(cert.subject().iter_email().filter_map(|email| email.as_str().ok()));
+
+        map_user(&self.config.rules, cn, &emails)
+            .ok_or(anyhow!("no rule matches client certificate '{}'", cert.subject()))
+    }
+}
+
+#[async_trait]
+impl AuthBackend for ClientCert {
+    fn validate_config(&self) -> Result<()> {
+        // certificates of unverified clients would be accepted otherwise
+        if !self.client_ca {
+            bail!("ClientCert: tls client_ca must be specified");
+        }
+        self.config.validate()
+    }
+
+    fn get_login_type(&self, _: &str, _: &AuthCache) -> Result<LoginType> {
+        Ok(LoginType::None)
+    }
+
+    fn request_login(&self, request: &HttpRequest) -> Result<Option<UserInfo>> {
+        let cert = request.conn_data::<ClientCertificate>().ok_or(anyhow!("no client certificate"))?;
+
+        self.user_from_cert(cert.0.as_ref()).map(Some)
+    }
+}
+
+// Applies the first matching rule
+fn map_user(rules: &[Rule], cn: Option<&str>, emails: &[&str]) -> Option<UserInfo> {
+    for rule in rules {
+        let values = match rule.field {
+            Field::Cn => cn.into_iter().collect(),
+            Field::Email => emails.to_vec(),
+        };
+
+        for value in values {
+            let Some(captures) = rule.pattern.captures(value) else {
+                continue;
+            };
+            let expand = |template: &str| {
+                let mut value = String::new();
+                captures.expand(template, &mut value);
+                value
+            };
+
+            let id = expand(&rule.id);
+            if id.is_empty() {
+                continue;
+            }
+            let name = match &rule.name {
+                Some(template) => expand(template),
+                None => cn.unwrap_or(&id).to_string(),
+            };
+
+            return Some(
+                UserInfo {
+                    id,
+                    name,
+                    db_location: rule.db_location.as_deref().map(expand),
+                    keyfile_location: None,
+                    additional_data: None,
+                }
+            );
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    // self-signed, CN "Alice Example", SAN email alice@example.com, subject email alice.legacy@example.org
+    const CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIB+DCCAZ2gAwIBAgIUN8KHOaUZVoocZ1sMNjCw1sVtzqQwCgYIKoZIzj0EAwIw
+QTEWMBQGA1UEAwwNQWxpY2UgRXhhbXBsZTEnMCUGCSqGSIb3DQEJARYYYWxpY2Uu
+bGVnYWN5QGV4YW1wbGUub3JnMCAXDTI2MTAxNzA1NDQ1NFoYDzIxMjYwOTIzMDU0
+NDU0WjBBMRYwFAYDVQQDDA1BbGljZSBFeGFtcGxlMScwJQYJKoZIhvcNAQkBFhhh
+bGljZS5sZWdhY3lAZXhhbXBsZS5vcmcwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNC
+AAQnKF8g3gIYnzaR45oxierisCynLWuoZstuzHe5lEBf/DWn9y7a/farrMjOJ7I3
+FMc4g4pXsuNeePBI9ID8BM7Co3EwbzAdBgNVHQ4EFgQUAjbG0UDJT411urqscJd/
+LD+ENCMwHwYDVR0jBBgwFoAUAjbG0UDJT411urqscJd/LD+ENCMwDwYDVR0TAQH/
+BAUwAwEB/zAcBgNVHREEFTATgRFhbGljZUBleGFtcGxlLmNvbTAKBggqhkjOPQQD
+AgNJADBGAiEAyLmEg1kCjk+/9cJ3e/Oziqj4yYkiFLk6VsNtj7dYHUICIQDBYDdI
+IOZGGtr3d5mQHxbaVlaTirdhC80HkhV/CRoHGA==
+-----END CERTIFICATE-----
+";
+
+    fn user(rules: &str) -> Result<UserInfo> {
+        let backend = ClientCert {
+            config: serde_yaml::from_str(rules).unwrap(),
+            client_ca: true,
+        };
+        let cert = rustls_pemfile::certs(&mut CERT.as_bytes()).next().unwrap().unwrap();
+
+        backend.user_from_cert(cert.as_ref())
+    }
+
+    #[test]
+    fn rules() {
+        // SAN emails come first
+        let user_info = user(r#"
+            rules:
+              - field: email
+                pattern: '(?P<user>[^@]+)@example\.(com|org)'
+                id: '$user'
+                db_location: '/srv/keepass/${user}.kdbx'
+        "#).unwrap();
+        assert_eq!(user_info.id, "alice");
+        assert_eq!(user_info.name, "Alice Example");
+        assert_eq!(user_info.db_location.as_deref(), Some("/srv/keepass/alice.kdbx"));
+
+        // the first matching rule applies
+        let user_info = user(r#"
+            rules:
+              - field: email
+                pattern: '(?P<user>[^@]+)@example\.org'
+                id: '$user'
+              - field: email
+                pattern: '(?P<user>[^@]+)@example\.com'
+                id: '$user'
+        "#).unwrap();
+        assert_eq!(user_info.id, "alice.legacy");
+        assert!(user_info.db_location.is_none());
+
+        // the whole value has to match
+        let user_info = user(r#"
+            rules:
+              - field: cn
+                pattern: 'Alice'
+              - field: cn
+                pattern: '(\w+) (\w+)'
+                id: '${1}.${2}'
+                name: '$2, $1'
+        "#).unwrap();
+        assert_eq!(user_info.id, "Alice.Example");
+        assert_eq!(user_info.name, "Example, Alice");
+
+        let rules = r#"
+            rules:
+              - field: email
+                pattern: '.+@example\.net'
+        "#;
+        assert!(user(rules).is_err());
+        let backend = ClientCert {
+            config: serde_yaml::from_str(rules).unwrap(),
+            client_ca: true,
+        };
+        assert!(backend.request_login(&TestRequest::default().to_http_request()).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use actix_web::HttpRequest;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use crate::auth_backend::{AuthBackend, AuthCache, LoginType, UserInfo};
+use crate::config::config::Config;
+use crate::config::header;
+
+#[derive(Debug, Clone)]
+pub struct UntrustedProxyError;
+
+impl Display for UntrustedProxyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request not from a trusted proxy")
+    }
+}
+
+impl Error for UntrustedProxyError {}
+
+// Users authenticated by a reverse proxy in front (e.g. oauth2-proxy, Authelia), passed on in headers
+pub struct Header {
+    pub(crate) config: header::Header,
+}
+
+impl Header {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.header.clone()
+        }
+    }
+
+    // The peer itself has to be a trusted proxy, headers like X-Forwarded-For could be set by anyone
+    pub fn check_peer(&self, request: &HttpRequest) -> Result<(), UntrustedProxyError> {
+        let peer = request.peer_addr().ok_or(UntrustedProxyError)?.ip().to_canonical();
+        if !self.config.trusted_proxies.iter().any(|net| net.contains(&peer)) {
+            return Err(UntrustedProxyError);
+        }
+        Ok(())
+    }
+
+    fn header(&self, request: &HttpRequest, name: &str) -> Result<Option<String>> {
+        match request.headers().get(name) {
+            Some(value) => Ok(Some(String::from_utf8(value.as_bytes().to_vec())?.trim().to_string()).filter(|v| !v.is_empty())),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthBackend for Header {
+    fn validate_config(&self) -> Result<()> {
+        self.config.validate()
+    }
+
+    fn get_login_type(&self, _: &str, _: &AuthCache) -> Result<LoginType> {
+        Ok(LoginType::None)
+    }
+
+    fn request_login(&self, request: &HttpRequest) -> Result<Option<UserInfo>> {
+        self.check_peer(request)?;
+
+        let id = self.header(request, &self.config.user_header)?
+            .ok_or(anyhow!("header '{}' missing", self.config.user_header))?;
+        let db_location = match &self.config.db_location_header {
+            Some(name) => self.header(request, name)?,
+            None => None,
+        };
+
+        Ok(Some(
+            UserInfo {
+                name: self.header(request, &self.config.name_header)?.unwrap_or_else(|| id.clone()),
+                id,
+                db_location,
+                keyfile_location: None,
+                additional_data: None,
+            }
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn request_login() {
+        let backend = Header {
+            config: header::Header {
+                db_location_header: Some("X-Remote-Db".to_string()),
+                trusted_proxies: vec!["10.0.0.0/8".parse().unwrap(), "::1/128".parse().unwrap()],
+                ..Default::default()
+            },
+        };
+        let request = |peer: &str| TestRequest::default()
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("X-Remote-User", "alice"))
+            .insert_header(("X-Remote-Name", "Alice"))
+            .insert_header(("X-Remote-Db", "/srv/keepass/alice.kdbx"));
+
+        let user_info = backend.request_login(&request("10.1.2.3:4567").to_http_request()).unwrap().unwrap();
+        assert_eq!(user_info.id, "alice");
+        assert_eq!(user_info.name, "Alice");
+        assert_eq!(user_info.db_location.as_deref(), Some("/srv/keepass/alice.kdbx"));
+
+        // ipv4 mapped addresses of dual stack sockets
+        assert!(backend.request_login(&request("[::ffff:10.1.2.3]:4567").to_http_request()).is_ok());
+        assert!(backend.request_login(&request("[::1]:4567").to_http_request()).is_ok());
+
+        let result = backend.request_login(&request("192.168.1.2:4567").to_http_request());
+        assert!(result.is_err_and(|err| err.downcast_ref::<UntrustedProxyError>().is_some()));
+        assert!(backend.request_login(&TestRequest::default().to_http_request()).is_err());
+
+        let request = TestRequest::default()
+            .peer_addr("10.1.2.3:4567".parse().unwrap())
+            .insert_header(("X-Remote-User", "bob"))
+            .to_http_request();
+        let user_info = backend.request_login(&request).unwrap().unwrap();
+        assert_eq!(user_info.name, "bob");
+        assert!(user_info.db_location.is_none());
+
+        let request = TestRequest::default().peer_addr("10.1.2.3:4567".parse().unwrap()).to_http_request();
+        assert!(backend.request_login(&request).is_err());
+    }
+}
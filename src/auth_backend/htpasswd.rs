@@ -31,6 +31,19 @@ impl Htpasswd {
 
         bail!("username or password incorrect");
     }
+
+    async fn has_user(&self, username: &str) -> Result<bool> {
+        let file = File::open(&self.config.path).await?;
+        let mut lines = io::BufReader::new(file).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.split_once(':').is_some_and(|(user, _)| user == username) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
 }
 
 #[async_trait]
@@ -56,4 +69,8 @@ impl AuthBackend for Htpasswd {
             }
         )
     }
+
+    async fn user_enabled(&self, user_id: &str) -> Result<bool> {
+        self.has_user(user_id).await
+    }
 }
@@ -18,18 +18,9 @@ impl Ldap {
             config: config.ldap.clone()
         }
     }
-}
-
-#[async_trait]
-impl AuthBackend for Ldap {
-    fn get_login_type(&self, _: &str, _: &AuthCache) -> Result<LoginType> {
-        Ok(LoginType::Mask)
-    }
-
-    async fn login(&self, username: &str, password: &str) -> Result<UserInfo> {
-        let (conn, mut ldap) = LdapConnAsync::new(self.config.uri.as_str()).await?;
 
-        drive!(conn);
+    // Searches the user with the bind account
+    async fn search_user(&self, ldap: &mut ldap3::Ldap, username: &str) -> Result<Option<SearchEntry>> {
         ldap.simple_bind(
             self.config.bind.as_str(),
             self.config.password.as_str(),
@@ -57,11 +48,23 @@ impl AuthBackend for Ldap {
         ).await?.success()?;
         ldap.unbind().await?;
 
-        if results.is_empty() {
-            bail!("no users found");
-        }
+        Ok(results.into_iter().next().map(SearchEntry::construct))
+    }
+}
 
-        let user = SearchEntry::construct(results[0].clone());
+#[async_trait]
+impl AuthBackend for Ldap {
+    fn get_login_type(&self, _: &str, _: &AuthCache) -> Result<LoginType> {
+        Ok(LoginType::Mask)
+    }
+
+    async fn login(&self, username: &str, password: &str) -> Result<UserInfo> {
+        let (conn, mut ldap) = LdapConnAsync::new(self.config.uri.as_str()).await?;
+
+        drive!(conn);
+        let Some(user) = self.search_user(&mut ldap, username).await? else {
+            bail!("no users found");
+        };
 
         ldap.simple_bind(
             user.dn.as_str(),
@@ -97,4 +100,12 @@ impl AuthBackend for Ldap {
             }
         )
     }
+
+    async fn user_enabled(&self, user_id: &str) -> Result<bool> {
+        let (conn, mut ldap) = LdapConnAsync::new(self.config.uri.as_str()).await?;
+
+        drive!(conn);
+        // users that were removed or no longer match the filter
+        Ok(self.search_user(&mut ldap, user_id).await?.is_some())
+    }
 }
\ No newline at end of file
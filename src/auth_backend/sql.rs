@@ -0,0 +1,243 @@
+use std::path::Path;
+use std::sync::LazyLock;
+
+use anyhow::{anyhow, bail, Result};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::OsRng;
+use async_trait::async_trait;
+use rusqlite::{Connection, OptionalExtension, params};
+use zeroize::Zeroizing;
+
+use crate::auth_backend::{AuthBackend, AuthCache, LoginType, UserInfo};
+use crate::config::config::Config;
+use crate::config::sql;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS users (
+        username TEXT PRIMARY KEY NOT NULL,
+        password_hash TEXT NOT NULL,
+        name TEXT,
+        db_location TEXT,
+        keyfile_location TEXT,
+        disabled INTEGER NOT NULL DEFAULT 0
+    )
+";
+
+// verified against for unknown users, so they take as long as known ones
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+    hash_password("dummy").expect("failed to hash dummy password")
+});
+
+pub struct Sql {
+    pub(crate) config: sql::Sql,
+}
+
+#[derive(Default)]
+pub struct NewUser {
+    pub username: String,
+    pub name: Option<String>,
+    pub db_location: Option<String>,
+    pub keyfile_location: Option<String>,
+}
+
+impl Sql {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.sql.clone()
+        }
+    }
+}
+
+#[async_trait]
+impl AuthBackend for Sql {
+    fn validate_config(&self) -> Result<()> {
+        self.config.validate()
+    }
+
+    fn get_login_type(&self, _: &str, _: &AuthCache) -> Result<LoginType> {
+        Ok(LoginType::Mask)
+    }
+
+    async fn login(&self, username: &str, password: &str) -> Result<UserInfo> {
+        let path = self.config.path.clone();
+        let username = username.to_owned();
+        let password = Zeroizing::new(password.to_owned());
+
+        // sqlite and password hashing are both blocking
+        tokio::task::spawn_blocking(move || {
+            match_user(&open(&path)?, &username, &password)
+        }).await?
+    }
+
+    async fn user_enabled(&self, user_id: &str) -> Result<bool> {
+        let path = self.config.path.clone();
+        let username = user_id.to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            is_enabled(&open(&path)?, &username)
+        }).await?
+    }
+}
+
+// Opens the database, creating the users table if necessary
+pub(crate) fn open(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+
+    Ok(conn)
+}
+
+fn match_user(conn: &Connection, username: &str, password: &str) -> Result<UserInfo> {
+    let row = conn.query_row(
+        "SELECT password_hash, name, db_location, keyfile_location, disabled FROM users WHERE username = ?1",
+        params![username],
+        |row| Ok((
+            row.get::<_, String>(0)?,
+            UserInfo {
+                id: username.to_owned(),
+                name: row.get::<_, Option<String>>(1)?.unwrap_or_else(|| username.to_owned()),
+                db_location: row.get(2)?,
+                keyfile_location: row.get(3)?,
+                additional_data: None,
+            },
+            row.get::<_, bool>(4)?,
+        )),
+    ).optional()?;
+
+    let Some((hash, user_info, disabled)) = row else {
+        let _ = verify_password(password, &DUMMY_HASH);
+        bail!("username or password incorrect");
+    };
+    if !verify_password(password, &hash)? {
+        bail!("username or password incorrect");
+    }
+    // only revealed with the correct password
+    if disabled {
+        bail!("user is disabled");
+    }
+
+    Ok(user_info)
+}
+
+// Users that were removed count as disabled
+fn is_enabled(conn: &Connection, username: &str) -> Result<bool> {
+    let disabled = conn.query_row(
+        "SELECT disabled FROM users WHERE username = ?1",
+        params![username],
+        |row| row.get::<_, bool>(0),
+    ).optional()?;
+
+    Ok(disabled == Some(false))
+}
+
+// Argon2id in PHC string format
+pub(crate) fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(
+        Argon2::default().hash_password(password.as_bytes(), &salt)
+            .map_err(|err| anyhow!("failed to hash password: {}", err))?
+            .to_string()
+    )
+}
+
+// Accepts argon2 (PHC string) and bcrypt ($2a$, $2b$, $2y$) hashes
+fn verify_password(password: &str, hash: &str) -> Result<bool> {
+    if hash.starts_with("$2") {
+        return Ok(bcrypt::verify(password, hash)?);
+    }
+
+    let parsed = PasswordHash::new(hash).map_err(|err| anyhow!("invalid password hash: {}", err))?;
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(_) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(err) => bail!("failed to verify password: {}", err),
+    }
+}
+
+pub(crate) fn add_user(conn: &Connection, user: &NewUser, password: &str) -> Result<()> {
+    let exists = conn.query_row("SELECT 1 FROM users WHERE username = ?1", params![user.username], |_| Ok(()))
+        .optional()?
+        .is_some();
+    if exists {
+        bail!("user '{}' already exists", user.username);
+    }
+
+    conn.execute(
+        "INSERT INTO users (username, password_hash, name, db_location, keyfile_location) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![user.username, hash_password(password)?, user.name, user.db_location, user.keyfile_location],
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn set_password(conn: &Connection, username: &str, password: &str) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE users SET password_hash = ?1 WHERE username = ?2",
+        params![hash_password(password)?, username],
+    )?;
+    if updated == 0 {
+        bail!("user '{}' not found", username);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn set_disabled(conn: &Connection, username: &str, disabled: bool) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE users SET disabled = ?1 WHERE username = ?2",
+        params![disabled, username],
+    )?;
+    if updated == 0 {
+        bail!("user '{}' not found", username);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+
+        add_user(&conn, &NewUser {
+            username: "alice".to_string(),
+            name: Some("Alice".to_string()),
+            db_location: Some("alice.kdbx".to_string()),
+            ..Default::default()
+        }, "secret").unwrap();
+        assert!(add_user(&conn, &NewUser { username: "alice".to_string(), ..Default::default() }, "other").is_err());
+        // bcrypt hashes, e.g. from htpasswd -B
+        conn.execute(
+            "INSERT INTO users (username, password_hash) VALUES ('bob', ?1)",
+            params![bcrypt::hash("hunter2", 4).unwrap()],
+        ).unwrap();
+
+        let user_info = match_user(&conn, "alice", "secret").unwrap();
+        assert_eq!(user_info.name, "Alice");
+        assert_eq!(user_info.db_location.as_deref(), Some("alice.kdbx"));
+        assert!(user_info.keyfile_location.is_none());
+        assert!(match_user(&conn, "alice", "wrong").is_err());
+        assert!(match_user(&conn, "carol", "secret").is_err());
+        assert_eq!(match_user(&conn, "bob", "hunter2").unwrap().name, "bob");
+
+        set_password(&conn, "alice", "changed").unwrap();
+        assert!(match_user(&conn, "alice", "secret").is_err());
+        assert!(match_user(&conn, "alice", "changed").is_ok());
+        assert!(set_password(&conn, "carol", "secret").is_err());
+
+        assert!(is_enabled(&conn, "alice").unwrap());
+        assert!(!is_enabled(&conn, "carol").unwrap());
+        set_disabled(&conn, "alice", true).unwrap();
+        assert!(match_user(&conn, "alice", "changed").is_err());
+        assert!(!is_enabled(&conn, "alice").unwrap());
+        set_disabled(&conn, "alice", false).unwrap();
+        assert!(match_user(&conn, "alice", "changed").is_ok());
+        assert!(is_enabled(&conn, "alice").unwrap());
+    }
+}
@@ -26,4 +26,8 @@ impl AuthBackend for Test {
 
         bail!("username or password incorrect");
     }
+
+    async fn user_enabled(&self, user_id: &str) -> Result<bool> {
+        Ok(user_id == "test")
+    }
 }
\ No newline at end of file
@@ -0,0 +1,98 @@
+use std::io::{BufRead, IsTerminal};
+
+use anyhow::{bail, Result};
+use clap::Subcommand;
+use zeroize::Zeroizing;
+
+use crate::auth_backend::sql;
+use crate::auth_backend::sql::NewUser;
+use crate::config::config::Config;
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manage users of the Sql auth backend
+    User {
+        #[command(subcommand)]
+        command: UserCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Add a user, the password is prompted for
+    Add {
+        username: String,
+        /// Display name, defaults to the username
+        #[arg(long)]
+        name: Option<String>,
+        /// Database location passed to the db backend
+        #[arg(long)]
+        db_location: Option<String>,
+        /// Key file location passed to the db backend
+        #[arg(long)]
+        keyfile_location: Option<String>,
+    },
+    /// Set a new password, the password is prompted for
+    Passwd {
+        username: String,
+    },
+    /// Disable a user, they can't log in anymore, neither with tokens nor passkeys
+    Disable {
+        username: String,
+        /// Enable the user again instead
+        #[arg(long)]
+        enable: bool,
+    },
+}
+
+pub fn run(config: &Config, command: Command) -> Result<()> {
+    match command {
+        Command::User { command } => user(config, command),
+    }
+}
+
+fn user(config: &Config, command: UserCommand) -> Result<()> {
+    config.sql.validate()?;
+    let conn = sql::open(&config.sql.path)?;
+
+    match command {
+        UserCommand::Add { username, name, db_location, keyfile_location } => {
+            let password = read_password()?;
+            sql::add_user(&conn, &NewUser { username: username.clone(), name, db_location, keyfile_location }, &password)?;
+            println!("added user '{}'", username);
+        }
+        UserCommand::Passwd { username } => {
+            let password = read_password()?;
+            sql::set_password(&conn, &username, &password)?;
+            println!("changed password of user '{}'", username);
+        }
+        UserCommand::Disable { username, enable } => {
+            sql::set_disabled(&conn, &username, !enable)?;
+            println!("{} user '{}'", if enable { "enabled" } else { "disabled" }, username);
+        }
+    }
+
+    Ok(())
+}
+
+// Prompts twice on a terminal, otherwise reads the first line of stdin (e.g. for scripts)
+fn read_password() -> Result<Zeroizing<String>> {
+    let password = if std::io::stdin().is_terminal() {
+        let password = Zeroizing::new(rpassword::prompt_password("Password: ")?);
+        let repeated = Zeroizing::new(rpassword::prompt_password("Repeat password: ")?);
+        if password != repeated {
+            bail!("passwords don't match");
+        }
+        password
+    } else {
+        let mut line = Zeroizing::new(String::new());
+        std::io::stdin().lock().read_line(&mut line)?;
+        Zeroizing::new(line.trim_end_matches(['\r', '\n']).to_string())
+    };
+
+    if password.is_empty() {
+        bail!("password must not be empty");
+    }
+
+    Ok(password)
+}
@@ -8,3 +8,14 @@ pub mod oidc;
 pub mod cookie;
 pub mod http;
 pub mod htpasswd;
+pub mod header;
+pub mod client_cert;
+pub mod generator;
+pub mod health;
+pub mod breach;
+pub mod url_match;
+pub mod tokens;
+pub mod sql;
+pub mod two_factor;
+pub mod webauthn;
+pub mod tls;
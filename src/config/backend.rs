@@ -11,6 +11,12 @@ pub enum AuthBackend {
     Oidc,
     #[serde(alias = "htpasswd")]
     Htpasswd,
+    #[serde(alias = "SQL", alias = "sql")]
+    Sql,
+    #[serde(alias = "header")]
+    Header,
+    #[serde(alias = "client_cert")]
+    ClientCert,
 }
 
 #[derive(Clone, Default, Deserialize)]
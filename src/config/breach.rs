@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Breach {
+    // local mirror of the Pwned Passwords range files, one file per 5 hex digit hash prefix
+    pub directory: Option<PathBuf>,
+    // alternatively a file of SHA-1 hashes sorted ascending, each 20 bytes followed by a 4 byte big endian count
+    pub file: Option<PathBuf>,
+}
+
+impl Breach {
+    pub(crate) fn validate(&self) -> Result<()> {
+        match (&self.directory, &self.file) {
+            (Some(_), Some(_)) => bail!("Breach: only one of directory and file can be specified"),
+            (Some(directory), None) if !directory.is_dir() => bail!("Breach: directory '{}' not found", directory.display()),
+            (None, Some(file)) if !file.is_file() => bail!("Breach: file '{}' not found", file.display()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.directory.is_some() || self.file.is_some()
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::{bail, Result};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ClientCert {
+    // tried in order, the first rule matching the certificate determines the user
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    // subject common name
+    Cn,
+    // subject alternative name or subject email address, each one is tried
+    Email,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Rule {
+    pub field: Field,
+    // has to match the whole value
+    #[serde(deserialize_with = "anchored_regex")]
+    pub pattern: Regex,
+    // templates with references to capture groups of the pattern ($0, $1, $name)
+    #[serde(default = "default_id")]
+    pub id: String,
+    // the common name if not set, the user id without one
+    pub name: Option<String>,
+    // the db backend config is used if not set
+    pub db_location: Option<String>,
+}
+
+impl ClientCert {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.rules.is_empty() {
+            bail!("ClientCert: at least one rule must be specified");
+        }
+        Ok(())
+    }
+}
+
+fn default_id() -> String {
+    "$0".to_string()
+}
+
+fn anchored_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+    where D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&format!("^(?:{})$", pattern)).map_err(serde::de::Error::custom)
+}
@@ -3,20 +3,31 @@ use std::path::PathBuf;
 use std::time::Duration;
 
 use actix_web::cookie;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::Deserialize;
 use serde_yaml::from_reader;
 
 use crate::{auth_backend, db_backend};
 use crate::config::backend::{AuthBackend, DbBackend};
+use crate::config::breach::Breach;
+use crate::config::client_cert::ClientCert;
 use crate::config::cookie::SameSiteDef;
 use crate::config::filesystem::Filesystem;
+use crate::config::generator::Generator;
+use crate::config::header::Header;
+use crate::config::health::Health;
 use crate::config::htpasswd::Htpasswd;
 use crate::config::http::Http;
 use crate::config::key::Key;
 use crate::config::ldap::Ldap;
 use crate::config::oidc::Oidc;
 use crate::config::search::Search;
+use crate::config::sql::Sql;
+use crate::config::tls::Tls;
+use crate::config::tokens::Tokens;
+use crate::config::two_factor::TwoFactor;
+use crate::config::url_match::UrlMatch;
+use crate::config::webauthn::Webauthn;
 
 #[derive(Clone, Deserialize)]
 #[serde(default)]
@@ -24,6 +35,8 @@ pub struct Config {
     #[serde(alias = "server")]
     pub listen: String,
     pub port: u16,
+    #[serde(alias = "TLS")]
+    pub tls: Tls,
     #[serde(with = "humantime_serde")]
     pub db_session_timeout: Duration,
     #[serde(with = "humantime_serde")]
@@ -33,15 +46,29 @@ pub struct Config {
     pub session_secret_key: Key,
     #[serde(with = "humantime_serde")]
     pub session_lifetime: Duration,
+    pub tokens: Tokens,
+    pub two_factor: TwoFactor,
+    #[serde(alias = "WebAuthn")]
+    pub webauthn: Webauthn,
     #[serde(with = "SameSiteDef")]
     pub cookie_samesite: cookie::SameSite,
     pub search: Search,
+    pub url_match: UrlMatch,
+    pub generator: Generator,
+    pub health: Health,
+    pub breach: Breach,
     #[serde(alias = "LDAP", alias = "Ldap")]
     pub ldap: Ldap,
     #[serde(alias = "OIDC", alias = "Oidc")]
     pub oidc: Oidc,
     #[serde(alias = "Htpasswd")]
     pub htpasswd: Htpasswd,
+    #[serde(alias = "SQL", alias = "Sql")]
+    pub sql: Sql,
+    #[serde(alias = "Header")]
+    pub header: Header,
+    #[serde(alias = "ClientCert")]
+    pub client_cert: ClientCert,
     #[serde(alias = "Filesystem")]
     pub filesystem: Filesystem,
     #[serde(alias = "HTTP", alias = "Http")]
@@ -53,6 +80,7 @@ impl Default for Config {
         Config {
             listen: "127.0.0.1".to_string(),
             port: 8080,
+            tls: Default::default(),
             // 10 minutes
             db_session_timeout: Duration::from_secs(10 * 60),
             // 1 hour, 5 minutes
@@ -62,11 +90,21 @@ impl Default for Config {
             session_secret_key: Key(cookie::Key::generate()),
             // 1 hour
             session_lifetime: Duration::from_secs(60 * 60),
+            tokens: Default::default(),
+            two_factor: Default::default(),
+            webauthn: Default::default(),
             cookie_samesite: cookie::SameSite::Strict,
             search: Default::default(),
+            url_match: Default::default(),
+            generator: Default::default(),
+            health: Default::default(),
+            breach: Default::default(),
             ldap: Default::default(),
             oidc: Default::default(),
             htpasswd: Default::default(),
+            sql: Default::default(),
+            header: Default::default(),
+            client_cert: Default::default(),
             filesystem: Default::default(),
             http: Default::default(),
         }
@@ -78,8 +116,19 @@ impl Config {
         let file = File::open(filename)?;
         let conf: Config = from_reader(file)?;
 
+        conf.tls.validate()?;
         auth_backend::new(&conf).validate_config()?;
         db_backend::new(&conf).validate_config()?;
+        conf.generator.validate()?;
+        conf.breach.validate()?;
+        conf.tokens.validate()?;
+        conf.two_factor.validate()?;
+        conf.webauthn.validate()?;
+        // a passkey alone replaces the password, only backends with a login mask know their users
+        if conf.webauthn.enabled() && conf.webauthn.passwordless
+            && !matches!(conf.auth_backend, AuthBackend::Ldap | AuthBackend::Htpasswd | AuthBackend::Sql) {
+            bail!("Webauthn: passwordless requires the LDAP, htpasswd or Sql auth backend");
+        }
 
         Ok(conf)
     }
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::generator::{passphrase, password};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CharSet {
+    Upper,
+    Lower,
+    Digits,
+    Minus,
+    Underline,
+    Space,
+    Special,
+    Brackets,
+    Extended,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Profile {
+    pub length: usize,
+    pub charsets: Vec<CharSet>,
+    // minimum number of characters from a character set
+    pub minimum: HashMap<CharSet, usize>,
+    // O0Il1|
+    pub exclude_lookalike: bool,
+    pub exclude: String,
+    // KeePass password pattern, e.g. 'u{4}d{2}', takes precedence over length and character sets
+    pub pattern: Option<String>,
+    // randomly permute the characters generated from a pattern
+    pub permute: bool,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            length: 20,
+            charsets: vec![
+                CharSet::Upper,
+                CharSet::Lower,
+                CharSet::Digits,
+            ],
+            minimum: HashMap::new(),
+            exclude_lookalike: false,
+            exclude: String::new(),
+            pattern: None,
+            permute: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capitalization {
+    None,
+    // first letter of every word
+    First,
+    All,
+    // first letter of random words
+    Random,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Passphrase {
+    pub words: usize,
+    pub separator: String,
+    pub capitalization: Capitalization,
+    // number of random digits appended to random words
+    pub digits: usize,
+    // file name of a configured wordlist without extension, the built-in EFF large wordlist if unset
+    pub wordlist: Option<String>,
+}
+
+impl Default for Passphrase {
+    fn default() -> Self {
+        Passphrase {
+            words: 6,
+            separator: " ".to_string(),
+            capitalization: Capitalization::None,
+            digits: 0,
+            wordlist: None,
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Generator {
+    // used if no profile is requested
+    pub default: Profile,
+    pub profiles: HashMap<String, Profile>,
+    pub passphrase: Passphrase,
+    // additional wordlists with one word per line, dice numbers in front of words are ignored
+    pub wordlists: Vec<PathBuf>,
+}
+
+impl Generator {
+    pub(crate) fn validate(&self) -> Result<()> {
+        password::generate(&self.default).map_err(|err| anyhow!("Generator: default profile: {}", err))?;
+        for (name, profile) in &self.profiles {
+            password::generate(profile).map_err(|err| anyhow!("Generator: profile '{}': {}", name, err))?;
+        }
+
+        for path in &self.wordlists {
+            let content = std::fs::read_to_string(path).map_err(|err| anyhow!("Generator: wordlist '{}': {}", path.display(), err))?;
+            passphrase::Wordlist::parse(&content).map_err(|err| anyhow!("Generator: wordlist '{}': {}", path.display(), err))?;
+        }
+        passphrase::check(&self.passphrase, self).map_err(|err| anyhow!("Generator: passphrase: {}", err))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use anyhow::{bail, Result};
+use ipnet::IpNet;
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Header {
+    // header with the user id, required
+    pub user_header: String,
+    // header with the display name, the user id is used if missing
+    pub name_header: String,
+    // header with the database location, the db backend config is used if not set
+    pub db_location_header: Option<String>,
+    // networks of the reverse proxies, requests from other peers are rejected.
+    // Has to match the address the proxy connects from, X-Forwarded-For isn't considered
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            user_header: "X-Remote-User".to_string(),
+            name_header: "X-Remote-Name".to_string(),
+            db_location_header: None,
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+impl Header {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.user_header.is_empty() {
+            bail!("Header: user_header must be specified");
+        }
+        // anyone could set the headers otherwise
+        if self.trusted_proxies.is_empty() {
+            bail!("Header: trusted_proxies must be specified");
+        }
+        Ok(())
+    }
+}
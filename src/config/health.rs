@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Health {
+    // passwords unchanged for longer are reported as old
+    #[serde(with = "humantime_serde")]
+    pub max_age: Duration,
+    // passwords with a lower strength score (0-4) are reported as weak
+    pub min_score: u8,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health {
+            // 1 year
+            max_age: Duration::from_secs(365 * 24 * 60 * 60),
+            min_score: 3,
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use serde::Deserialize;
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Field {
     Title,
@@ -16,6 +16,8 @@ pub struct Search {
     pub fields: Vec<Field>,
     pub extra_fields: bool,
     pub allow_regex: bool,
+    // tolerate typos and missing characters, results are ranked by how well they match
+    pub fuzzy: bool,
 }
 
 impl Default for Search {
@@ -30,6 +32,7 @@ impl Default for Search {
             ],
             extra_fields: true,
             allow_regex: false,
+            fuzzy: false,
         }
     }
 }
@@ -0,0 +1,20 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Sql {
+    // SQLite database file with the users table, managed with `keepass4web-rs user`
+    pub path: PathBuf,
+}
+
+impl Sql {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.path.as_os_str().is_empty() {
+            bail!("Sql: path must be specified");
+        }
+        Ok(())
+    }
+}
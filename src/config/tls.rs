@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Tls {
+    // certificate chain and private key in PEM format, TLS is disabled if not set
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    // CA bundle in PEM format, client certificates are requested and verified against it if set
+    pub client_ca: Option<PathBuf>,
+    // accept clients without a certificate, e.g. for other auth backends or personal access tokens.
+    // The handshake fails for those otherwise. Invalid certificates are always rejected
+    pub client_cert_optional: bool,
+}
+
+impl Tls {
+    pub(crate) fn validate(&self) -> Result<()> {
+        match (&self.cert, &self.key) {
+            (Some(_), Some(_)) | (None, None) => {}
+            _ => bail!("Tls: cert and key must be specified together"),
+        }
+        if self.client_ca.is_some() && !self.enabled() {
+            bail!("Tls: client_ca requires cert and key");
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.cert.is_some() && self.key.is_some()
+    }
+}
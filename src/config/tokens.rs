@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Tokens {
+    // file the hashed personal access tokens are kept in, tokens are disabled if not set
+    pub file: Option<PathBuf>,
+}
+
+impl Tokens {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(file) = &self.file {
+            match file.parent() {
+                Some(dir) if dir.as_os_str().is_empty() || dir.is_dir() => {}
+                _ => bail!("Tokens: directory of '{}' not found", file.display()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.file.is_some()
+    }
+}
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct TwoFactor {
+    // file the TOTP secrets and hashed recovery codes are kept in, the second factor is disabled if not set
+    pub file: Option<PathBuf>,
+    // users without an enrolled second factor have to enroll on their next login
+    pub required: bool,
+    // shown in authenticator apps
+    pub issuer: String,
+}
+
+impl Default for TwoFactor {
+    fn default() -> Self {
+        TwoFactor {
+            file: None,
+            required: false,
+            issuer: "KeePass4Web".to_string(),
+        }
+    }
+}
+
+impl TwoFactor {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(file) = &self.file {
+            match file.parent() {
+                Some(dir) if dir.as_os_str().is_empty() || dir.is_dir() => {}
+                _ => bail!("TwoFactor: directory of '{}' not found", file.display()),
+            }
+        }
+        if self.required && self.file.is_none() {
+            bail!("TwoFactor: file must be specified if required is set");
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.file.is_some()
+    }
+}
@@ -0,0 +1,21 @@
+use serde::Deserialize;
+
+// How closely the host of an entry URL has to match the requested one.
+// Each level includes the stricter ones
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Strictness {
+    // same host only
+    Exact,
+    // the requested host may be a subdomain of the entry host
+    Subdomain,
+    // same registrable domain, e.g. login.example.com and www.example.com
+    #[default]
+    Domain,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct UrlMatch {
+    pub strictness: Strictness,
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use url::Url;
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Webauthn {
+    // file the registered passkeys are kept in, passkeys are disabled if not set
+    pub file: Option<PathBuf>,
+    // relying party id, the domain the web interface is served on (e.g. keepass.example.com).
+    // Not derived from the request, the host header can't be trusted
+    pub rp_id: String,
+    // shown by browsers and authenticators
+    pub rp_name: String,
+    // origins the web interface is served from, https://<rp_id> if empty
+    pub origins: Vec<Url>,
+    // allow logging in with a passkey alone, without the password of the auth backend.
+    // Passkeys are a second factor otherwise
+    pub passwordless: bool,
+}
+
+impl Default for Webauthn {
+    fn default() -> Self {
+        Webauthn {
+            file: None,
+            rp_id: "".to_string(),
+            rp_name: "KeePass4Web".to_string(),
+            origins: vec![],
+            passwordless: false,
+        }
+    }
+}
+
+impl Webauthn {
+    pub(crate) fn validate(&self) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        match file.parent() {
+            Some(dir) if dir.as_os_str().is_empty() || dir.is_dir() => {}
+            _ => bail!("Webauthn: directory of '{}' not found", file.display()),
+        }
+        if self.rp_id.is_empty() {
+            bail!("Webauthn: rp_id must be specified");
+        }
+        for origin in &self.origins {
+            // origins have to be the rp id itself or a subdomain of it
+            match origin.host_str() {
+                Some(host) if host == self.rp_id || host.ends_with(&format!(".{}", self.rp_id)) => {}
+                _ => bail!("Webauthn: origin '{}' doesn't match rp_id '{}'", origin, self.rp_id),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub fn origins(&self) -> Result<Vec<Url>> {
+        match self.origins.is_empty() {
+            true => Ok(vec![Url::parse(&format!("https://{}", self.rp_id))?]),
+            false => Ok(self.origins.clone()),
+        }
+    }
+}
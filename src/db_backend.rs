@@ -1,9 +1,13 @@
 use std::any::Any;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::pin::Pin;
+use std::time::SystemTime;
 
 use actix_web::web::Form;
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::sync::oneshot::Receiver;
 
@@ -19,14 +23,42 @@ pub mod filesystem;
 pub mod test;
 pub mod http;
 
+// Identifies the state of the database file as seen on read, to detect concurrent modifications on write
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DbVersion {
+    File {
+        modified: SystemTime,
+        size: u64,
+    },
+    ETag(String),
+    LastModified(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ConflictError;
+
+impl Display for ConflictError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "database was modified concurrently")
+    }
+}
+
+impl Error for ConflictError {}
+
+pub type DbReader<'a> = Pin<Box<dyn AsyncRead + 'a>>;
+// The receiver, if any, returns the version of the written database once the write completed
+pub type DbWriter<'a> = (Pin<Box<dyn AsyncWrite + 'a>>, Option<Receiver<Result<Option<DbVersion>>>>);
+
 #[async_trait]
 pub trait DbBackend {
     fn init(&self, _: Form<BackendLogin>) -> Result<()> { Ok(()) }
     fn authenticated(&self) -> bool;
-    async fn get_db_read(&self, user_info: &UserInfo) -> Result<Pin<Box<dyn AsyncRead + '_>>>;
+    // return the version of the database alongside the reader, None if the backend can't tell
+    async fn get_db_read(&self, user_info: &UserInfo) -> Result<(DbReader<'_>, Option<DbVersion>)>;
     // return None if the db backend doesn't return key files or is not configured to do so
     async fn get_key_read(&self, user_info: &UserInfo) -> Option<Result<Pin<Box<dyn AsyncRead + '_>>>>;
-    async fn get_db_write(&mut self, user_info: &UserInfo) -> Result<(Pin<Box<dyn AsyncWrite + '_>>, Option<Receiver<Result<()>>>)>;
+    // fail with ConflictError if the database doesn't match the given version anymore
+    async fn get_db_write(&mut self, user_info: &UserInfo, version: Option<&DbVersion>) -> Result<DbWriter<'_>>;
     fn as_any(&mut self) -> &mut dyn Any;
     fn validate_config(&self) -> Result<()> { Ok(()) }
 }
@@ -1,17 +1,21 @@
 use std::any::Any;
+use std::fs::Metadata;
 use std::path::Path;
 use std::pin::Pin;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use tokio::fs::File;
-use tokio::io::{AsyncRead, AsyncWrite};
-use tokio::sync::oneshot::Receiver;
+use tokio::io::{AsyncRead, DuplexStream};
+use tokio::sync::oneshot;
 
+use crate::auth::gen_token;
 use crate::auth_backend::UserInfo;
 use crate::config::config::Config;
 use crate::config::filesystem;
-use crate::db_backend::DbBackend;
+use crate::db_backend::{ConflictError, DbBackend, DbReader, DbVersion, DbWriter};
+
+const TMP_SUFFIX_LENGTH: usize = 8;
 
 pub struct Filesystem {
     pub config: filesystem::Filesystem,
@@ -23,17 +27,17 @@ impl DbBackend for Filesystem {
         true
     }
 
-    async fn get_db_read(&self, user_info: &UserInfo) -> Result<Pin<Box<dyn AsyncRead + '_>>> {
-        let mut path = self.config.db_location.as_path();
-
-        if let Some(db_location) = &user_info.db_location {
-            path = Path::new(db_location);
-        }
+    async fn get_db_read(&self, user_info: &UserInfo) -> Result<(DbReader<'_>, Option<DbVersion>)> {
+        let file = File::open(
+            self.get_db_path(user_info)
+        ).await?;
+        let version = Self::version(&file.metadata().await?)?;
 
         Ok(
-            Box::pin(File::open(
-                path
-            ).await?)
+            (
+                Box::pin(file),
+                Some(version),
+            )
         )
     }
 
@@ -58,19 +62,32 @@ impl DbBackend for Filesystem {
         None
     }
 
-    async fn get_db_write(&mut self, user_info: &UserInfo) -> Result<(Pin<Box<dyn AsyncWrite + '_>>, Option<Receiver<Result<()>>>)> {
-        let mut path = self.config.db_location.as_path();
+    async fn get_db_write(&mut self, user_info: &UserInfo, version: Option<&DbVersion>) -> Result<DbWriter<'_>> {
+        let path = self.get_db_path(user_info).to_path_buf();
 
-        if let Some(db_location) = &user_info.db_location {
-            path = Path::new(db_location);
-        }
+        // write to a temporary file next to the database first, so the database is replaced atomically
+        let file_name = path.file_name().ok_or(anyhow!("invalid database path"))?.to_string_lossy();
+        let tmp_path = path.with_file_name(format!(".{}.{}", file_name, gen_token(TMP_SUFFIX_LENGTH)));
+        let tmp_file = File::create(&tmp_path).await?;
+
+        let (asyncwriter, asyncreader) = tokio::io::duplex(256 * 1024);
+        let version = version.cloned();
+
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let result = Self::replace_db(asyncreader, tmp_file, &tmp_path, &path, version).await;
+            if result.is_err() {
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+            }
+            tx.send(result) // ignore failed send
+        });
 
         Ok(
             (
-                Box::pin(File::open(
-                    path
-                ).await?),
-                None
+                Box::pin(
+                    asyncwriter
+                ),
+                Some(rx)
             )
         )
     }
@@ -90,4 +107,81 @@ impl Filesystem {
             config: config.filesystem.clone()
         }
     }
+
+    fn get_db_path<'a>(&'a self, user_info: &'a UserInfo) -> &'a Path {
+        match &user_info.db_location {
+            Some(db_location) => Path::new(db_location),
+            None => self.config.db_location.as_path(),
+        }
+    }
+
+    fn version(metadata: &Metadata) -> Result<DbVersion> {
+        Ok(
+            DbVersion::File {
+                modified: metadata.modified()?,
+                size: metadata.len(),
+            }
+        )
+    }
+
+    async fn replace_db(mut data: DuplexStream, mut tmp_file: File, tmp_path: &Path, path: &Path, version: Option<DbVersion>) -> Result<Option<DbVersion>> {
+        tokio::io::copy(&mut data, &mut tmp_file).await?;
+        tmp_file.sync_all().await?;
+        drop(tmp_file);
+
+        // compare as late as possible, right before the rename
+        let metadata = tokio::fs::metadata(path).await?;
+        if let Some(expected) = version {
+            if Self::version(&metadata)? != expected {
+                return Err(ConflictError.into());
+            }
+        }
+
+        tokio::fs::set_permissions(tmp_path, metadata.permissions()).await?;
+        tokio::fs::rename(tmp_path, path).await?;
+
+        Ok(
+            Some(Self::version(&tokio::fs::metadata(path).await?)?)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    async fn write_fs(fs: &mut Filesystem, version: Option<&DbVersion>, data: &str) -> Result<Option<DbVersion>> {
+        let (mut writer, rx) = fs.get_db_write(&UserInfo::default(), version).await?;
+
+        writer.write_all(data.as_bytes()).await?;
+        writer.shutdown().await?;
+        drop(writer);
+
+        rx.unwrap().await?
+    }
+
+    #[tokio::test]
+    async fn write_conflict() {
+        let path = std::env::temp_dir().join(format!("keepass4web-test-{}.kdbx", gen_token(8)));
+        tokio::fs::write(&path, "initial").await.unwrap();
+
+        let mut config = Config::default();
+        config.filesystem.db_location = path.clone();
+        let mut fs = Filesystem::new(&config);
+
+        let (_, version) = fs.get_db_read(&UserInfo::default()).await.unwrap();
+        let version = write_fs(&mut fs, version.as_ref(), "first write").await.unwrap();
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "first write");
+
+        // someone else modified the file in the meantime
+        tokio::fs::write(&path, "concurrent write").await.unwrap();
+
+        let err = write_fs(&mut fs, version.as_ref(), "second write").await.unwrap_err();
+        assert!(err.downcast_ref::<ConflictError>().is_some());
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "concurrent write");
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
 }
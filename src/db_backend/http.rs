@@ -6,18 +6,19 @@ use anyhow::{bail, Result};
 use anyhow::Error;
 use async_trait::async_trait;
 use futures_util::TryStreamExt;
+use log::warn;
 use reqwest;
-use reqwest::{Body, Client, Method, RequestBuilder, Response};
-use tokio::io::{AsyncRead, AsyncWrite};
+use reqwest::{Body, Client, Method, RequestBuilder, Response, StatusCode};
+use reqwest::header::{ETAG, HeaderMap, IF_MATCH, IF_UNMODIFIED_SINCE, LAST_MODIFIED};
+use tokio::io::AsyncRead;
 use tokio::sync::oneshot;
-use tokio::sync::oneshot::Receiver;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use url::Url;
 
 use crate::auth_backend::UserInfo;
 use crate::config::config::Config;
 use crate::config::http;
-use crate::db_backend::DbBackend;
+use crate::db_backend::{ConflictError, DbBackend, DbReader, DbVersion, DbWriter};
 
 pub struct Http {
     pub config: http::Http,
@@ -29,12 +30,16 @@ impl DbBackend for Http {
         true
     }
 
-    async fn get_db_read(&self, user_info: &UserInfo) -> Result<Pin<Box<dyn AsyncRead + '_>>> {
+    async fn get_db_read(&self, user_info: &UserInfo) -> Result<(DbReader<'_>, Option<DbVersion>)> {
         let url = self.get_db_url(user_info)?;
 
         let response = self.get_request(Method::GET, url)?.send().await?;
+        let version = Self::version(response.headers());
         Ok(
-            Self::get_boxed_response(response)
+            (
+                Self::get_boxed_response(response),
+                version,
+            )
         )
     }
 
@@ -70,19 +75,31 @@ impl DbBackend for Http {
         }
     }
 
-    async fn get_db_write(&mut self, user_info: &UserInfo) -> Result<(Pin<Box<dyn AsyncWrite + '_>>, Option<Receiver<Result<()>>>)> {
+    async fn get_db_write(&mut self, user_info: &UserInfo, version: Option<&DbVersion>) -> Result<DbWriter<'_>> {
         let url = self.get_db_url(user_info)?;
 
         let (asyncwriter, asyncreader) = tokio::io::duplex(256 * 1024);
         let streamreader = tokio_util::io::ReaderStream::new(asyncreader);
 
-        let req = self.get_request(Method::PUT, url)?
+        let mut req = self.get_request(Method::PUT, url.clone())?
             .body(Body::wrap_stream(streamreader));
+        let head = self.get_request(Method::HEAD, url)?;
+
+        // let the server reject the write if the database changed since we read it
+        match version {
+            Some(DbVersion::ETag(etag)) => req = req.header(IF_MATCH, etag),
+            Some(DbVersion::LastModified(date)) => req = req.header(IF_UNMODIFIED_SINCE, date),
+            _ => {}
+        }
 
         let (tx, rx) = oneshot::channel();
         tokio::spawn(async move {
             tx.send(match req.send().await {
-                Ok(_) => Ok(()),
+                Ok(response) if response.status() == StatusCode::PRECONDITION_FAILED => Err(ConflictError.into()),
+                Ok(response) => match response.error_for_status().map(|response| Self::version(response.headers())) {
+                    Ok(None) => Self::current_version(head).await,
+                    result => result.map_err(Error::new),
+                },
                 Err(err) => Err(err).map_err(Error::new),
             }) // ignore failed send
         });
@@ -121,6 +138,30 @@ impl Http {
         )
     }
 
+    fn version(headers: &HeaderMap) -> Option<DbVersion> {
+        if let Some(etag) = headers.get(ETAG).and_then(|v| v.to_str().ok()) {
+            return Some(DbVersion::ETag(etag.to_string()));
+        }
+        if let Some(date) = headers.get(LAST_MODIFIED).and_then(|v| v.to_str().ok()) {
+            return Some(DbVersion::LastModified(date.to_string()));
+        }
+
+        None
+    }
+
+    // Servers don't have to send validators with the PUT response, without asking
+    // the next save would be unconditional and overwrite concurrent changes
+    async fn current_version(head: RequestBuilder) -> Result<Option<DbVersion>> {
+        match head.send().await.and_then(|response| response.error_for_status()) {
+            Ok(response) => Ok(Self::version(response.headers())),
+            // the database was written, don't report the save as failed
+            Err(err) => {
+                warn!("failed to retrieve version of written database: {}", err);
+                Ok(None)
+            }
+        }
+    }
+
     fn get_db_url(&self, user_info: &UserInfo) -> Result<Url> {
         let url;
         if let Some(u) = &user_info.db_location {
@@ -159,22 +200,25 @@ mod tests {
 
     use super::*;
 
-    async fn write_http(url: Url) -> Result<()> {
+    async fn write_http(url: Url) -> Result<Option<DbVersion>> {
+        write_http_version(url, None).await
+    }
+
+    async fn write_http_version(url: Url, version: Option<&DbVersion>) -> Result<Option<DbVersion>> {
         let mut config = Config::default();
         config.http.database_url = Some(url);
         let mut http = Http::new(&config);
 
-        let (mut writer, rx) = http.get_db_write(&UserInfo::default()).await?;
+        let (mut writer, rx) = http.get_db_write(&UserInfo::default(), version).await?;
 
         let data = "some random data";
         writer.write_all(data.as_bytes()).await?;
         writer.shutdown().await?;
 
-        if let Some(rx) = rx {
-            rx.await??
+        match rx {
+            Some(rx) => rx.await?,
+            None => Ok(None),
         }
-
-        Ok(())
     }
 
     #[tokio::test]
@@ -190,7 +234,7 @@ mod tests {
         let mut config = Config::default();
         config.http.database_url = Some(Url::from_str(&server.url()).unwrap());
         let http = Http::new(&config);
-        let mut reader = http.get_db_read(&UserInfo::default()).await.unwrap();
+        let (mut reader, _) = http.get_db_read(&UserInfo::default()).await.unwrap();
 
         let mut str = String::new();
         reader.read_to_string(&mut str).await.unwrap();
@@ -219,5 +263,58 @@ mod tests {
 
         assert!(res.is_err());
     }
-}
 
+    #[tokio::test]
+    async fn read_version() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("GET", "/")
+            .with_body("some random data")
+            .with_header("ETag", "\"abc\"")
+            .with_status(200)
+            .create_async().await;
+
+        let mut config = Config::default();
+        config.http.database_url = Some(Url::from_str(&server.url()).unwrap());
+        let http = Http::new(&config);
+        let (_, version) = http.get_db_read(&UserInfo::default()).await.unwrap();
+
+        mock.assert_async().await;
+
+        assert_eq!(version, Some(DbVersion::ETag("\"abc\"".to_string())));
+    }
+
+    #[tokio::test]
+    async fn write_version() {
+        let mut server = mockito::Server::new_async().await;
+        let put = server.mock("PUT", "/")
+            .with_status(204)
+            .create_async().await;
+        let head = server.mock("HEAD", "/")
+            .with_header("ETag", "\"def\"")
+            .with_status(200)
+            .create_async().await;
+
+        let version = write_http(Url::from_str(&server.url()).unwrap()).await.unwrap();
+
+        put.assert_async().await;
+        head.assert_async().await;
+
+        assert_eq!(version, Some(DbVersion::ETag("\"def\"".to_string())));
+    }
+
+    #[tokio::test]
+    async fn write_conflict() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("PUT", "/")
+            .match_header("If-Match", "\"abc\"")
+            .with_status(412)
+            .create_async().await;
+
+        let version = DbVersion::ETag("\"abc\"".to_string());
+        let res = write_http_version(Url::from_str(&server.url()).unwrap(), Some(&version)).await;
+
+        mock.assert_async().await;
+
+        assert!(res.unwrap_err().downcast_ref::<ConflictError>().is_some());
+    }
+}
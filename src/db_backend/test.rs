@@ -3,11 +3,10 @@ use std::pin::Pin;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use tokio::io::{AsyncRead, AsyncWrite};
-use tokio::sync::oneshot::Receiver;
+use tokio::io::AsyncRead;
 
 use crate::auth_backend::UserInfo;
-use crate::db_backend::DbBackend;
+use crate::db_backend::{DbBackend, DbReader, DbVersion, DbWriter};
 
 pub struct Test {
     pub buf: Vec<u8>,
@@ -19,15 +18,15 @@ impl DbBackend for Test {
         true
     }
 
-    async fn get_db_read(&self, _user_info: &UserInfo) -> Result<Pin<Box<dyn AsyncRead + '_>>> {
-        Ok(Box::pin(self.buf.as_slice()))
+    async fn get_db_read(&self, _user_info: &UserInfo) -> Result<(DbReader<'_>, Option<DbVersion>)> {
+        Ok((Box::pin(self.buf.as_slice()), None))
     }
 
     async fn get_key_read(&self, _: &UserInfo) -> Option<Result<Pin<Box<dyn AsyncRead + '_>>>> {
         None
     }
 
-    async fn get_db_write(&mut self, _user_info: &UserInfo) -> Result<(Pin<Box<dyn AsyncWrite + '_>>, Option<Receiver<Result<()>>>)> {
+    async fn get_db_write(&mut self, _user_info: &UserInfo, _version: Option<&DbVersion>) -> Result<DbWriter<'_>> {
         Ok(
             (
                 Box::pin(&mut self.buf),
@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+// Server side state kept in JSON files, e.g. personal access tokens.
+// A missing file is treated as empty
+pub(crate) async fn load<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    match tokio::fs::read(path).await {
+        Ok(v) => Ok(serde_json::from_slice(&v)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Replaces the file at once, so concurrent readers never see a partial file.
+// The file is only readable by the owner, it holds secrets or their hashes
+pub(crate) async fn save<T: Serialize>(path: &Path, items: &[T]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp)
+        .await?;
+    file.write_all(&serde_json::to_vec_pretty(items)?).await?;
+    file.sync_all().await?;
+    tokio::fs::rename(&tmp, path).await?;
+
+    Ok(())
+}
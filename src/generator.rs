@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::generator::{Capitalization, CharSet, Generator, Passphrase, Profile};
+
+pub mod passphrase;
+pub mod password;
+
+// Generation options as requested by the user, unset options are taken from the selected profile
+#[derive(Default, Deserialize)]
+pub struct PasswordOptions {
+    pub profile: Option<String>,
+    pub length: Option<usize>,
+    pub charsets: Option<Vec<CharSet>>,
+    pub minimum: Option<HashMap<CharSet, usize>>,
+    pub exclude_lookalike: Option<bool>,
+    pub exclude: Option<String>,
+    pub pattern: Option<String>,
+    pub permute: Option<bool>,
+}
+
+impl PasswordOptions {
+    pub fn profile(&self, config: &Generator) -> Result<Profile> {
+        let mut profile = match &self.profile {
+            Some(name) => config.profiles.get(name).ok_or(anyhow!("unknown generator profile '{}'", name))?.clone(),
+            None => config.default.clone(),
+        };
+
+        if let Some(v) = self.length {
+            profile.length = v;
+        }
+        if let Some(v) = &self.charsets {
+            profile.charsets = v.clone();
+        }
+        if let Some(v) = &self.minimum {
+            profile.minimum = v.clone();
+        }
+        if let Some(v) = self.exclude_lookalike {
+            profile.exclude_lookalike = v;
+        }
+        if let Some(v) = &self.exclude {
+            profile.exclude = v.clone();
+        }
+        if let Some(v) = &self.pattern {
+            // an empty pattern switches back to character sets
+            profile.pattern = Some(v.clone()).filter(|v| !v.is_empty());
+        }
+        if let Some(v) = self.permute {
+            profile.permute = v;
+        }
+
+        Ok(profile)
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct PassphraseOptions {
+    pub words: Option<usize>,
+    pub separator: Option<String>,
+    pub capitalization: Option<Capitalization>,
+    pub digits: Option<usize>,
+    pub wordlist: Option<String>,
+}
+
+impl PassphraseOptions {
+    pub fn profile(&self, config: &Generator) -> Passphrase {
+        let mut profile = config.passphrase.clone();
+
+        if let Some(v) = self.words {
+            profile.words = v;
+        }
+        if let Some(v) = &self.separator {
+            profile.separator = v.clone();
+        }
+        if let Some(v) = self.capitalization {
+            profile.capitalization = v;
+        }
+        if let Some(v) = self.digits {
+            profile.digits = v;
+        }
+        if let Some(v) = &self.wordlist {
+            profile.wordlist = Some(v.clone());
+        }
+
+        profile
+    }
+}
+
+#[derive(Serialize)]
+pub struct Generated {
+    pub password: String,
+    // estimated strength in bits, assuming the attacker knows how the password was generated
+    pub entropy: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_override() {
+        let mut config = Generator::default();
+        config.profiles.insert("pin".to_string(), Profile {
+            length: 6,
+            charsets: vec![CharSet::Digits],
+            ..Default::default()
+        });
+
+        let options = PasswordOptions {
+            profile: Some("pin".to_string()),
+            length: Some(8),
+            ..Default::default()
+        };
+        let profile = options.profile(&config).unwrap();
+        assert_eq!(profile.length, 8);
+        assert_eq!(profile.charsets, vec![CharSet::Digits]);
+
+        let options = PasswordOptions {
+            profile: Some("unknown".to_string()),
+            ..Default::default()
+        };
+        assert!(options.profile(&config).is_err());
+
+        let profile = PasswordOptions::default().profile(&config).unwrap();
+        assert_eq!(profile.length, Profile::default().length);
+    }
+}
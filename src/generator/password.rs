@@ -0,0 +1,297 @@
+use anyhow::{anyhow, bail, Result};
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+
+use crate::config::generator::{CharSet, Profile};
+use crate::generator::Generated;
+
+// also limits repetitions in patterns
+const MAX_LENGTH: usize = 1024;
+
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const DIGITS: &str = "0123456789";
+const SPECIAL: &str = "!\"#$%&'*+,./:;=?@\\^`|~";
+const BRACKETS: &str = "[]{}()<>";
+const LOOKALIKE: &str = "O0Il1|";
+
+// only available in patterns
+const UPPER_HEX: &str = "0123456789ABCDEF";
+const LOWER_HEX: &str = "0123456789abcdef";
+const UPPER_VOWELS: &str = "AEIOU";
+const LOWER_VOWELS: &str = "aeiou";
+const UPPER_CONSONANTS: &str = "BCDFGHJKLMNPQRSTVWXYZ";
+const LOWER_CONSONANTS: &str = "bcdfghjklmnpqrstvwxyz";
+const PUNCTUATION: &str = ",.;:";
+const PRINTABLE_SPECIAL: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+// Generates a password from the pattern of the profile, or its character sets if there is none
+pub fn generate(profile: &Profile) -> Result<Generated> {
+    let positions = match &profile.pattern {
+        Some(pattern) => parse_pattern(pattern, profile)?,
+        None => from_charsets(profile)?,
+    };
+
+    let mut rng = thread_rng();
+    let mut chars: Vec<char> = positions.iter().filter_map(|set| set.choose(&mut rng).copied()).collect();
+    // passwords from character sets start with the required characters
+    if profile.pattern.is_none() || profile.permute {
+        chars.shuffle(&mut rng);
+    }
+
+    let entropy: f64 = positions.iter().map(|set| (set.len() as f64).log2()).sum();
+
+    Ok(
+        Generated {
+            password: chars.into_iter().collect(),
+            entropy: entropy.floor() as u32,
+        }
+    )
+}
+
+// Returns the characters to choose from, per position of the password
+fn from_charsets(profile: &Profile) -> Result<Vec<Vec<char>>> {
+    if profile.length == 0 || profile.length > MAX_LENGTH {
+        bail!("length must be between 1 and {}", MAX_LENGTH);
+    }
+
+    for set in profile.minimum.keys() {
+        if !profile.charsets.contains(set) {
+            bail!("minimum given for disabled character set {:?}", set);
+        }
+    }
+
+    let mut pool: Vec<char> = vec![];
+    let mut positions = vec![];
+    for set in &profile.charsets {
+        let chars = filter(charset(*set), profile);
+        let minimum = profile.minimum.get(set).copied().unwrap_or_default();
+        if chars.is_empty() && minimum > 0 {
+            bail!("all characters of character set {:?} are excluded", set);
+        }
+
+        positions.extend(std::iter::repeat_n(chars.clone(), minimum));
+        for c in chars {
+            if !pool.contains(&c) {
+                pool.push(c);
+            }
+        }
+    }
+
+    if pool.is_empty() {
+        bail!("no characters to choose from");
+    }
+    if positions.len() > profile.length {
+        bail!("minimum character counts exceed the length");
+    }
+
+    let remaining = profile.length - positions.len();
+    positions.extend(std::iter::repeat_n(pool, remaining));
+
+    Ok(positions)
+}
+
+// KeePass pattern syntax: placeholders, `\` escapes a literal, `{n}` repeats the preceding
+// placeholder n times and `[...]` defines a custom set, with characters after `^` excluded from it
+fn parse_pattern(pattern: &str, profile: &Profile) -> Result<Vec<Vec<char>>> {
+    let mut positions: Vec<Vec<char>> = vec![];
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // literals are never filtered
+            '\\' => positions.push(vec![chars.next().ok_or(anyhow!("pattern ends with an escape character"))?]),
+            '{' => {
+                let mut count = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => count.push(c),
+                        None => bail!("unterminated repetition in pattern"),
+                    }
+                }
+                let count: usize = count.parse().map_err(|_| anyhow!("invalid repetition count '{}' in pattern", count))?;
+                // the count is user input, don't let the sum overflow
+                if positions.len().checked_add(count).is_none_or(|len| len > MAX_LENGTH) {
+                    bail!("pattern generates more than {} characters", MAX_LENGTH);
+                }
+
+                let last = positions.pop().ok_or(anyhow!("repetition without preceding placeholder in pattern"))?;
+                positions.extend(std::iter::repeat_n(last, count));
+            }
+            '[' => {
+                let mut set = vec![];
+                let mut excluded = vec![];
+                let mut exclude = false;
+                loop {
+                    let c = chars.next().ok_or(anyhow!("unterminated character set in pattern"))?;
+                    let target = match exclude {
+                        true => &mut excluded,
+                        false => &mut set,
+                    };
+                    match c {
+                        ']' => break,
+                        '^' => exclude = true,
+                        '\\' => target.push(chars.next().ok_or(anyhow!("pattern ends with an escape character"))?),
+                        // anything else than a placeholder is taken literally in here
+                        c => target.extend(placeholder(c).unwrap_or(vec![c])),
+                    }
+                }
+
+                let mut set: Vec<char> = filter(set, profile).into_iter().filter(|c| !excluded.contains(c)).collect();
+                set.sort_unstable();
+                set.dedup();
+                if set.is_empty() {
+                    bail!("character set in pattern is empty");
+                }
+                positions.push(set);
+            }
+            c => {
+                let set = filter(placeholder(c).ok_or(anyhow!("unknown placeholder '{}' in pattern", c))?, profile);
+                if set.is_empty() {
+                    bail!("all characters of placeholder '{}' are excluded", c);
+                }
+                positions.push(set);
+            }
+        }
+    }
+
+    if positions.is_empty() {
+        bail!("pattern is empty");
+    }
+    if positions.len() > MAX_LENGTH {
+        bail!("pattern generates more than {} characters", MAX_LENGTH);
+    }
+
+    Ok(positions)
+}
+
+fn charset(set: CharSet) -> Vec<char> {
+    match set {
+        CharSet::Upper => UPPER.chars().collect(),
+        CharSet::Lower => LOWER.chars().collect(),
+        CharSet::Digits => DIGITS.chars().collect(),
+        CharSet::Minus => vec!['-'],
+        CharSet::Underline => vec!['_'],
+        CharSet::Space => vec![' '],
+        CharSet::Special => SPECIAL.chars().collect(),
+        CharSet::Brackets => BRACKETS.chars().collect(),
+        CharSet::Extended => extended(),
+    }
+}
+
+fn placeholder(c: char) -> Option<Vec<char>> {
+    let sets: &[&str] = match c {
+        'a' => &[LOWER, DIGITS],
+        'A' => &[UPPER, LOWER, DIGITS],
+        'U' => &[UPPER, DIGITS],
+        'd' => &[DIGITS],
+        'h' => &[LOWER_HEX],
+        'H' => &[UPPER_HEX],
+        'l' => &[LOWER],
+        'L' => &[UPPER, LOWER],
+        'u' => &[UPPER],
+        'v' => &[LOWER_VOWELS],
+        'V' => &[UPPER_VOWELS, LOWER_VOWELS],
+        'Z' => &[UPPER_VOWELS],
+        'c' => &[LOWER_CONSONANTS],
+        'C' => &[UPPER_CONSONANTS, LOWER_CONSONANTS],
+        'z' => &[UPPER_CONSONANTS],
+        'p' => &[PUNCTUATION],
+        'b' => &[BRACKETS],
+        's' => &[PRINTABLE_SPECIAL],
+        'S' => &[UPPER, LOWER, DIGITS, PRINTABLE_SPECIAL],
+        'x' => return Some(extended()),
+        _ => return None,
+    };
+
+    Some(sets.concat().chars().collect())
+}
+
+// high ANSI characters, without the (invisible) soft hyphen
+fn extended() -> Vec<char> {
+    (0xa1u8..=0xff).filter(|c| *c != 0xad).map(char::from).collect()
+}
+
+fn filter(chars: Vec<char>, profile: &Profile) -> Vec<char> {
+    chars.into_iter()
+        .filter(|c| !(profile.exclude_lookalike && LOOKALIKE.contains(*c)))
+        .filter(|c| !profile.exclude.contains(*c))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn pattern(pattern: &str) -> Profile {
+        Profile {
+            pattern: Some(pattern.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn charsets() {
+        let profile = Profile {
+            length: 32,
+            charsets: vec![CharSet::Digits, CharSet::Special],
+            minimum: HashMap::from([(CharSet::Special, 5)]),
+            ..Default::default()
+        };
+        let generated = generate(&profile).unwrap();
+        assert_eq!(generated.password.chars().count(), 32);
+        assert!(generated.password.chars().all(|c| DIGITS.contains(c) || SPECIAL.contains(c)));
+        assert!(generated.password.chars().filter(|c| SPECIAL.contains(*c)).count() >= 5);
+        // 5 of 22 special characters, the rest out of 32 characters
+        assert_eq!(generated.entropy, (5.0 * 22f64.log2() + 27.0 * 5.0).floor() as u32);
+
+        let profile = Profile {
+            length: 200,
+            charsets: vec![CharSet::Upper, CharSet::Lower, CharSet::Digits],
+            exclude_lookalike: true,
+            exclude: "abc".to_string(),
+            ..Default::default()
+        };
+        let generated = generate(&profile).unwrap();
+        assert!(!generated.password.chars().any(|c| LOOKALIKE.contains(c) || "abc".contains(c)));
+
+        let profile = Profile {
+            length: 4,
+            charsets: vec![CharSet::Digits, CharSet::Upper],
+            minimum: HashMap::from([(CharSet::Digits, 3), (CharSet::Upper, 2)]),
+            ..Default::default()
+        };
+        assert!(generate(&profile).is_err());
+
+        let profile = Profile {
+            minimum: HashMap::from([(CharSet::Special, 1)]),
+            ..Default::default()
+        };
+        assert!(generate(&profile).is_err());
+    }
+
+    #[test]
+    fn patterns() {
+        let generated = generate(&pattern("u{4}d{2}")).unwrap();
+        let chars: Vec<char> = generated.password.chars().collect();
+        assert_eq!(chars.len(), 6);
+        assert!(chars[..4].iter().all(|c| c.is_ascii_uppercase()));
+        assert!(chars[4..].iter().all(|c| c.is_ascii_digit()));
+        assert_eq!(generated.entropy, 25);
+
+        let generated = generate(&pattern("\\u\\-[d^0]{3}")).unwrap();
+        assert!(generated.password.starts_with("u-"));
+        assert!(generated.password[2..].chars().all(|c| c.is_ascii_digit() && c != '0'));
+
+        let mut profile = pattern("[\\1\\2]{50}");
+        profile.exclude_lookalike = true;
+        assert_eq!(generate(&profile).unwrap().password, "2".repeat(50));
+
+        for invalid in ["q", "{2}", "u{", "u{x}", "[d", "[^d]", "\\", "u{2000}", "u{18446744073709551615}", "uu{18446744073709551615}"] {
+            assert!(generate(&pattern(invalid)).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -2,4 +2,13 @@ pub mod keepass;
 pub mod db_cache;
 pub mod encrypted;
 pub mod key;
+pub mod query;
+pub mod otp;
 mod entry;
+mod attachment;
+mod breach;
+mod health;
+mod merge;
+mod placeholder;
+mod strength;
+mod url_match;
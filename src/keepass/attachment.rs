@@ -0,0 +1,70 @@
+use std::borrow::Cow;
+use std::io::Read;
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use keepass::config::DatabaseVersion;
+use keepass::Database;
+
+// Binary fields of entries hold the reference to the database wide binary pool (`<Value Ref="..."/>`),
+// never the file content itself
+pub(crate) fn reference(value: &[u8]) -> Result<usize> {
+    std::str::from_utf8(value).ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .ok_or(anyhow!("invalid binary reference"))
+}
+
+// KDBX4 keeps the pool in the inner header, referenced by position.
+// Older versions keep it in the meta data, referenced by identifier
+pub(crate) fn in_header(db: &Database) -> bool {
+    matches!(db.config.version, DatabaseVersion::KDB4(_))
+}
+
+pub(crate) fn resolve<'a>(db: &'a Database, value: &[u8]) -> Result<Cow<'a, [u8]>> {
+    let id = reference(value)?;
+
+    if in_header(db) {
+        return match db.header_attachments.get(id) {
+            Some(attachment) => Ok(Cow::Borrowed(&attachment.content)),
+            None => Err(anyhow!("binary reference '{}' not found", id)),
+        };
+    }
+
+    let identifier = id.to_string();
+    let binary = db.meta.binaries.binaries.iter()
+        .find(|b| b.identifier.as_deref() == Some(identifier.as_str()))
+        .ok_or(anyhow!("binary reference '{}' not found", id))?;
+
+    if !binary.compressed {
+        return Ok(Cow::Borrowed(&binary.content));
+    }
+
+    let mut content = vec![];
+    GzDecoder::new(binary.content.as_slice()).read_to_end(&mut content)?;
+
+    Ok(Cow::Owned(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use keepass::db::{BinaryAttachment, HeaderAttachment};
+
+    use super::*;
+
+    #[test]
+    fn references() {
+        let mut db = Database::new(Default::default());
+        db.config.version = DatabaseVersion::KDB4(1);
+        db.header_attachments.push(HeaderAttachment { flags: 0, content: b"3".to_vec() });
+
+        // numeric content is still content, the field only ever holds the reference
+        assert_eq!(resolve(&db, b"0").unwrap().as_ref(), b"3");
+        assert!(resolve(&db, b"3").is_err());
+        assert!(resolve(&db, b"content").is_err());
+
+        db.config.version = DatabaseVersion::KDB3(1);
+        db.meta.binaries.binaries.push(BinaryAttachment { identifier: Some("7".to_string()), compressed: false, content: b"kdbx3".to_vec() });
+        assert_eq!(resolve(&db, b"7").unwrap().as_ref(), b"kdbx3");
+        assert!(resolve(&db, b"0").is_err());
+    }
+}
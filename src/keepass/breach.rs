@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use data_encoding::HEXUPPER_PERMISSIVE;
+use keepass::Database;
+use keepass::db::{Group, Node};
+use log::warn;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use uuid::Uuid;
+
+use crate::config::breach::Breach;
+use crate::keepass::health::entry_password;
+
+// 20 bytes SHA-1 hash, 4 bytes count
+const RECORD_SIZE: u64 = 24;
+
+pub(crate) type Hash = [u8; 20];
+
+#[derive(Serialize)]
+pub struct BreachedEntry {
+    pub id: Uuid,
+    pub path: String,
+    // number of times the password was seen in breaches
+    pub count: u64,
+}
+
+#[derive(Default, Serialize)]
+pub struct Report {
+    pub checked: usize,
+    // passwords whose range is missing from the local copy
+    pub unchecked: usize,
+    pub breached: Vec<BreachedEntry>,
+}
+
+pub(crate) fn hash(password: &str) -> Hash {
+    Sha1::digest(password.as_bytes()).into()
+}
+
+// Hashes of all non-empty passwords outside the recycle bin, the passwords themselves don't leave this function
+pub(crate) fn collect(db: &Database) -> Vec<(Uuid, String, Hash)> {
+    let mut hashes = vec![];
+    collect_group(&db.root, &db.root, &db.root.name, db.meta.recyclebin_uuid, &mut hashes);
+    hashes
+}
+
+fn collect_group(root: &Group, group: &Group, path: &str, recycle_bin: Option<Uuid>, hashes: &mut Vec<(Uuid, String, Hash)>) {
+    for node in &group.children {
+        match node {
+            Node::Group(child) if Some(child.uuid) == recycle_bin => {}
+            Node::Group(child) => collect_group(root, child, &format!("{}/{}", path, child.name), recycle_bin, hashes),
+            Node::Entry(entry) => {
+                if let Some(password) = entry_password(root, entry).filter(|v| !v.is_empty()) {
+                    hashes.push((entry.uuid, path.to_string(), hash(&password)));
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn report(config: &Breach, hashes: Vec<(Uuid, String, Hash)>) -> Result<Report> {
+    let counts = count(config, &hashes.iter().map(|(_, _, hash)| *hash).collect::<Vec<Hash>>())?;
+
+    let unchecked = counts.iter().filter(|count| count.is_none()).count();
+    if unchecked > 0 {
+        warn!("breach report: {} password(s) not checked, range files are missing", unchecked);
+    }
+
+    let mut report = Report {
+        checked: hashes.len() - unchecked,
+        unchecked,
+        breached: hashes.into_iter().zip(counts)
+            .filter_map(|((id, path, _), count)| match count {
+                Some(count) if count > 0 => Some(BreachedEntry { id, path, count }),
+                _ => None,
+            })
+            .collect(),
+    };
+    report.breached.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(report)
+}
+
+// Looks up the hashes by their 5 hex digit prefix (k-anonymity range), like the Pwned Passwords API does.
+// Returns how often each hash was seen in breaches, 0 if not at all, None if its range is not available
+pub(crate) fn count(config: &Breach, hashes: &[Hash]) -> Result<Vec<Option<u64>>> {
+    if !config.enabled() {
+        bail!("breach check is not configured");
+    }
+
+    let mut ranges: HashMap<u32, Option<Vec<(Hash, u64)>>> = HashMap::new();
+    let mut counts = Vec::with_capacity(hashes.len());
+    for hash in hashes {
+        let prefix = prefix(hash);
+        let range = match ranges.entry(prefix) {
+            Entry::Occupied(v) => v.into_mut(),
+            Entry::Vacant(v) => v.insert(range(config, prefix)?),
+        };
+        counts.push(range.as_ref().map(|range| range.iter().find(|(h, _)| h == hash).map(|(_, count)| *count).unwrap_or_default()));
+    }
+
+    Ok(counts)
+}
+
+fn prefix(hash: &Hash) -> u32 {
+    u32::from_be_bytes([0, hash[0], hash[1], hash[2]]) >> 4
+}
+
+fn range(config: &Breach, prefix: u32) -> Result<Option<Vec<(Hash, u64)>>> {
+    match (&config.directory, &config.file) {
+        (Some(directory), _) => range_from_directory(directory, prefix),
+        (None, Some(file)) => range_from_file(file, prefix).map(Some),
+        (None, None) => bail!("breach check is not configured"),
+    }
+}
+
+// Range files contain lines of `<35 hex digit hash suffix>:<count>`.
+// A missing range file means the hashes of that range can't be checked, partial copies are common
+fn range_from_directory(directory: &Path, prefix: u32) -> Result<Option<Vec<(Hash, u64)>>> {
+    let prefix = format!("{:05X}", prefix);
+    let path = match [directory.join(format!("{}.txt", prefix)), directory.join(&prefix)].into_iter().find(|path| path.is_file()) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    std::fs::read_to_string(path)?.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (suffix, count) = line.split_once(':').ok_or(anyhow!("invalid line in range file"))?;
+            let hash = HEXUPPER_PERMISSIVE.decode(format!("{}{}", prefix, suffix).as_bytes())?;
+
+            Ok((hash.try_into().map_err(|_| anyhow!("invalid hash in range file"))?, count.parse()?))
+        })
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+fn range_from_file(path: &Path, prefix: u32) -> Result<Vec<(Hash, u64)>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    if size % RECORD_SIZE != 0 {
+        bail!("size of '{}' is not a multiple of {} bytes", path.display(), RECORD_SIZE);
+    }
+
+    let records = size / RECORD_SIZE;
+
+    // binary search for the first record of the range
+    let (mut low, mut high) = (0, records);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        file.seek(SeekFrom::Start(mid * RECORD_SIZE))?;
+        if self::prefix(&read_record(&mut file)?.0) < prefix {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    file.seek(SeekFrom::Start(low * RECORD_SIZE))?;
+    let mut reader = BufReader::new(file);
+    let mut range = vec![];
+    for _ in low..records {
+        let record = read_record(&mut reader)?;
+        if self::prefix(&record.0) != prefix {
+            break;
+        }
+        range.push(record);
+    }
+
+    Ok(range)
+}
+
+fn read_record(reader: &mut impl Read) -> Result<(Hash, u64)> {
+    let mut hash = [0u8; 20];
+    let mut count = [0u8; 4];
+    reader.read_exact(&mut hash)?;
+    reader.read_exact(&mut count)?;
+
+    Ok((hash, u32::from_be_bytes(count) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::gen_token;
+
+    #[test]
+    fn range_directory() {
+        let directory = std::env::temp_dir().join(format!("keepass4web-test-{}", gen_token(8)));
+        std::fs::create_dir(&directory).unwrap();
+        // SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+        std::fs::write(
+            directory.join("5BAA6.txt"),
+            "003D68EB55068C33ACE09247EE4C639306B:3\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:10434004\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD9:0\r\n",
+        ).unwrap();
+        let config = Breach {
+            directory: Some(directory.clone()),
+            file: None,
+        };
+
+        let result = count(&config, &[hash("password"), hash("password")]);
+        let missing = count(&config, &[hash("x7#Lq9!vR2@mZp4$wK8&")]);
+        let report = report(&config, vec![
+            (Uuid::new_v4(), "Root".to_string(), hash("password")),
+            (Uuid::new_v4(), "Root".to_string(), hash("x7#Lq9!vR2@mZp4$wK8&")),
+        ]).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(result.unwrap(), vec![Some(10434004), Some(10434004)]);
+        assert_eq!(missing.unwrap(), vec![None]);
+        assert_eq!((report.checked, report.unchecked, report.breached.len()), (1, 1, 1));
+        assert!(count(&Breach::default(), &[hash("password")]).is_err());
+    }
+
+    #[test]
+    fn range_file() {
+        let mut records: Vec<(Hash, u32)> = ["password", "123456", "qwerty", "letmein", "dragon", "monkey"].iter()
+            .enumerate()
+            .map(|(i, password)| (hash(password), i as u32 + 1))
+            .collect();
+        // same prefix as "password"
+        let mut neighbour = hash("password");
+        neighbour[19] ^= 1;
+        records.push((neighbour, 7));
+        records.sort();
+
+        let path = std::env::temp_dir().join(format!("keepass4web-test-{}.bin", gen_token(8)));
+        std::fs::write(&path, records.iter().flat_map(|(hash, count)| [&hash[..], &count.to_be_bytes()].concat()).collect::<Vec<u8>>()).unwrap();
+        let config = Breach {
+            directory: None,
+            file: Some(path.clone()),
+        };
+
+        let result = count(&config, &[hash("password"), hash("dragon"), hash("monkey"), hash("x7#Lq9!vR2@mZp4$wK8&"), neighbour]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap(), vec![Some(1), Some(5), Some(6), Some(0), Some(7)]);
+    }
+}
@@ -10,7 +10,7 @@ use anyhow::Result;
 use log::info;
 use tokio::sync::RwLock;
 
-use crate::auth::SESSION_KEY_USER;
+use crate::auth::{SESSION_KEY_TOKEN, SESSION_KEY_USER};
 use crate::auth_backend::UserInfo;
 use crate::keepass::encrypted::Encrypted;
 
@@ -79,8 +79,14 @@ impl DbCache {
     }
 
     fn get_user(&self, session: &Session) -> Result<String> {
+        let user = session.get::<UserInfo>(SESSION_KEY_USER)?.ok_or(anyhow!("unable to retrieve user from session"))?.id;
+
+        // token sessions don't share the database of the user's browser session
         Ok(
-            session.get::<UserInfo>(SESSION_KEY_USER)?.ok_or(anyhow!("unable to retrieve user from session"))?.id
+            match session.get::<String>(SESSION_KEY_TOKEN)? {
+                Some(token) => format!("{}:token:{}", user, token),
+                None => user,
+            }
         )
     }
 }
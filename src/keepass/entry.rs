@@ -1,11 +1,35 @@
 use std::collections::HashMap;
 
+use chrono::NaiveDateTime;
 use keepass::db::Value;
-use regex::Regex;
 use serde::Serialize;
 use uuid::Uuid;
 
-use crate::config::search::{Field, Search};
+use crate::keepass::otp;
+use crate::keepass::query::SearchMatch;
+
+#[derive(Serialize)]
+pub struct Times {
+    pub creation: Option<NaiveDateTime>,
+    pub last_modification: Option<NaiveDateTime>,
+    pub last_access: Option<NaiveDateTime>,
+    pub expiry: Option<NaiveDateTime>,
+    pub expires: bool,
+    pub usage_count: usize,
+}
+
+impl From<&keepass::db::Times> for Times {
+    fn from(times: &keepass::db::Times) -> Self {
+        Times {
+            creation: times.get_creation().copied(),
+            last_modification: times.get_last_modification().copied(),
+            last_access: times.get_last_access().copied(),
+            expiry: times.get_expiry().copied(),
+            expires: times.expires,
+            usage_count: times.usage_count,
+        }
+    }
+}
 
 #[derive(Serialize)]
 pub struct Group {
@@ -15,6 +39,8 @@ pub struct Group {
     pub custom_icon_uuid: Option<Uuid>,
     pub children: Vec<Group>,
     pub expanded: bool,
+    pub times: Times,
+    pub recycle_bin: bool,
 }
 
 #[derive(Serialize)]
@@ -23,6 +49,8 @@ pub struct EntryGroup {
     pub icon: Option<usize>,
     pub custom_icon_uuid: Option<Uuid>,
     pub entries: Vec<Entry>,
+    // number of entries before pagination
+    pub total: usize,
 }
 
 #[derive(Serialize)]
@@ -31,18 +59,58 @@ pub struct Entry {
     pub title: Option<String>,
     pub username: Option<String>,
     pub notes: Option<String>,
-    pub binary: Option<Vec<String>>,
+    pub binary: Option<HashMap<String, usize>>,
     pub protected: Option<HashMap<String, ()>>,
     pub tags: Option<Vec<String>>,
     pub icon: Option<usize>,
     pub custom_icon_uuid: Option<Uuid>,
     pub url: Option<String>,
     pub strings: Option<HashMap<String, Option<String>>>,
+    // values of fields with placeholders or references, after resolving them
+    pub resolved: Option<HashMap<String, String>>,
+    pub otp: bool,
+    pub times: Times,
+    // search results only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<SearchMatch>,
+}
+
+// Entries of a single group, the path being the group names from the root down
+#[derive(Serialize)]
+pub struct GroupPath {
+    pub id: Uuid,
+    pub path: String,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+pub struct Revision {
+    pub revision: usize,
+    pub title: Option<String>,
+    pub username: Option<String>,
+    pub last_modification: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Serialize)]
+pub struct FieldDiff {
+    pub name: String,
+    pub change: Change,
+    pub protected: bool,
+    // values of protected fields are left out
+    pub old: Option<String>,
+    pub new: Option<String>,
 }
 
 impl From<&keepass::db::Entry> for Entry {
     fn from(entry: &keepass::db::Entry) -> Self {
-        let files = vec![];
         let mut strings: HashMap<String, Option<String>> = Default::default();
         let mut protected: HashMap<String, ()> = Default::default();
 
@@ -66,52 +134,17 @@ impl From<&keepass::db::Entry> for Entry {
             title: strings.remove("Title").flatten(),
             username: strings.remove("UserName").flatten(),
             notes: strings.remove("Notes").flatten(),
-            binary: Some(files),
+            binary: None,
             protected: Some(protected),
             tags: Some(entry.tags.clone()),
             icon: entry.icon_id,
             custom_icon_uuid: entry.custom_icon_uuid,
             url: strings.remove("URL").flatten(),
             strings: Some(strings),
+            resolved: None,
+            otp: otp::has_otp(entry),
+            times: (&entry.times).into(),
+            search: None,
         }
     }
 }
-
-impl Entry {
-    pub fn matches_regex(&self, term: &Regex, config: &Search) -> bool {
-        for field in &config.fields {
-            let tmp;
-            let match_str = match field {
-                Field::Title => self.title.as_deref().unwrap_or_default(),
-                Field::Username => self.username.as_deref().unwrap_or_default(),
-                Field::Tags => {
-                    match &self.tags {
-                        None => "",
-                        Some(v) => {
-                            tmp = v.join(";");
-                            &tmp
-                        }
-                    }
-                }
-                Field::Notes => self.notes.as_deref().unwrap_or_default(),
-                Field::Url => self.url.as_deref().unwrap_or_default(),
-            };
-
-            if term.is_match(match_str) {
-                return true;
-            }
-        }
-
-        if config.extra_fields && self.strings.is_some() {
-            for (k, v) in self.strings.as_ref().unwrap() {
-                if term.is_match(k) || term.is_match(v.as_deref().unwrap_or_default()) {
-                    return true;
-                }
-            }
-        }
-
-        false
-    }
-}
-
-
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use hmac::{Hmac, Mac};
+use keepass::Database;
+use keepass::db::{Entry, Group, Node, Times};
+use log::warn;
+use rand::{Rng, thread_rng};
+use serde::Serialize;
+use sha2::Sha256;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::config::health::Health;
+use crate::keepass::keepass::{PASSWORD, TITLE, URL, USERNAME};
+use crate::keepass::placeholder::Resolver;
+use crate::keepass::strength;
+
+#[derive(Serialize)]
+pub struct ReportEntry {
+    pub id: Uuid,
+    pub path: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct Report {
+    // entries sharing the same password, one list per password
+    pub reused: Vec<Vec<ReportEntry>>,
+    pub weak: Vec<ReportEntry>,
+    pub old: Vec<ReportEntry>,
+    pub empty: Vec<ReportEntry>,
+}
+
+struct Collector<'a> {
+    root: &'a Group,
+    config: &'a Health,
+    recycle_bin: Option<Uuid>,
+    changed_before: NaiveDateTime,
+    // passwords are only compared by their keyed hash, the key is thrown away with the report
+    key: Zeroizing<[u8; 32]>,
+    hashes: HashMap<Vec<u8>, Vec<ReportEntry>>,
+    report: Report,
+}
+
+// Checks the passwords of all entries outside the recycle bin, the report only refers to entries by id and group path
+pub(crate) fn report(db: &Database, config: &Health) -> Result<Report> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    thread_rng().fill(&mut key[..]);
+
+    let mut collector = Collector {
+        root: &db.root,
+        config,
+        recycle_bin: db.meta.recyclebin_uuid,
+        changed_before: Times::now() - chrono::Duration::from_std(config.max_age)?,
+        key,
+        hashes: HashMap::new(),
+        report: Report::default(),
+    };
+    collector.check_group(&db.root, &db.root.name)?;
+
+    let mut report = collector.report;
+    for entries in [&mut report.weak, &mut report.old, &mut report.empty] {
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    report.reused = collector.hashes.into_values().filter(|entries| entries.len() > 1).collect();
+    for entries in &mut report.reused {
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    report.reused.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+
+    Ok(report)
+}
+
+impl Collector<'_> {
+    fn check_group(&mut self, group: &Group, path: &str) -> Result<()> {
+        for node in &group.children {
+            match node {
+                Node::Group(child) if Some(child.uuid) == self.recycle_bin => {}
+                Node::Group(child) => self.check_group(child, &format!("{}/{}", path, child.name))?,
+                Node::Entry(entry) => self.check_entry(entry, path)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_entry(&mut self, entry: &Entry, path: &str) -> Result<()> {
+        let report_entry = || ReportEntry {
+            id: entry.uuid,
+            path: path.to_string(),
+        };
+
+        let password = match entry_password(self.root, entry) {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        if password.is_empty() {
+            self.report.empty.push(report_entry());
+            return Ok(());
+        }
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key[..])?;
+        mac.update(password.as_bytes());
+        self.hashes.entry(mac.finalize().into_bytes().to_vec()).or_default().push(report_entry());
+
+        let user_inputs: Vec<&str> = [TITLE, USERNAME, URL].iter().filter_map(|field| entry.get(field)).collect();
+        // one password that can't be rated doesn't spoil the report of all others
+        match strength::estimate(&password, &user_inputs) {
+            Ok(strength) if strength.score < self.config.min_score => self.report.weak.push(report_entry()),
+            Ok(_) => {}
+            Err(err) => warn!("failed to estimate password strength of entry '{}': {}", entry.uuid, err),
+        }
+
+        if password_changed(entry).is_some_and(|changed| changed < self.changed_before) {
+            self.report.old.push(report_entry());
+        }
+
+        Ok(())
+    }
+}
+
+// The password of the entry with placeholders resolved.
+// None for references, the referenced entry is checked on its own and sharing its password is intended
+pub(super) fn entry_password(root: &Group, entry: &Entry) -> Option<Zeroizing<String>> {
+    let raw = entry.get(PASSWORD).unwrap_or_default();
+    // no upper case copy of the password, it would not be zeroized
+    if raw.as_bytes().windows(5).any(|v| v.eq_ignore_ascii_case(b"{REF:")) {
+        return None;
+    }
+    if !raw.contains('{') {
+        return Some(Zeroizing::new(raw.to_string()));
+    }
+
+    match Resolver::new(root).resolve_field(entry, PASSWORD) {
+        Ok(v) => Some(Zeroizing::new(v.unwrap_or_default())),
+        Err(err) => {
+            warn!("failed to resolve password of entry '{}': {}", entry.uuid, err);
+            Some(Zeroizing::new(raw.to_string()))
+        }
+    }
+}
+
+// The oldest revision in a row having the current password
+fn password_changed(entry: &Entry) -> Option<NaiveDateTime> {
+    let password = entry.get(PASSWORD);
+    let mut changed = entry.times.get_last_modification().copied();
+
+    if let Some(history) = &entry.history {
+        // most recent first
+        for revision in history.get_entries() {
+            if revision.get(PASSWORD) != password {
+                break;
+            }
+            changed = revision.times.get_last_modification().copied().or(changed);
+        }
+    }
+
+    changed
+}
@@ -1,30 +1,74 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
 use actix_web::web::{Path, Query};
 use anyhow::{anyhow, bail};
 use anyhow::Result;
 use base64;
 use base64::Engine;
 use base64::engine::general_purpose;
+use chrono::NaiveDateTime;
 use keepass::{Database, DatabaseKey};
-use keepass::db::{Icon, Node, Value};
-use regex::Regex;
+use keepass::db::{DeletedObject, Icon, Node, Times, Value};
+use log::{info, warn};
 use secrecy::{ExposeSecret, SecretString};
+use secstr::SecStr;
 use serde::Deserialize;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use uuid::Uuid;
-use zeroize::Zeroize;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
-use crate::auth::DbLogin;
+use crate::auth::{DbCredentials, DbLogin};
 use crate::auth_backend::UserInfo;
 use crate::config::config::Config;
 use crate::config::search::Search;
-use crate::db_backend::DbBackend;
+use crate::db_backend::{ConflictError, DbBackend, DbVersion};
+use crate::keepass::{attachment, breach, health, merge, otp, query, strength, url_match};
+use crate::keepass::breach::Report as BreachReport;
 use crate::keepass::encrypted::Encrypted;
 use crate::keepass::entry::{
+    Change,
     Entry,
     EntryGroup,
+    FieldDiff,
     Group,
+    GroupPath,
+    Revision,
 };
+use crate::keepass::health::Report;
 use crate::keepass::key::SecretKey;
+use crate::keepass::otp::{Code, Otp};
+use crate::keepass::placeholder::Resolver;
+use crate::keepass::query::SearchQuery;
+use crate::keepass::strength::Strength;
+
+pub(crate) const TITLE: &str = "Title";
+pub(crate) const USERNAME: &str = "UserName";
+pub(crate) const PASSWORD: &str = "Password";
+pub(crate) const URL: &str = "URL";
+pub(crate) const NOTES: &str = "Notes";
+pub(crate) const STANDARD_FIELDS: [&str; 5] = [TITLE, USERNAME, PASSWORD, URL, NOTES];
+// not a field, used to report tag changes between revisions
+const TAGS: &str = "Tags";
+
+const RECYCLE_BIN_TITLE: &str = "Recycle Bin";
+const RECYCLE_BIN_ICON: usize = 43;
+
+// reading, merging and writing again can race with yet another modification
+const MAX_SAVE_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct NotFoundError(pub &'static str);
+
+impl Display for NotFoundError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} not found", self.0)
+    }
+}
+
+impl Error for NotFoundError {}
 
 #[derive(Deserialize)]
 pub struct Id {
@@ -35,6 +79,8 @@ pub struct Id {
 pub struct Protected {
     pub entry_id: Uuid,
     pub name: String,
+    // history revision of the entry, None for the current state
+    pub revision: Option<usize>,
 }
 
 #[derive(Deserialize)]
@@ -43,14 +89,136 @@ pub struct File {
     pub filename: String,
 }
 
+// Revisions are numbered like the entry history, 0 being the most recent one
+#[derive(Deserialize)]
+pub struct RevisionId {
+    pub id: Uuid,
+    pub revision: usize,
+}
+
+// None compares against the current state of the entry
+#[derive(Deserialize)]
+pub struct RevisionDiff {
+    pub id: Uuid,
+    pub from: Option<usize>,
+    pub to: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct Within {
+    // None lists expired entries only
+    #[serde(default, with = "humantime_serde")]
+    pub within: Option<Duration>,
+}
+
+// Titles are sorted ascending, dates newest first and relevance best match first
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    Title,
+    Modified,
+    Created,
+    Relevance,
+}
+
 #[derive(Deserialize)]
 pub struct SearchTerm {
     pub term: String,
+    #[serde(default)]
+    pub include_recycled: bool,
+    // restrict the search to the subtree of this group
+    pub group: Option<Uuid>,
+    // defaults to relevance
+    pub sort: Option<Sort>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct UrlLookup {
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+pub struct GroupEntries {
+    pub id: Uuid,
+    // None keeps the database order
+    pub sort: Option<Sort>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+// Restores a node from the recycle bin, to the root group if no parent is given
+#[derive(Deserialize)]
+pub struct Restore {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct CustomField {
+    pub name: String,
+    // None removes the field
+    pub value: Option<String>,
+    #[serde(default)]
+    pub protected: bool,
+}
+
+// Fields left out (None) are kept as they are
+#[derive(Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct EntryFields {
+    pub title: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub url: Option<String>,
+    pub notes: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub icon: Option<usize>,
+    #[serde(default)]
+    pub custom: Vec<CustomField>,
+}
+
+#[derive(Deserialize)]
+pub struct NewEntry {
+    pub group_id: Uuid,
+    #[serde(flatten)]
+    pub fields: EntryFields,
+}
+
+#[derive(Deserialize)]
+pub struct EntryUpdate {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub fields: EntryFields,
+}
+
+#[derive(Deserialize)]
+pub struct NewGroup {
+    pub parent_id: Uuid,
+    pub title: String,
+    pub icon: Option<usize>,
+}
+
+// Fields left out (None) are kept as they are
+#[derive(Deserialize)]
+pub struct GroupUpdate {
+    pub id: Uuid,
+    pub title: Option<String>,
+    pub icon: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct Move {
+    pub id: Uuid,
+    pub parent_id: Uuid,
 }
 
 pub struct KeePass {
     config: Config,
     db: Database,
+    credentials: DbCredentials,
+    // state of the database file when it was read, to detect concurrent modifications on save
+    version: Option<DbVersion>,
 }
 
 
@@ -59,28 +227,45 @@ impl KeePass {
         // TODO: add some aad from the keepass db
         let ser_db = enc.decrypt(key, &[])?;
 
-        let db: Database = postcard::from_bytes(ser_db.expose_secret())?;
+        let (db, credentials, version): (Database, DbCredentials, Option<DbVersion>) = postcard::from_bytes(ser_db.expose_secret())?;
         Ok(
             Self {
                 config: config.clone(),
                 db,
+                credentials,
+                version,
             }
         )
     }
 
     pub fn to_enc(self) -> Result<(SecretKey, Encrypted)> {
         // TODO: avoid vector realloc to make zeroize effective
-        let ser_db = postcard::to_stdvec(&self.db)?;
+        let ser_db = postcard::to_stdvec(&(&self.db, &self.credentials, &self.version))?;
         drop(self.db);
+        drop(self.credentials);
 
         // TODO: add some aad from the keepass db
         Encrypted::encrypt(ser_db, &[], self.config.db_session_timeout)
     }
 
     pub async fn from_backend(config: &Config, db_backend: &dyn DbBackend, params: &DbLogin, user_info: &UserInfo) -> Result<Self> {
-        let db_key = Self::db_key_from_params(db_backend, params, user_info).await?;
+        let credentials = DbCredentials::from(params);
+        let (db, version) = Self::read_db(db_backend, &credentials, user_info).await?;
 
-        let mut reader = db_backend.get_db_read(user_info).await?;
+        Ok(
+            KeePass {
+                config: config.clone(),
+                db,
+                credentials,
+                version,
+            }
+        )
+    }
+
+    async fn read_db(db_backend: &dyn DbBackend, credentials: &DbCredentials, user_info: &UserInfo) -> Result<(Database, Option<DbVersion>)> {
+        let db_key = Self::db_key_from_params(db_backend, credentials, user_info).await?;
+
+        let (mut reader, version) = db_backend.get_db_read(user_info).await?;
 
         // bridge sync and async by caching the whole file in memory for now
         let mut buf = vec![];
@@ -92,39 +277,60 @@ impl KeePass {
             db
         }).await??;
 
-        Ok(
-            KeePass {
-                config: config.clone(),
-                db,
+        Ok((db, version))
+    }
+
+    // write the database back and remember the new state of the file for the next save.
+    // If the database was modified in the meantime, the current state is merged in and written instead
+    pub async fn save(&mut self, db_backend: &mut dyn DbBackend, user_info: &UserInfo) -> Result<()> {
+        for _ in 0..MAX_SAVE_ATTEMPTS {
+            match self.to_backend(db_backend, user_info).await {
+                Ok(version) => {
+                    // without a new version keep the old one, the next save then conflicts and merges
+                    // instead of overwriting unconditionally
+                    if version.is_some() {
+                        self.version = version;
+                    }
+                    return Ok(());
+                }
+                Err(err) if err.downcast_ref::<ConflictError>().is_some() => {
+                    info!("database was modified concurrently, merging changes");
+
+                    let (remote, version) = Self::read_db(db_backend, &self.credentials, user_info).await?;
+                    merge::merge(&mut self.db, &remote);
+                    self.version = version;
+                }
+                Err(err) => return Err(err),
             }
-        )
+        }
+
+        Err(ConflictError.into())
     }
 
-    #[allow(dead_code)]
-    pub async fn to_backend(self, db_backend: &mut dyn DbBackend, params: &DbLogin, user_info: &UserInfo) -> Result<()> {
-        let key = Self::db_key_from_params(db_backend, params, user_info).await?;
+    pub async fn to_backend(&self, db_backend: &mut dyn DbBackend, user_info: &UserInfo) -> Result<Option<DbVersion>> {
+        let key = Self::db_key_from_params(db_backend, &self.credentials, user_info).await?;
 
+        let db = self.db.clone();
         let mut buf: Vec<u8> = vec![];
         let (result, mut buf) = tokio::task::spawn_blocking(move || {
-            (self.db.save(&mut buf, key), buf)
+            (db.save(&mut buf, key), buf)
         }).await?;
         result?;
 
-        let (mut writer, rx) = db_backend.get_db_write(user_info).await?;
+        let (mut writer, rx) = db_backend.get_db_write(user_info, self.version.as_ref()).await?;
         writer.write_all(&buf).await?;
         buf.zeroize();
 
         // close our side to signal end of data
         // otherwise we could get a deadlock awaiting the channel
         writer.shutdown().await?;
-        if let Some(rx) = rx {
-            rx.await??;
+        match rx {
+            Some(rx) => rx.await?,
+            None => Ok(None),
         }
-
-        Ok(())
     }
 
-    async fn db_key_from_params(db_backend: &dyn DbBackend, params: &DbLogin, user_info: &UserInfo) -> Result<DatabaseKey> {
+    async fn db_key_from_params(db_backend: &dyn DbBackend, params: &DbCredentials, user_info: &UserInfo) -> Result<DatabaseKey> {
         let mut db_key = DatabaseKey::new();
         let mut temp1;
         let mut temp2;
@@ -162,53 +368,282 @@ impl KeePass {
 
         Ok(
             (
-                Self::find_all_groups(&self.db.root),
+                Self::find_all_groups(&self.db.root, self.recycle_bin().map(|bin| bin.uuid).as_ref()),
                 last_selected,
             )
         )
     }
 
-    pub fn get_group_entries(&self, params: &Query<Id>) -> Result<EntryGroup> {
+    pub fn get_group_entries(&self, params: &Query<GroupEntries>) -> Result<EntryGroup> {
         let group = Self::find_group_by_id(&self.db.root, &params.id).ok_or(anyhow!("group not found"))?;
 
         let mut entries = Vec::with_capacity(group.children.len());
         for node in &group.children {
             if let Node::Entry(entry) = node {
-                entries.push(
-                    // Populate (potentially) visible fields only
-                    Entry {
-                        id: entry.uuid,
-                        title: entry.get_title().map(String::from),
-                        username: entry.get_username().map(String::from),
-                        notes: None,
-                        strings: None,
-                        binary: None,
-                        protected: None,
-                        tags: None,
-                        icon: entry.icon_id,
-                        custom_icon_uuid: entry.custom_icon_uuid,
-                        url: entry.get_url().map(String::from),
-                    }
-                )
+                entries.push(Self::entry_summary(entry))
             }
         }
+        // no query to rank by, relevance keeps the database order
+        Self::sort_entries(&mut entries, params.sort, |_| 0);
+        let total = entries.len();
 
         Ok(EntryGroup {
             title: group.name.clone(),
-            entries,
+            entries: Self::paginate(entries, params.offset, params.limit),
             icon: group.icon_id,
             custom_icon_uuid: group.custom_icon_uuid,
+            total,
         })
     }
 
+    // Entries to fill in on the site, best matches first
+    pub fn entries_for_url(&self, params: &Query<UrlLookup>) -> Result<Vec<Entry>> {
+        let entries = url_match::find_entries(&self.db.root, &params.url, self.config.url_match.strictness, self.db.meta.recyclebin_uuid)?;
+
+        Ok(entries.into_iter().map(Self::entry_summary).collect())
+    }
+
+    fn sort_entries(entries: &mut [Entry], sort: Option<Sort>, relevance: impl Fn(&Entry) -> u32) {
+        // sorts are stable, ties keep the database order
+        match sort {
+            None => {}
+            Some(Sort::Title) => entries.sort_by_cached_key(|entry| entry.title.as_deref().unwrap_or_default().to_lowercase()),
+            Some(Sort::Modified) => entries.sort_by_key(|entry| std::cmp::Reverse(entry.times.last_modification)),
+            Some(Sort::Created) => entries.sort_by_key(|entry| std::cmp::Reverse(entry.times.creation)),
+            Some(Sort::Relevance) => entries.sort_by_cached_key(|entry| std::cmp::Reverse(relevance(entry))),
+        }
+    }
+
+    fn paginate(entries: Vec<Entry>, offset: Option<usize>, limit: Option<usize>) -> Vec<Entry> {
+        entries.into_iter()
+            .skip(offset.unwrap_or_default())
+            .take(limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    pub fn expiring_entries(&self, params: &Query<Within>) -> Result<Vec<GroupPath>> {
+        let until = Times::now() + chrono::Duration::from_std(params.within.unwrap_or_default())?;
+
+        let mut groups = vec![];
+        Self::find_expiring_entries(&self.db.root, &self.db.root.name, &until, self.db.meta.recyclebin_uuid.as_ref(), &mut groups);
+        groups.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(groups)
+    }
+
+    fn find_expiring_entries(group: &keepass::db::Group, path: &str, until: &NaiveDateTime, recycle_bin: Option<&Uuid>, groups: &mut Vec<GroupPath>) {
+        let mut entries = vec![];
+        for node in &group.children {
+            match node {
+                // deleted entries don't need rotation
+                Node::Group(child) if Some(&child.uuid) == recycle_bin => {}
+                Node::Group(child) => {
+                    let path = format!("{}/{}", path, child.name);
+                    Self::find_expiring_entries(child, &path, until, recycle_bin, groups);
+                }
+                Node::Entry(entry) => {
+                    if entry.times.expires && entry.times.get_expiry().is_some_and(|expiry| expiry <= until) {
+                        entries.push(Self::entry_summary(entry));
+                    }
+                }
+            }
+        }
+
+        if !entries.is_empty() {
+            groups.push(GroupPath {
+                id: group.uuid,
+                path: path.to_string(),
+                entries,
+            });
+        }
+    }
+
+    // rating every password is expensive, keep it off the async workers
+    pub async fn health_report(self) -> Result<Report> {
+        tokio::task::spawn_blocking(move || health::report(&self.db, &self.config.health)).await?
+    }
+
+    pub async fn breach_report(&self) -> Result<BreachReport> {
+        let hashes = breach::collect(&self.db);
+        let config = self.config.breach.clone();
+
+        tokio::task::spawn_blocking(move || breach::report(&config, hashes)).await?
+    }
+
     pub fn get_entry(&self, params: &Query<Id>) -> Result<Entry> {
         let entry = Self::find_entry_by_id(&self.db.root, &params.id).ok_or(anyhow!("entry not found"))?;
 
-        Ok(entry.into())
+        Ok(self.entry_details(entry))
+    }
+
+    pub fn get_entry_history(&self, params: &Query<Id>) -> Result<Vec<Revision>> {
+        let entry = Self::find_entry_by_id(&self.db.root, &params.id).ok_or(NotFoundError("entry"))?;
+
+        let revisions = match &entry.history {
+            Some(history) => history.get_entries().iter()
+                .enumerate()
+                .map(|(revision, entry)| Revision {
+                    revision,
+                    title: entry.get_title().map(String::from),
+                    username: entry.get_username().map(String::from),
+                    last_modification: entry.times.get_last_modification().copied(),
+                })
+                .collect(),
+            None => vec![],
+        };
+
+        Ok(revisions)
+    }
+
+    pub fn get_entry_revision(&self, params: &Query<RevisionId>) -> Result<Entry> {
+        let entry = Self::find_entry_by_id(&self.db.root, &params.id).ok_or(NotFoundError("entry"))?;
+        let revision = Self::revision(entry, Some(params.revision))?;
+
+        Ok(self.entry_details(revision))
+    }
+
+    pub fn diff_entry_revisions(&self, params: &Query<RevisionDiff>) -> Result<Vec<FieldDiff>> {
+        let entry = Self::find_entry_by_id(&self.db.root, &params.id).ok_or(NotFoundError("entry"))?;
+        let from = Self::revision(entry, params.from)?;
+        let to = Self::revision(entry, params.to)?;
+
+        let mut names: Vec<&String> = from.fields.keys().chain(to.fields.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        let mut diff = vec![];
+        for name in names {
+            let (old, new) = (from.fields.get(name), to.fields.get(name));
+            if old == new {
+                continue;
+            }
+
+            // protected values are only reported as changed, fetch them through get_protected
+            let protected = matches!(old, Some(Value::Protected(_))) || matches!(new, Some(Value::Protected(_)));
+            let value = |v: Option<&Value>| match v {
+                Some(Value::Unprotected(v)) if !protected => Some(v.clone()),
+                _ => None,
+            };
+
+            diff.push(FieldDiff {
+                name: name.clone(),
+                change: match (old, new) {
+                    (None, _) => Change::Added,
+                    (_, None) => Change::Removed,
+                    _ => Change::Modified,
+                },
+                protected,
+                old: value(old),
+                new: value(new),
+            });
+        }
+
+        if from.tags != to.tags {
+            diff.push(FieldDiff {
+                name: TAGS.to_string(),
+                change: Change::Modified,
+                protected: false,
+                old: Some(from.tags.join(";")),
+                new: Some(to.tags.join(";")),
+            });
+        }
+
+        Ok(diff)
+    }
+
+    // The current state goes into the history first, so restoring can be undone
+    pub fn restore_entry_revision(&mut self, params: &RevisionId) -> Result<()> {
+        let entry = Self::find_entry_by_id_mut(&mut self.db.root, &params.id).ok_or(NotFoundError("entry"))?;
+        let mut restored = Self::revision(entry, Some(params.revision))?.clone();
+
+        let mut previous = entry.clone();
+        previous.history = None;
+
+        restored.uuid = entry.uuid;
+        restored.history = entry.history.take();
+        restored.history.get_or_insert_with(Default::default).add_entry(previous);
+        if let Some(time) = entry.times.get_location_changed() {
+            restored.times.set_location_changed(*time);
+        }
+        restored.times.set_last_modification(Times::now());
+
+        *entry = restored;
+
+        Ok(())
+    }
+
+    fn revision(entry: &keepass::db::Entry, revision: Option<usize>) -> Result<&keepass::db::Entry> {
+        match revision {
+            Some(revision) => entry.history.as_ref()
+                .and_then(|history| history.get_entries().get(revision))
+                .ok_or(NotFoundError("revision").into()),
+            None => Ok(entry),
+        }
+    }
+
+    // Populate (potentially) visible fields only
+    fn entry_summary(entry: &keepass::db::Entry) -> Entry {
+        Entry {
+            id: entry.uuid,
+            title: entry.get_title().map(String::from),
+            username: entry.get_username().map(String::from),
+            notes: None,
+            strings: None,
+            binary: None,
+            protected: None,
+            tags: None,
+            icon: entry.icon_id,
+            custom_icon_uuid: entry.custom_icon_uuid,
+            url: entry.get_url().map(String::from),
+            resolved: None,
+            otp: otp::has_otp(entry),
+            times: (&entry.times).into(),
+            search: None,
+        }
     }
 
-    pub fn get_protected(&self, params: &Query<Protected>) -> Result<SecretString> {
+    fn entry_details(&self, entry: &keepass::db::Entry) -> Entry {
+        let mut files = HashMap::new();
+        for (name, value) in &entry.fields {
+            if let Value::Bytes(b) = value {
+                match attachment::resolve(&self.db, b) {
+                    Ok(v) => {
+                        files.insert(name.clone(), v.len());
+                    }
+                    Err(err) => warn!("failed to resolve file '{}' of entry '{}': {}", name, entry.uuid, err),
+                }
+            }
+        }
+
+        // protected values are only handed out through get_protected,
+        // so don't resolve anything that would pull them into the plain listing
+        let mut resolved = HashMap::new();
+        for (name, value) in &entry.fields {
+            if !matches!(value, Value::Unprotected(v) if v.contains('{')) {
+                continue;
+            }
+
+            let mut resolver = Resolver::new(&self.db.root);
+            match resolver.resolve_field(entry, name) {
+                Ok(Some(v)) if !resolver.protected => {
+                    resolved.insert(name.clone(), v);
+                }
+                Ok(_) => {}
+                Err(err) => warn!("failed to resolve field '{}' of entry '{}': {}", name, entry.uuid, err),
+            }
+        }
+
+        let mut entry: Entry = entry.into();
+        entry.binary = Some(files);
+        entry.resolved = Some(resolved);
+
+        entry
+    }
+
+    // Returns the raw value and, if it contains placeholders or references, the resolved one
+    pub fn get_protected(&self, params: &Query<Protected>) -> Result<(SecretString, Option<SecretString>)> {
         let entry = Self::find_entry_by_id(&self.db.root, &params.entry_id).ok_or(anyhow!("entry not found"))?;
+        let entry = Self::revision(entry, params.revision)?;
 
         let field = match params.name.as_str() {
             "password" => entry.fields.get("Password").cloned(),
@@ -222,34 +657,389 @@ impl KeePass {
             },
             None => bail!("field not found"),
         };
+        let value = String::from_utf8_lossy(protected.unsecure()).to_string();
+
+        let mut resolved = None;
+        if value.contains('{') {
+            let name = match params.name.as_str() {
+                "password" => PASSWORD,
+                k => k,
+            };
+            // the raw value is still handed out if resolving fails
+            match Resolver::new(&self.db.root).resolve_field(entry, name) {
+                Ok(v) => resolved = v.filter(|v| v != &value).map(SecretString::new),
+                Err(err) => warn!("failed to resolve field '{}' of entry '{}': {}", name, entry.uuid, err),
+            }
+        }
 
         Ok(
-            SecretString::new(
-                String::from_utf8_lossy(protected.unsecure()).to_string()
+            (
+                SecretString::new(value),
+                resolved,
             )
         )
     }
 
+    // Runs on the decrypted value, resolving references first, but only hands out the estimate
+    pub fn get_password_strength(&self, params: &Query<Protected>) -> Result<Strength> {
+        let (value, resolved) = self.get_protected(params)?;
+
+        let entry = Self::find_entry_by_id(&self.db.root, &params.entry_id).ok_or(anyhow!("entry not found"))?;
+        let entry = Self::revision(entry, params.revision)?;
+        let user_inputs: Vec<&str> = [entry.get_title(), entry.get_username(), entry.get_url()].into_iter().flatten().collect();
+
+        strength::estimate(resolved.as_ref().unwrap_or(&value).expose_secret(), &user_inputs)
+    }
+
+    // Number of times the value was seen in breaches, 0 if not at all
+    pub async fn get_breach_count(&self, params: &Query<Protected>) -> Result<u64> {
+        let (value, resolved) = self.get_protected(params)?;
+        let value = resolved.as_ref().unwrap_or(&value).expose_secret();
+        if value.is_empty() {
+            bail!("field is empty");
+        }
+
+        let hash = breach::hash(value);
+        let config = self.config.breach.clone();
+        let counts = tokio::task::spawn_blocking(move || breach::count(&config, &[hash])).await??;
+
+        counts.into_iter().next().flatten().ok_or(anyhow!("password could not be checked, range file is missing"))
+    }
+
+    // Returns the current code and whether the database was modified, as HOTP moves the counter forward
+    pub fn get_totp(&mut self, params: &Id) -> Result<(Code, bool)> {
+        let entry = Self::find_entry_by_id_mut(&mut self.db.root, &params.id).ok_or(NotFoundError("entry"))?;
+
+        let otp = Otp::from_entry(entry)?;
+        let code = otp.generate()?;
+        let modified = otp.increment_counter(entry)?;
+        if modified {
+            entry.times.set_last_modification(Times::now());
+        }
+
+        Ok((code, modified))
+    }
+
     pub fn get_file(&self, params: &Query<File>) -> Result<Vec<u8>> {
-        let _entry = Self::find_entry_by_id(&self.db.root, &params.entry_id).ok_or(anyhow!("entry not found"))?;
+        let entry = Self::find_entry_by_id(&self.db.root, &params.entry_id).ok_or(NotFoundError("entry"))?;
+
+        let value = match entry.fields.get(&params.filename) {
+            Some(Value::Bytes(b)) => b,
+            _ => return Err(NotFoundError("file").into()),
+        };
 
-        todo!()
+        Ok(
+            attachment::resolve(&self.db, value)?.into_owned()
+        )
     }
 
-    pub fn search_entries(&self, params: &Query<SearchTerm>) -> Result<EntryGroup> {
-        let mut term = params.term.clone();
-        if !self.config.search.allow_regex {
-            term = regex::escape(&params.term);
+    pub fn create_entry(&mut self, params: &NewEntry) -> Result<Uuid> {
+        let group = Self::find_group_by_id_mut(&mut self.db.root, &params.group_id).ok_or(NotFoundError("group"))?;
+
+        let mut entry = keepass::db::Entry::new();
+        Self::apply_fields(&mut entry, &params.fields)?;
+
+        let id = entry.uuid;
+        group.children.push(Node::Entry(entry));
+
+        Ok(id)
+    }
+
+    pub fn update_entry(&mut self, params: &EntryUpdate) -> Result<()> {
+        let entry = Self::find_entry_by_id_mut(&mut self.db.root, &params.id).ok_or(NotFoundError("entry"))?;
+
+        // keep the previous state, like the desktop clients do
+        let mut previous = entry.clone();
+        previous.history = None;
+
+        Self::apply_fields(entry, &params.fields)?;
+
+        entry.history.get_or_insert_with(Default::default).add_entry(previous);
+        entry.times.set_last_modification(Times::now());
+
+        Ok(())
+    }
+
+    pub fn delete_entry(&mut self, params: &Id) -> Result<()> {
+        Self::find_entry_by_id(&self.db.root, &params.id).ok_or(NotFoundError("entry"))?;
+
+        self.delete_node(&params.id)
+    }
+
+    pub fn create_group(&mut self, params: &NewGroup) -> Result<Uuid> {
+        if params.title.is_empty() {
+            bail!("group title cannot be empty");
+        }
+
+        let parent = Self::find_group_by_id_mut(&mut self.db.root, &params.parent_id).ok_or(NotFoundError("parent group"))?;
+
+        let mut group = keepass::db::Group::new(&params.title);
+        group.icon_id = params.icon;
+
+        let id = group.uuid;
+        parent.children.push(Node::Group(group));
+
+        Ok(id)
+    }
+
+    pub fn update_group(&mut self, params: &GroupUpdate) -> Result<()> {
+        let group = Self::find_group_by_id_mut(&mut self.db.root, &params.id).ok_or(NotFoundError("group"))?;
+
+        if let Some(title) = &params.title {
+            if title.is_empty() {
+                bail!("group title cannot be empty");
+            }
+            group.name = title.clone();
+        }
+
+        if let Some(icon) = params.icon {
+            group.icon_id = Some(icon);
+            group.custom_icon_uuid = None;
+        }
+
+        group.times.set_last_modification(Times::now());
+
+        Ok(())
+    }
+
+    pub fn move_node(&mut self, params: &Move) -> Result<()> {
+        if params.id == self.db.root.uuid {
+            bail!("cannot move root group");
+        }
+        if let Some(group) = Self::find_group_by_id(&self.db.root, &params.id) {
+            if Self::find_group_by_id(group, &params.parent_id).is_some() {
+                bail!("cannot move group into itself or one of its subgroups");
+            }
         }
-        let rgx = Regex::new(&format!("(?i){}", term))?;
-        let entries = Self::find_entries_by_string(&self.db.root, &rgx, &self.config.search);
+        Self::find_group_by_id(&self.db.root, &params.parent_id).ok_or(NotFoundError("parent group"))?;
+
+        let mut node = Self::take_node(&mut self.db.root, &params.id).ok_or(NotFoundError("node"))?;
+        Self::node_times_mut(&mut node).set_location_changed(Times::now());
+
+        // parent can't be part of the moved node, checked above
+        Self::find_group_by_id_mut(&mut self.db.root, &params.parent_id)
+            .ok_or(NotFoundError("parent group"))?
+            .children.push(node);
+
+        Ok(())
+    }
+
+    pub fn delete_group(&mut self, params: &Id) -> Result<()> {
+        if params.id == self.db.root.uuid {
+            bail!("cannot delete root group");
+        }
+        let group = Self::find_group_by_id(&self.db.root, &params.id).ok_or(NotFoundError("group"))?;
+        // the bin would end up recycled into a new bin of its own, or be purged with everything in it
+        if let Some(bin_id) = self.db.meta.recyclebin_uuid.filter(|v| v != &params.id) {
+            if Self::find_group_by_id(group, &bin_id).is_some() {
+                bail!("cannot delete a group containing the recycle bin");
+            }
+        }
+
+        self.delete_node(&params.id)
+    }
+
+    // Moves the node to the recycle bin if enabled, nodes already in the bin are removed permanently
+    fn delete_node(&mut self, id: &Uuid) -> Result<()> {
+        let recycle = self.db.meta.recyclebin_enabled == Some(true) && !self.in_recycle_bin(id);
+
+        let mut node = Self::take_node(&mut self.db.root, id).ok_or(NotFoundError("node"))?;
+
+        if recycle {
+            Self::node_times_mut(&mut node).set_location_changed(Times::now());
+            self.recycle_bin_mut()?.children.push(node);
+            return Ok(());
+        }
+
+        let deletion_time = Times::now();
+        let mut ids = vec![];
+        Self::collect_ids(&node, &mut ids);
+        for uuid in ids {
+            self.db.deleted_objects.objects.push(DeletedObject {
+                uuid,
+                deletion_time,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn restore_node(&mut self, params: &Restore) -> Result<()> {
+        let bin = self.recycle_bin().map(|bin| bin.uuid).ok_or(NotFoundError("recycle bin"))?;
+        if params.id == bin || !self.in_recycle_bin(&params.id) {
+            bail!("node is not in the recycle bin");
+        }
+
+        let parent_id = params.parent_id.unwrap_or(self.db.root.uuid);
+        if self.in_recycle_bin(&parent_id) {
+            bail!("cannot restore into the recycle bin");
+        }
+
+        self.move_node(&Move {
+            id: params.id,
+            parent_id,
+        })
+    }
+
+    // Permanently deletes everything in the recycle bin, the bin itself is kept
+    pub fn empty_recycle_bin(&mut self) -> Result<()> {
+        let bin_id = self.recycle_bin().map(|bin| bin.uuid).ok_or(NotFoundError("recycle bin"))?;
+        let bin = Self::find_group_by_id_mut(&mut self.db.root, &bin_id).ok_or(NotFoundError("recycle bin"))?;
+
+        let mut ids = vec![];
+        for node in &bin.children {
+            Self::collect_ids(node, &mut ids);
+        }
+        bin.children.clear();
+        bin.times.set_last_modification(Times::now());
+
+        let deletion_time = Times::now();
+        for uuid in ids {
+            self.db.deleted_objects.objects.push(DeletedObject {
+                uuid,
+                deletion_time,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn recycle_bin(&self) -> Option<&keepass::db::Group> {
+        self.db.meta.recyclebin_uuid.and_then(|bin_id| Self::find_group_by_id(&self.db.root, &bin_id))
+    }
+
+    fn in_recycle_bin(&self, id: &Uuid) -> bool {
+        let bin = match self.recycle_bin() {
+            Some(v) => v,
+            None => return false,
+        };
+
+        &bin.uuid == id || Self::find_group_by_id(bin, id).is_some() || Self::find_entry_by_id(bin, id).is_some()
+    }
+
+    fn recycle_bin_mut(&mut self) -> Result<&mut keepass::db::Group> {
+        let bin_id = match self.db.meta.recyclebin_uuid.filter(|v| Self::find_group_by_id(&self.db.root, v).is_some()) {
+            Some(v) => v,
+            None => {
+                let mut bin = keepass::db::Group::new(RECYCLE_BIN_TITLE);
+                bin.icon_id = Some(RECYCLE_BIN_ICON);
+                let bin_id = bin.uuid;
+
+                self.db.meta.recyclebin_uuid = Some(bin_id);
+                self.db.meta.recyclebin_changed = Some(Times::now());
+                self.db.root.children.push(Node::Group(bin));
+
+                bin_id
+            }
+        };
+
+        Self::find_group_by_id_mut(&mut self.db.root, &bin_id).ok_or(anyhow!("recycle bin not found"))
+    }
+
+    fn node_times_mut(node: &mut Node) -> &mut Times {
+        match node {
+            Node::Group(group) => &mut group.times,
+            Node::Entry(entry) => &mut entry.times,
+        }
+    }
+
+    fn collect_ids(node: &Node, ids: &mut Vec<Uuid>) {
+        match node {
+            Node::Group(group) => {
+                ids.push(group.uuid);
+                for child in &group.children {
+                    Self::collect_ids(child, ids);
+                }
+            }
+            Node::Entry(entry) => ids.push(entry.uuid),
+        }
+    }
+
+    fn apply_fields(entry: &mut keepass::db::Entry, fields: &EntryFields) -> Result<()> {
+        let standard = [
+            (TITLE, &fields.title),
+            (USERNAME, &fields.username),
+            (PASSWORD, &fields.password),
+            (URL, &fields.url),
+            (NOTES, &fields.notes),
+        ];
+        // protection is only ever added here, a field protected in another client stays protected
+        let protected = |entry: &keepass::db::Entry, name: &str| matches!(entry.fields.get(name), Some(Value::Protected(_)));
+
+        for (name, value) in standard {
+            if let Some(v) = value {
+                let protect = name == PASSWORD || protected(entry, name);
+                entry.fields.insert(name.to_string(), Self::new_value(v, protect));
+            }
+        }
+
+        for field in &fields.custom {
+            if field.name.is_empty() {
+                bail!("field name cannot be empty");
+            }
+            if STANDARD_FIELDS.contains(&field.name.as_str()) {
+                bail!("'{}' is a standard field", field.name);
+            }
+            if let Some(Value::Bytes(_)) = entry.fields.get(&field.name) {
+                bail!("'{}' is a file", field.name);
+            }
+
+            match &field.value {
+                Some(v) => {
+                    let protect = field.protected || protected(entry, &field.name);
+                    entry.fields.insert(field.name.clone(), Self::new_value(v, protect));
+                }
+                None => {
+                    entry.fields.remove(&field.name);
+                }
+            }
+        }
+
+        if let Some(tags) = &fields.tags {
+            entry.tags = tags.clone();
+        }
+
+        if let Some(icon) = fields.icon {
+            entry.icon_id = Some(icon);
+            entry.custom_icon_uuid = None;
+        }
+
+        Ok(())
+    }
+
+    fn new_value(value: &str, protected: bool) -> Value {
+        match protected {
+            true => Value::Protected(SecStr::new(value.as_bytes().to_vec())),
+            false => Value::Unprotected(value.to_string()),
+        }
+    }
+
+    pub fn search_entries(&self, params: &Query<SearchTerm>) -> Result<EntryGroup> {
+        let query = query::parse(&params.term, self.config.search.allow_regex)?;
+        let (group, path) = match params.group {
+            Some(id) => {
+                let group = Self::find_group_by_id(&self.db.root, &id).ok_or(NotFoundError("group"))?;
+                (group, Self::group_path(&self.db.root, &id, &self.db.root.name).unwrap_or_default())
+            }
+            None => (&self.db.root, self.db.root.name.clone()),
+        };
+        let skip = match params.include_recycled {
+            true => None,
+            // searching inside the recycle bin itself
+            false => self.recycle_bin().map(|bin| bin.uuid).filter(|bin| bin != &group.uuid),
+        };
+
+        let mut entries = Self::find_entries_by_query(group, &path, &query, &self.config.search, skip.as_ref(), Times::now());
+        Self::sort_entries(&mut entries, Some(params.sort.unwrap_or(Sort::Relevance)), |entry| entry.search.as_ref().map(|v| v.score).unwrap_or_default());
+        let total = entries.len();
 
         Ok(EntryGroup {
             title: format!("Search results for '{}'", params.term),
-            entries,
+            entries: Self::paginate(entries, params.offset, params.limit),
             // search icon
             icon: Some(40),
             custom_icon_uuid: None,
+            total,
         })
     }
 
@@ -264,11 +1054,11 @@ impl KeePass {
         bail!("icon not found")
     }
 
-    pub(crate) fn find_all_groups(group: &keepass::db::Group) -> Group {
+    pub(crate) fn find_all_groups(group: &keepass::db::Group, recycle_bin: Option<&Uuid>) -> Group {
         let mut children: Vec<Group> = Vec::with_capacity(group.children.len());
         for node in &group.children {
             if let Node::Group(group) = node {
-                children.push(Self::find_all_groups(group));
+                children.push(Self::find_all_groups(group, recycle_bin));
             }
         }
         Group {
@@ -278,6 +1068,8 @@ impl KeePass {
             custom_icon_uuid: None,
             children,
             expanded: group.is_expanded,
+            times: (&group.times).into(),
+            recycle_bin: recycle_bin == Some(&group.uuid),
         }
     }
 
@@ -297,6 +1089,39 @@ impl KeePass {
         None
     }
 
+    // Path of the group as used by searches, e.g. `Root/Infra/Servers`
+    fn group_path(group: &keepass::db::Group, id: &Uuid, path: &str) -> Option<String> {
+        if &group.uuid == id {
+            return Some(path.to_string());
+        }
+        for node in &group.children {
+            if let Node::Group(group) = node {
+                let found = Self::group_path(group, id, &format!("{}/{}", path, group.name));
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+
+        None
+    }
+
+    pub(crate) fn find_group_by_id_mut<'a>(group: &'a mut keepass::db::Group, id: &Uuid) -> Option<&'a mut keepass::db::Group> {
+        if &group.uuid == id {
+            return Some(group);
+        }
+        for node in &mut group.children {
+            if let Node::Group(group) = node {
+                let found = Self::find_group_by_id_mut(group, id);
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+
+        None
+    }
+
     pub(crate) fn find_entry_by_id<'a>(group: &'a keepass::db::Group, id: &Uuid) -> Option<&'a keepass::db::Entry> {
         for node in &group.children {
             match node {
@@ -317,17 +1142,61 @@ impl KeePass {
         None
     }
 
-    pub(crate) fn find_entries_by_string(group: &keepass::db::Group, term: &Regex, config: &Search) -> Vec<Entry> {
+    pub(crate) fn find_entry_by_id_mut<'a>(group: &'a mut keepass::db::Group, id: &Uuid) -> Option<&'a mut keepass::db::Entry> {
+        for node in &mut group.children {
+            match node {
+                Node::Group(group) => {
+                    let found = Self::find_entry_by_id_mut(group, id);
+                    if found.is_some() {
+                        return found;
+                    }
+                }
+                Node::Entry(entry) => {
+                    if &entry.uuid == id {
+                        return Some(entry);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    pub(crate) fn take_node(group: &mut keepass::db::Group, id: &Uuid) -> Option<Node> {
+        let pos = group.children.iter().position(|node| match node {
+            Node::Group(group) => &group.uuid == id,
+            Node::Entry(entry) => &entry.uuid == id,
+        });
+        if let Some(pos) = pos {
+            return Some(group.children.remove(pos));
+        }
+
+        for node in &mut group.children {
+            if let Node::Group(group) = node {
+                let found = Self::take_node(group, id);
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+
+        None
+    }
+
+    pub(crate) fn find_entries_by_query(group: &keepass::db::Group, path: &str, query: &SearchQuery, config: &Search, skip: Option<&Uuid>, now: NaiveDateTime) -> Vec<Entry> {
         let mut entries = vec![];
 
         for node in &group.children {
             match node {
+                Node::Group(group) if Some(&group.uuid) == skip => {}
                 Node::Group(group) => {
-                    entries.append(&mut Self::find_entries_by_string(group, term, config));
+                    let path = format!("{}/{}", path, group.name);
+                    entries.append(&mut Self::find_entries_by_query(group, &path, query, config, skip, now));
                 }
                 Node::Entry(entry) => {
-                    let entry: Entry = entry.into();
-                    if entry.matches_regex(term, config) {
+                    let mut entry: Entry = entry.into();
+                    entry.search = query.evaluate(&entry, path, config, now);
+                    if entry.search.is_some() {
                         entries.push(entry);
                     }
                 }
@@ -348,6 +1217,23 @@ mod tests {
 
     use super::*;
 
+    async fn open_test_db() -> KeePass {
+        let params = DbLogin {
+            password: Some("test".to_string()),
+            key: None,
+        };
+        let config = Config {
+            db_backend: DbBackend::Test,
+            ..Default::default()
+        };
+
+        let mut db_backend = db_backend::new(&config);
+        let test_backend: &mut Test = db_backend.as_any().downcast_mut().unwrap();
+        test_backend.buf.extend_from_slice(&fs::read("tests/test.kdbx").await.unwrap());
+
+        KeePass::from_backend(&config, test_backend, &params, &UserInfo::default()).await.unwrap()
+    }
+
     #[tokio::test]
     async fn database_roundtrip() {
         let params = DbLogin {
@@ -377,8 +1263,393 @@ mod tests {
         assert_eq!(keepass.db, dec.db);
 
         test_backend.buf = Vec::new();
-        keepass.to_backend(test_backend, &params, &user_info).await.unwrap();
+        keepass.to_backend(test_backend, &user_info).await.unwrap();
 
         // TODO: compare KeePass::to_backend result
     }
+
+    #[tokio::test]
+    async fn entry_modification() {
+        let mut keepass = open_test_db().await;
+        let group_id = keepass.db.root.uuid;
+
+        let id = keepass.create_entry(&NewEntry {
+            group_id,
+            fields: EntryFields {
+                title: Some("new entry".to_string()),
+                username: None,
+                password: Some("secret".to_string()),
+                url: None,
+                notes: None,
+                tags: None,
+                icon: None,
+                custom: vec![CustomField {
+                    name: "pin".to_string(),
+                    value: Some("1234".to_string()),
+                    protected: true,
+                }],
+            },
+        }).unwrap();
+
+        let entry = KeePass::find_entry_by_id(&keepass.db.root, &id).unwrap();
+        assert_eq!(entry.get_title(), Some("new entry"));
+        assert!(matches!(entry.fields.get("Password"), Some(Value::Protected(_))));
+        assert!(matches!(entry.fields.get("pin"), Some(Value::Protected(_))));
+
+        // protected in another client
+        let entry = KeePass::find_entry_by_id_mut(&mut keepass.db.root, &id).unwrap();
+        entry.fields.insert(USERNAME.to_string(), KeePass::new_value("user", true));
+        keepass.update_entry(&EntryUpdate {
+            id,
+            fields: EntryFields {
+                title: None,
+                username: Some("other".to_string()),
+                password: None,
+                url: None,
+                notes: None,
+                tags: None,
+                icon: None,
+                custom: vec![CustomField {
+                    name: "pin".to_string(),
+                    value: Some("4321".to_string()),
+                    protected: false,
+                }],
+            },
+        }).unwrap();
+
+        let entry = KeePass::find_entry_by_id(&keepass.db.root, &id).unwrap();
+        assert!(matches!(entry.fields.get(USERNAME), Some(Value::Protected(_))));
+        assert!(matches!(entry.fields.get("pin"), Some(Value::Protected(_))));
+
+        keepass.update_entry(&EntryUpdate {
+            id,
+            fields: EntryFields {
+                title: Some("renamed".to_string()),
+                username: None,
+                password: None,
+                url: None,
+                notes: None,
+                tags: None,
+                icon: None,
+                custom: vec![CustomField {
+                    name: "pin".to_string(),
+                    value: None,
+                    protected: false,
+                }],
+            },
+        }).unwrap();
+
+        let entry = KeePass::find_entry_by_id(&keepass.db.root, &id).unwrap();
+        assert_eq!(entry.get_title(), Some("renamed"));
+        assert!(!entry.fields.contains_key("pin"));
+        assert_eq!(entry.history.as_ref().unwrap().get_entries()[0].get_title(), Some("new entry"));
+
+        keepass.db.meta.recyclebin_enabled = Some(false);
+        keepass.delete_entry(&Id { id }).unwrap();
+        assert!(KeePass::find_entry_by_id(&keepass.db.root, &id).is_none());
+        assert!(keepass.db.deleted_objects.objects.iter().any(|o| o.uuid == id));
+    }
+
+    #[tokio::test]
+    async fn group_modification() {
+        let mut keepass = open_test_db().await;
+        let root_id = keepass.db.root.uuid;
+
+        let outer = keepass.create_group(&NewGroup { parent_id: root_id, title: "outer".to_string(), icon: None }).unwrap();
+        let inner = keepass.create_group(&NewGroup { parent_id: outer, title: "inner".to_string(), icon: None }).unwrap();
+
+        keepass.update_group(&GroupUpdate { id: inner, title: Some("renamed".to_string()), icon: Some(3) }).unwrap();
+        let group = KeePass::find_group_by_id(&keepass.db.root, &inner).unwrap();
+        assert_eq!(group.name, "renamed");
+        assert_eq!(group.icon_id, Some(3));
+
+        // cycles
+        assert!(keepass.move_node(&Move { id: outer, parent_id: inner }).is_err());
+        assert!(keepass.move_node(&Move { id: outer, parent_id: outer }).is_err());
+
+        keepass.move_node(&Move { id: inner, parent_id: root_id }).unwrap();
+        assert!(KeePass::find_group_by_id(KeePass::find_group_by_id(&keepass.db.root, &outer).unwrap(), &inner).is_none());
+
+        // first deletion goes to the recycle bin, second one is permanent
+        keepass.db.meta.recyclebin_enabled = Some(true);
+        keepass.delete_group(&Id { id: outer }).unwrap();
+        let bin_id = keepass.db.meta.recyclebin_uuid.unwrap();
+        assert!(KeePass::find_group_by_id(KeePass::find_group_by_id(&keepass.db.root, &bin_id).unwrap(), &outer).is_some());
+
+        keepass.delete_group(&Id { id: outer }).unwrap();
+        assert!(KeePass::find_group_by_id(&keepass.db.root, &outer).is_none());
+        assert!(keepass.db.deleted_objects.objects.iter().any(|o| o.uuid == outer));
+
+        assert!(keepass.delete_group(&Id { id: root_id }).is_err());
+
+        // the recycle bin can't be deleted along with its parent
+        let parent = keepass.create_group(&NewGroup { parent_id: root_id, title: "parent".to_string(), icon: None }).unwrap();
+        keepass.move_node(&Move { id: bin_id, parent_id: parent }).unwrap();
+        assert!(keepass.delete_group(&Id { id: parent }).is_err());
+        assert!(KeePass::find_group_by_id(&keepass.db.root, &parent).is_some());
+        assert_eq!(keepass.db.meta.recyclebin_uuid, Some(bin_id));
+    }
+
+    fn new_entry(group_id: Uuid, title: &str) -> NewEntry {
+        NewEntry {
+            group_id,
+            fields: EntryFields {
+                title: Some(title.to_string()),
+                username: None,
+                password: None,
+                url: None,
+                notes: None,
+                tags: None,
+                icon: None,
+                custom: vec![],
+            },
+        }
+    }
+
+    fn rename_entry(id: Uuid, title: &str) -> EntryUpdate {
+        EntryUpdate {
+            id,
+            fields: EntryFields {
+                title: Some(title.to_string()),
+                username: None,
+                password: None,
+                url: None,
+                notes: None,
+                tags: None,
+                icon: None,
+                custom: vec![],
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn entry_history() {
+        let mut keepass = open_test_db().await;
+        let id = keepass.create_entry(&new_entry(keepass.db.root.uuid, "first")).unwrap();
+        keepass.update_entry(&rename_entry(id, "second")).unwrap();
+        keepass.update_entry(&rename_entry(id, "third")).unwrap();
+
+        let revisions = keepass.get_entry_history(&Query(Id { id })).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].title.as_deref(), Some("second"));
+        assert_eq!(revisions[1].title.as_deref(), Some("first"));
+
+        let diff = keepass.diff_entry_revisions(&Query(RevisionDiff { id, from: Some(1), to: None })).unwrap();
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].name, "Title");
+        assert_eq!(diff[0].old.as_deref(), Some("first"));
+        assert_eq!(diff[0].new.as_deref(), Some("third"));
+
+        keepass.restore_entry_revision(&RevisionId { id, revision: 1 }).unwrap();
+        let entry = KeePass::find_entry_by_id(&keepass.db.root, &id).unwrap();
+        assert_eq!(entry.get_title(), Some("first"));
+
+        let revisions = keepass.get_entry_history(&Query(Id { id })).unwrap();
+        assert_eq!(revisions.len(), 3);
+        assert_eq!(revisions[0].title.as_deref(), Some("third"));
+    }
+
+    #[tokio::test]
+    async fn expiring_entries() {
+        let mut keepass = open_test_db().await;
+        let root = keepass.db.root.uuid;
+        let expired = keepass.create_entry(&new_entry(root, "expired")).unwrap();
+        let expiring = keepass.create_entry(&new_entry(root, "expiring")).unwrap();
+
+        for (id, days) in [(expired, -1), (expiring, 10)] {
+            let entry = KeePass::find_entry_by_id_mut(&mut keepass.db.root, &id).unwrap();
+            entry.times.expires = true;
+            entry.times.set_expiry(Times::now() + chrono::Duration::days(days));
+        }
+
+        let ids = |groups: Vec<GroupPath>| -> Vec<Uuid> {
+            groups.iter().flat_map(|g| g.entries.iter().map(|e| e.id)).collect()
+        };
+
+        let groups = keepass.expiring_entries(&Query(Within { within: None })).unwrap();
+        let group = groups.iter().find(|g| g.id == root).unwrap();
+        assert_eq!(group.path, keepass.db.root.name);
+        let found = ids(groups);
+        assert!(found.contains(&expired) && !found.contains(&expiring));
+
+        let groups = keepass.expiring_entries(&Query(Within { within: Some(Duration::from_secs(30 * 24 * 3600)) })).unwrap();
+        let found = ids(groups);
+        assert!(found.contains(&expired) && found.contains(&expiring));
+    }
+
+    #[tokio::test]
+    async fn search_pagination() {
+        let mut keepass = open_test_db().await;
+        let root = keepass.db.root.uuid;
+        let group = keepass.create_group(&NewGroup { parent_id: root, title: "paged".to_string(), icon: None }).unwrap();
+        for title in ["beta item", "alpha item", "alpha"] {
+            keepass.create_entry(&new_entry(group, title)).unwrap();
+        }
+        keepass.create_entry(&new_entry(root, "alpha outside")).unwrap();
+
+        let search = |term: &str, group: Option<Uuid>, sort: Option<Sort>, offset: Option<usize>, limit: Option<usize>| {
+            let term = SearchTerm {
+                term: term.to_string(),
+                include_recycled: false,
+                group,
+                sort,
+                offset,
+                limit,
+            };
+            let result = keepass.search_entries(&Query(term))?;
+            Ok::<_, anyhow::Error>((result.entries.into_iter().filter_map(|e| e.title).collect::<Vec<String>>(), result.total))
+        };
+
+        assert_eq!(search("alpha", Some(group), None, None, None).unwrap(), (vec!["alpha".to_string(), "alpha item".to_string()], 2));
+        assert_eq!(search("item", Some(group), Some(Sort::Title), Some(1), Some(1)).unwrap(), (vec!["beta item".to_string()], 2));
+        assert_eq!(search("group:paged", None, Some(Sort::Title), None, Some(10)).unwrap().1, 3);
+        assert!(search("alpha", None, None, None, None).unwrap().0.contains(&"alpha outside".to_string()));
+        assert!(search("alpha", Some(Uuid::new_v4()), None, None, None).unwrap_err().downcast_ref::<NotFoundError>().is_some());
+
+        let entries = keepass.get_group_entries(&Query(GroupEntries { id: group, sort: Some(Sort::Title), offset: None, limit: Some(2) })).unwrap();
+        let titles: Vec<String> = entries.entries.into_iter().filter_map(|e| e.title).collect();
+        assert_eq!((titles, entries.total), (vec!["alpha".to_string(), "alpha item".to_string()], 3));
+    }
+
+    #[tokio::test]
+    async fn recycle_bin() {
+        let mut keepass = open_test_db().await;
+        keepass.db.meta.recyclebin_enabled = Some(true);
+        let root = keepass.db.root.uuid;
+        let id = keepass.create_entry(&new_entry(root, "recycled entry")).unwrap();
+        keepass.delete_entry(&Id { id }).unwrap();
+
+        let bin_id = keepass.db.meta.recyclebin_uuid.unwrap();
+        let (groups, _) = keepass.get_groups().unwrap();
+        assert!(groups.children.iter().any(|g| g.id == bin_id && g.recycle_bin));
+        assert!(!groups.recycle_bin);
+
+        let search = |keepass: &KeePass, include_recycled: bool| -> Vec<Uuid> {
+            let term = SearchTerm {
+                term: "recycled entry".to_string(),
+                include_recycled,
+                group: None,
+                sort: None,
+                offset: None,
+                limit: None,
+            };
+            keepass.search_entries(&Query(term)).unwrap().entries.iter().map(|e| e.id).collect()
+        };
+        assert!(!search(&keepass, false).contains(&id));
+        assert!(search(&keepass, true).contains(&id));
+
+        // only nodes in the bin can be restored, not into the bin
+        assert!(keepass.restore_node(&Restore { id: root, parent_id: None }).is_err());
+        assert!(keepass.restore_node(&Restore { id: bin_id, parent_id: None }).is_err());
+        assert!(keepass.restore_node(&Restore { id, parent_id: Some(bin_id) }).is_err());
+
+        keepass.restore_node(&Restore { id, parent_id: None }).unwrap();
+        assert!(keepass.db.root.children.iter().any(|n| matches!(n, Node::Entry(e) if e.uuid == id)));
+        assert!(search(&keepass, false).contains(&id));
+
+        keepass.delete_entry(&Id { id }).unwrap();
+        keepass.empty_recycle_bin().unwrap();
+        assert!(KeePass::find_entry_by_id(&keepass.db.root, &id).is_none());
+        assert!(KeePass::find_group_by_id(&keepass.db.root, &bin_id).unwrap().children.is_empty());
+        assert!(keepass.db.deleted_objects.objects.iter().any(|o| o.uuid == id));
+    }
+
+    #[tokio::test]
+    async fn health_report() {
+        let mut keepass = open_test_db().await;
+        let root = keepass.db.root.uuid;
+
+        let mut create = |title: &str, password: &str| {
+            let mut entry = new_entry(root, title);
+            entry.fields.password = Some(password.to_string());
+            keepass.create_entry(&entry).unwrap()
+        };
+        let shared = [create("shared 1", "Sh4red-Secret/Passw0rd!"), create("shared 2", "Sh4red-Secret/Passw0rd!")];
+        let weak = create("weak", "password");
+        let empty = create("empty", "");
+        let old = create("old", "x7#Lq9!vR2@mZp4$wK8&");
+        let reference = create("reference", &format!("{{REF:P@I:{}}}", shared[0].simple()));
+        let lower_reference = create("lower reference", &format!("{{ref:p@i:{}}}", shared[0].simple()));
+
+        let entry = KeePass::find_entry_by_id_mut(&mut keepass.db.root, &old).unwrap();
+        entry.times.set_last_modification(Times::now() - chrono::Duration::days(2 * 365));
+
+        let root_name = keepass.db.root.name.clone();
+        let report = keepass.health_report().await.unwrap();
+        let ids = |entries: &Vec<health::ReportEntry>| -> Vec<Uuid> { entries.iter().map(|e| e.id).collect() };
+
+        let reused = report.reused.iter().find(|entries| entries.iter().any(|e| e.id == shared[0])).unwrap();
+        assert_eq!(ids(reused).len(), 2);
+        assert!(ids(reused).contains(&shared[1]));
+        assert!(!report.reused.iter().any(|entries| ids(entries).contains(&reference) || ids(entries).contains(&lower_reference)));
+        assert!(ids(&report.weak).contains(&weak) && !ids(&report.weak).contains(&old));
+        assert!(ids(&report.empty).contains(&empty));
+        assert!(ids(&report.old).contains(&old) && !ids(&report.old).contains(&weak));
+
+        let path = &report.empty.iter().find(|e| e.id == empty).unwrap().path;
+        assert_eq!(path, &root_name);
+    }
+
+    #[tokio::test]
+    async fn broken_reference() {
+        let mut keepass = open_test_db().await;
+
+        let reference = format!("{{REF:P@I:{}}}", Uuid::new_v4().simple());
+        let mut entry = new_entry(keepass.db.root.uuid, "broken");
+        entry.fields.password = Some(reference.clone());
+        let entry_id = keepass.create_entry(&entry).unwrap();
+
+        let (value, resolved) = keepass.get_protected(&Query(Protected { entry_id, name: "password".to_string(), revision: None })).unwrap();
+        assert_eq!(value.expose_secret(), &reference);
+        assert!(resolved.is_none());
+    }
+
+    #[tokio::test]
+    async fn concurrent_modification() {
+        let params = DbLogin {
+            password: Some("test".to_string()),
+            key: None,
+        };
+        let path = std::env::temp_dir().join(format!("keepass4web-test-{}.kdbx", crate::auth::gen_token(8)));
+        fs::copy("tests/test.kdbx", &path).await.unwrap();
+
+        let mut config = Config::default();
+        config.filesystem.db_location = path.clone();
+        let mut db_backend = db_backend::new(&config);
+        let user_info = UserInfo::default();
+
+        // two sessions working on the same database
+        let mut first = KeePass::from_backend(&config, db_backend.as_ref(), &params, &user_info).await.unwrap();
+        let mut second = KeePass::from_backend(&config, db_backend.as_ref(), &params, &user_info).await.unwrap();
+        let group_id = first.db.root.uuid;
+
+        let first_id = first.create_entry(&new_entry(group_id, "first")).unwrap();
+        first.save(db_backend.as_mut(), &user_info).await.unwrap();
+
+        let second_id = second.create_entry(&new_entry(group_id, "second")).unwrap();
+        second.save(db_backend.as_mut(), &user_info).await.unwrap();
+        assert!(KeePass::find_entry_by_id(&second.db.root, &first_id).is_some());
+
+        // both rename the same entry, the later modification wins
+        first.update_entry(&rename_entry(first_id, "renamed by first")).unwrap();
+        first.save(db_backend.as_mut(), &user_info).await.unwrap();
+        second.update_entry(&rename_entry(first_id, "renamed by second")).unwrap();
+        second.save(db_backend.as_mut(), &user_info).await.unwrap();
+
+        // deletion by the first session is honored
+        first.db.meta.recyclebin_enabled = Some(false);
+        first.delete_entry(&Id { id: second_id }).unwrap();
+        first.save(db_backend.as_mut(), &user_info).await.unwrap();
+
+        let result = KeePass::from_backend(&config, db_backend.as_ref(), &params, &user_info).await.unwrap();
+        fs::remove_file(&path).await.unwrap();
+
+        assert!(KeePass::find_entry_by_id(&result.db.root, &second_id).is_none());
+
+        let entry = KeePass::find_entry_by_id(&result.db.root, &first_id).unwrap();
+        assert_eq!(entry.get_title(), Some("renamed by second"));
+        let history = entry.history.as_ref().unwrap().get_entries();
+        assert!(history.iter().any(|e| e.get_title() == Some("renamed by first")));
+        assert!(history.iter().any(|e| e.get_title() == Some("first")));
+    }
 }
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use keepass::Database;
+use keepass::db::{BinaryAttachment, DeletedObject, Entry, Group, HeaderAttachment, History, Node, Times, Value};
+use log::warn;
+use uuid::Uuid;
+
+use crate::keepass::attachment;
+use crate::keepass::keepass::KeePass;
+
+// Merges `source` into `target` the way KeePass synchronizes databases: nodes are matched by uuid,
+// the most recently modified version wins and the losing version of an entry ends up in its history.
+// Nodes deleted on either side stay deleted, unless they were modified after the deletion.
+pub(crate) fn merge(target: &mut Database, source: &Database) {
+    let deleted = deleted_objects(target, source);
+
+    let root = target.root.uuid;
+    merge_children(target, source, &source.root, root, &deleted);
+    apply_deletions(target, &deleted);
+
+    let mut objects: Vec<DeletedObject> = deleted.into_iter()
+        .map(|(uuid, deletion_time)| DeletedObject { uuid, deletion_time })
+        .collect();
+    objects.sort_by_key(|object| object.deletion_time);
+    target.deleted_objects.objects = objects;
+
+    merge_meta(target, source);
+}
+
+fn deleted_objects(target: &Database, source: &Database) -> HashMap<Uuid, NaiveDateTime> {
+    let mut deleted = HashMap::new();
+    for object in target.deleted_objects.objects.iter().chain(&source.deleted_objects.objects) {
+        let time = deleted.entry(object.uuid).or_insert(object.deletion_time);
+        if object.deletion_time > *time {
+            *time = object.deletion_time;
+        }
+    }
+
+    deleted
+}
+
+// `parent` is the group in the target the children of `group` belong to.
+// This is the nearest ancestor still existing in the target, if the group itself was deleted there.
+fn merge_children(target: &mut Database, source: &Database, group: &Group, parent: Uuid, deleted: &HashMap<Uuid, NaiveDateTime>) {
+    for node in &group.children {
+        match node {
+            Node::Group(group) => {
+                let parent = match merge_group(target, group, parent, deleted) {
+                    true => group.uuid,
+                    false => parent,
+                };
+                merge_children(target, source, group, parent, deleted);
+            }
+            Node::Entry(entry) => merge_entry(target, source, entry, parent, deleted),
+        }
+    }
+}
+
+// Returns whether the group exists in the target after merging
+fn merge_group(target: &mut Database, group: &Group, parent: Uuid, deleted: &HashMap<Uuid, NaiveDateTime>) -> bool {
+    if KeePass::find_group_by_id(&target.root, &group.uuid).is_none() {
+        if is_deleted(deleted, &group.uuid, &group.times) {
+            return false;
+        }
+
+        let mut new = group.clone();
+        new.children = vec![];
+        insert_node(target, parent, Node::Group(new));
+        return true;
+    }
+
+    relocate(target, &group.uuid, &group.times, parent);
+
+    if let Some(existing) = KeePass::find_group_by_id_mut(&mut target.root, &group.uuid) {
+        if newer(existing.times.get_last_modification(), group.times.get_last_modification()) {
+            let location_changed = later(existing.times.get_location_changed(), group.times.get_location_changed());
+
+            existing.name = group.name.clone();
+            existing.notes = group.notes.clone();
+            existing.icon_id = group.icon_id;
+            existing.custom_icon_uuid = group.custom_icon_uuid;
+            existing.times = group.times.clone();
+            if let Some(time) = location_changed {
+                existing.times.set_location_changed(time);
+            }
+        }
+    }
+
+    true
+}
+
+fn merge_entry(target: &mut Database, source: &Database, entry: &Entry, parent: Uuid, deleted: &HashMap<Uuid, NaiveDateTime>) {
+    let mut incoming = entry.clone();
+    import_binaries(target, source, &mut incoming);
+    if let Some(history) = &entry.history {
+        let mut entries = Vec::with_capacity(history.get_entries().len());
+        for old in history.get_entries() {
+            let mut old = old.clone();
+            import_binaries(target, source, &mut old);
+            entries.push(old);
+        }
+        incoming.history = Some(new_history(entries));
+    }
+
+    if KeePass::find_entry_by_id(&target.root, &entry.uuid).is_none() {
+        if !is_deleted(deleted, &entry.uuid, &entry.times) {
+            insert_node(target, parent, Node::Entry(incoming));
+        }
+        return;
+    }
+
+    relocate(target, &entry.uuid, &entry.times, parent);
+
+    let existing = match KeePass::find_entry_by_id_mut(&mut target.root, &entry.uuid) {
+        Some(v) => v,
+        None => return,
+    };
+
+    let mut history: Vec<Entry> = vec![];
+    history.extend(existing.history.take().iter().flat_map(|h| h.get_entries().clone()));
+    history.extend(incoming.history.take().iter().flat_map(|h| h.get_entries().clone()));
+
+    // the location was merged already, don't let it make versions differ
+    if let Some(time) = later(existing.times.get_location_changed(), incoming.times.get_location_changed()) {
+        existing.times.set_location_changed(time);
+        incoming.times.set_location_changed(time);
+    }
+
+    // identical versions only need their history combined
+    if !same_version(existing, &incoming) {
+        if newer(existing.times.get_last_modification(), incoming.times.get_last_modification()) {
+            history.push(std::mem::replace(existing, incoming));
+        } else {
+            history.push(incoming);
+        }
+    }
+
+    existing.history = Some(new_history(history));
+}
+
+// Builds a history with the most recent version first, dropping duplicates
+fn new_history(mut entries: Vec<Entry>) -> History {
+    entries.sort_by(|a, b| a.times.get_last_modification().cmp(&b.times.get_last_modification()));
+
+    let mut unique: Vec<Entry> = Vec::with_capacity(entries.len());
+    for mut entry in entries {
+        if !unique.iter().any(|e| same_version(e, &entry)) {
+            entry.history = None;
+            unique.push(entry);
+        }
+    }
+
+    let mut history = History::default();
+    // add_entry prepends, so add the oldest first
+    for entry in unique {
+        history.add_entry(entry);
+    }
+
+    history
+}
+
+// The database file stores times with second precision only, while our own modifications are more precise
+fn same_version(a: &Entry, b: &Entry) -> bool {
+    let seconds = |entry: &Entry| entry.times.get_last_modification().map(|t| t.and_utc().timestamp());
+
+    a.fields == b.fields
+        && a.tags == b.tags
+        && a.icon_id == b.icon_id
+        && a.custom_icon_uuid == b.custom_icon_uuid
+        && seconds(a) == seconds(b)
+}
+
+// Moves an existing node to `parent` if it was moved there more recently in the source
+fn relocate(target: &mut Database, id: &Uuid, times: &Times, parent: Uuid) {
+    let current = match find_parent(&target.root, id) {
+        Some(v) => v,
+        None => return,
+    };
+    if current == parent {
+        return;
+    }
+
+    let node = match KeePass::find_group_by_id(&target.root, id) {
+        Some(group) => {
+            // don't move a group into its own subtree
+            if KeePass::find_group_by_id(group, &parent).is_some() {
+                return;
+            }
+            group.times.get_location_changed()
+        }
+        None => match KeePass::find_entry_by_id(&target.root, id) {
+            Some(entry) => entry.times.get_location_changed(),
+            None => return,
+        }
+    };
+    if !newer(node, times.get_location_changed()) {
+        return;
+    }
+
+    if let Some(mut node) = KeePass::take_node(&mut target.root, id) {
+        if let Some(time) = times.get_location_changed() {
+            match &mut node {
+                Node::Group(group) => group.times.set_location_changed(*time),
+                Node::Entry(entry) => entry.times.set_location_changed(*time),
+            }
+        }
+        insert_node(target, parent, node);
+    }
+}
+
+fn insert_node(target: &mut Database, parent: Uuid, node: Node) {
+    match KeePass::find_group_by_id_mut(&mut target.root, &parent) {
+        Some(group) => group.children.push(node),
+        None => target.root.children.push(node),
+    }
+}
+
+fn find_parent(group: &Group, id: &Uuid) -> Option<Uuid> {
+    for node in &group.children {
+        match node {
+            Node::Group(child) => {
+                if &child.uuid == id {
+                    return Some(group.uuid);
+                }
+                let found = find_parent(child, id);
+                if found.is_some() {
+                    return found;
+                }
+            }
+            Node::Entry(entry) => {
+                if &entry.uuid == id {
+                    return Some(group.uuid);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn apply_deletions(target: &mut Database, deleted: &HashMap<Uuid, NaiveDateTime>) {
+    for (uuid, time) in deleted {
+        if uuid == &target.root.uuid {
+            continue;
+        }
+
+        let remove = match KeePass::find_group_by_id(&target.root, uuid) {
+            // keep groups with anything in them modified after the deletion
+            Some(group) => !modified_after(group, time),
+            None => match KeePass::find_entry_by_id(&target.root, uuid) {
+                Some(entry) => !newer(Some(time), entry.times.get_last_modification()),
+                None => false,
+            }
+        };
+
+        if remove {
+            KeePass::take_node(&mut target.root, uuid);
+        }
+    }
+}
+
+fn modified_after(group: &Group, time: &NaiveDateTime) -> bool {
+    if newer(Some(time), group.times.get_last_modification()) {
+        return true;
+    }
+
+    group.children.iter().any(|node| match node {
+        Node::Group(group) => modified_after(group, time),
+        Node::Entry(entry) => newer(Some(time), entry.times.get_last_modification()),
+    })
+}
+
+fn merge_meta(target: &mut Database, source: &Database) {
+    for icon in &source.meta.custom_icons.icons {
+        if !target.meta.custom_icons.icons.iter().any(|i| i.uuid == icon.uuid) {
+            target.meta.custom_icons.icons.push(icon.clone());
+        }
+    }
+
+    // adopt the recycle bin of the source if ours doesn't exist (anymore)
+    let has_bin = target.meta.recyclebin_uuid.is_some_and(|id| KeePass::find_group_by_id(&target.root, &id).is_some());
+    if !has_bin && source.meta.recyclebin_uuid.is_some_and(|id| KeePass::find_group_by_id(&target.root, &id).is_some()) {
+        target.meta.recyclebin_uuid = source.meta.recyclebin_uuid;
+        target.meta.recyclebin_changed = source.meta.recyclebin_changed;
+    }
+}
+
+// Binary fields of source entries reference the binary pool of the source database,
+// copy the content over to the target and point the reference to it
+fn import_binaries(target: &mut Database, source: &Database, entry: &mut Entry) {
+    for value in entry.fields.values_mut() {
+        let bytes = match value {
+            Value::Bytes(v) => v,
+            _ => continue,
+        };
+
+        match import_binary(target, source, bytes) {
+            Ok(new_id) => *bytes = new_id.to_string().into_bytes(),
+            Err(err) => warn!("failed to import binary of entry '{}': {}", entry.uuid, err),
+        }
+    }
+}
+
+// Returns the reference of the binary in the target pool, identical binaries are reused
+fn import_binary(target: &mut Database, source: &Database, reference: &[u8]) -> Result<usize> {
+    let id = attachment::reference(reference)?;
+
+    match (attachment::in_header(source), attachment::in_header(target)) {
+        (true, true) => {
+            let attachment = source.header_attachments.get(id)
+                .ok_or(anyhow!("binary reference '{}' not found", id))?;
+            Ok(add_header_attachment(target, attachment.flags, &attachment.content))
+        }
+        (false, false) => {
+            let identifier = id.to_string();
+            let binary = source.meta.binaries.binaries.iter()
+                .find(|b| b.identifier.as_deref() == Some(identifier.as_str()))
+                .ok_or(anyhow!("binary reference '{}' not found", id))?;
+            Ok(add_meta_binary(target, binary.compressed, &binary.content))
+        }
+        // the database was converted in between, the content is copied uncompressed
+        (_, true) => Ok(add_header_attachment(target, 0, &attachment::resolve(source, reference)?)),
+        (_, false) => Ok(add_meta_binary(target, false, &attachment::resolve(source, reference)?)),
+    }
+}
+
+fn add_header_attachment(target: &mut Database, flags: u8, content: &[u8]) -> usize {
+    if let Some(pos) = target.header_attachments.iter().position(|a| a.content == content) {
+        return pos;
+    }
+
+    target.header_attachments.push(HeaderAttachment {
+        flags,
+        content: content.to_vec(),
+    });
+    target.header_attachments.len() - 1
+}
+
+// Meta binaries are referenced by their numeric identifier
+fn add_meta_binary(target: &mut Database, compressed: bool, content: &[u8]) -> usize {
+    let binaries = &mut target.meta.binaries.binaries;
+    let identifier = |b: &BinaryAttachment| b.identifier.as_deref().and_then(|v| v.parse::<usize>().ok());
+
+    let existing = binaries.iter()
+        .filter(|b| b.compressed == compressed && b.content == content)
+        .find_map(identifier);
+    if let Some(existing) = existing {
+        return existing;
+    }
+
+    let new_id = binaries.iter().filter_map(identifier).max().map_or(0, |v| v + 1);
+    binaries.push(BinaryAttachment {
+        identifier: Some(new_id.to_string()),
+        compressed,
+        content: content.to_vec(),
+    });
+    new_id
+}
+
+fn is_deleted(deleted: &HashMap<Uuid, NaiveDateTime>, id: &Uuid, times: &Times) -> bool {
+    match deleted.get(id) {
+        Some(time) => !newer(Some(time), times.get_last_modification()),
+        None => false,
+    }
+}
+
+// Whether `other` is more recent than `current`
+fn newer(current: Option<&NaiveDateTime>, other: Option<&NaiveDateTime>) -> bool {
+    match (current, other) {
+        (Some(current), Some(other)) => other > current,
+        (None, Some(_)) => true,
+        _ => false,
+    }
+}
+
+fn later(a: Option<&NaiveDateTime>, b: Option<&NaiveDateTime>) -> Option<NaiveDateTime> {
+    a.max(b).copied()
+}
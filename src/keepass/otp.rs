@@ -0,0 +1,394 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+use base64::engine::general_purpose;
+use constant_time_eq::constant_time_eq;
+use data_encoding::{BASE32_NOPAD, HEXLOWER_PERMISSIVE};
+use hmac::{Hmac, Mac};
+use hmac::digest::KeyInit;
+use keepass::db::{Entry, Value};
+use secstr::SecStr;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use url::Url;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+// KeePassXC and most other clients
+const OTP: &str = "otp";
+// KeePass 2.x
+const TIME_OTP_SECRET: &str = "TimeOtp-Secret";
+const TIME_OTP_LENGTH: &str = "TimeOtp-Length";
+const TIME_OTP_PERIOD: &str = "TimeOtp-Period";
+const TIME_OTP_ALGORITHM: &str = "TimeOtp-Algorithm";
+const HMAC_OTP_SECRET: &str = "HmacOtp-Secret";
+const HMAC_OTP_COUNTER: &str = "HmacOtp-Counter";
+const SECRET_SUFFIXES: [&str; 4] = ["", "-Hex", "-Base32", "-Base64"];
+
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+const STEAM_DIGITS: u32 = 5;
+const STEAM_ALPHABET: &[u8] = b"23456789BCDFGHJKMNPQRTVWXY";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Totp {
+        period: u64,
+    },
+    Hotp {
+        counter: u64,
+    },
+}
+
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub(crate) struct Otp {
+    secret: Vec<u8>,
+    #[zeroize(skip)]
+    digits: u32,
+    #[zeroize(skip)]
+    algorithm: Algorithm,
+    #[zeroize(skip)]
+    kind: Kind,
+    #[zeroize(skip)]
+    steam: bool,
+    // the otpauth uri the parameters were parsed from, None for the KeePass 2.x fields
+    uri: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Code {
+    pub code: String,
+    pub period: Option<u64>,
+    // seconds the code is still valid for, TOTP only
+    pub remaining: Option<u64>,
+}
+
+pub(crate) fn has_otp(entry: &Entry) -> bool {
+    if entry.fields.contains_key(OTP) {
+        return true;
+    }
+
+    SECRET_SUFFIXES.iter().any(|suffix| {
+        entry.fields.contains_key(&format!("{}{}", TIME_OTP_SECRET, suffix))
+            || entry.fields.contains_key(&format!("{}{}", HMAC_OTP_SECRET, suffix))
+    })
+}
+
+impl Otp {
+    pub(crate) fn from_entry(entry: &Entry) -> Result<Self> {
+        if let Some(uri) = entry.get(OTP) {
+            return Self::from_uri(uri);
+        }
+
+        if let Some(secret) = Self::secret(entry, TIME_OTP_SECRET)? {
+            let digits = match entry.get(TIME_OTP_LENGTH) {
+                Some(v) => v.trim().parse()?,
+                None => DEFAULT_DIGITS,
+            };
+            let period = match entry.get(TIME_OTP_PERIOD) {
+                Some(v) => v.trim().parse()?,
+                None => DEFAULT_PERIOD,
+            };
+            let algorithm = match entry.get(TIME_OTP_ALGORITHM) {
+                Some(v) => Self::algorithm(v)?,
+                None => Algorithm::Sha1,
+            };
+
+            return Self::new(secret, digits, algorithm, Kind::Totp { period }, false, None);
+        }
+
+        if let Some(secret) = Self::secret(entry, HMAC_OTP_SECRET)? {
+            let counter = match entry.get(HMAC_OTP_COUNTER) {
+                Some(v) => v.trim().parse()?,
+                None => 0,
+            };
+
+            return Self::new(secret, DEFAULT_DIGITS, Algorithm::Sha1, Kind::Hotp { counter }, false, None);
+        }
+
+        bail!("entry has no otp fields")
+    }
+
+    // Plain TOTP with the parameters every authenticator app supports (SHA-1, 6 digits, 30 seconds)
+    pub(crate) fn totp(secret: Vec<u8>) -> Result<Self> {
+        Self::new(secret, DEFAULT_DIGITS, Algorithm::Sha1, Kind::Totp { period: DEFAULT_PERIOD }, false, None)
+    }
+
+    fn from_uri(uri: &str) -> Result<Self> {
+        let url = Url::parse(uri.trim())?;
+        if url.scheme() != "otpauth" {
+            bail!("unsupported otp uri scheme '{}'", url.scheme());
+        }
+
+        let mut secret = None;
+        let mut digits = None;
+        let mut period = DEFAULT_PERIOD;
+        let mut counter = None;
+        let mut algorithm = Algorithm::Sha1;
+        let mut steam = false;
+        for (k, v) in url.query_pairs() {
+            match k.to_lowercase().as_str() {
+                "secret" => secret = Some(Self::decode_base32(&v)?),
+                "digits" => digits = Some(v.parse()?),
+                "period" => period = v.parse()?,
+                "counter" => counter = Some(v.parse()?),
+                "algorithm" => algorithm = Self::algorithm(&v)?,
+                "encoder" => steam = v.eq_ignore_ascii_case("steam"),
+                _ => {}
+            }
+        }
+
+        let kind = match url.host_str().map(str::to_lowercase).as_deref() {
+            Some("totp") => Kind::Totp { period },
+            Some("hotp") => Kind::Hotp { counter: counter.ok_or(anyhow!("hotp uri without counter"))? },
+            _ => bail!("unsupported otp type"),
+        };
+        let digits = match steam {
+            true => STEAM_DIGITS,
+            false => digits.unwrap_or(DEFAULT_DIGITS),
+        };
+
+        Self::new(secret.ok_or(anyhow!("otp uri without secret"))?, digits, algorithm, kind, steam, Some(uri.to_string()))
+    }
+
+    fn new(secret: Vec<u8>, digits: u32, algorithm: Algorithm, kind: Kind, steam: bool, uri: Option<String>) -> Result<Self> {
+        if secret.is_empty() {
+            bail!("empty otp secret");
+        }
+        if !(1..=10).contains(&digits) {
+            bail!("invalid number of otp digits: {}", digits);
+        }
+        if matches!(kind, Kind::Totp { period: 0 }) {
+            bail!("invalid otp period: 0");
+        }
+
+        Ok(
+            Self {
+                secret,
+                digits,
+                algorithm,
+                kind,
+                steam,
+                uri,
+            }
+        )
+    }
+
+    pub(crate) fn generate(&self) -> Result<Code> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        Ok(self.generate_at(now))
+    }
+
+    fn generate_at(&self, time: u64) -> Code {
+        match self.kind {
+            Kind::Totp { period } => Code {
+                code: self.code(time / period),
+                period: Some(period),
+                remaining: Some(period - time % period),
+            },
+            Kind::Hotp { counter } => Code {
+                code: self.code(counter),
+                period: None,
+                remaining: None,
+            },
+        }
+    }
+
+    // The time step the TOTP code belongs to, allowing for `skew` steps of clock drift in both directions
+    pub(crate) fn verify_at(&self, code: &str, time: u64, skew: u64) -> Option<u64> {
+        let Kind::Totp { period } = self.kind else {
+            return None;
+        };
+        let step = time / period;
+
+        (step.saturating_sub(skew)..=step + skew)
+            .find(|step| constant_time_eq(self.code(*step).as_bytes(), code.trim().as_bytes()))
+    }
+
+    // HOTP codes can only be used once, so the counter has to move forward on every generated code.
+    // Returns whether the entry was modified
+    pub(crate) fn increment_counter(&self, entry: &mut Entry) -> Result<bool> {
+        let counter = match self.kind {
+            Kind::Hotp { counter } => counter + 1,
+            Kind::Totp { .. } => return Ok(false),
+        };
+
+        match &self.uri {
+            Some(uri) => {
+                let mut uri = Url::parse(uri.trim())?;
+                let pairs: Vec<(String, String)> = uri.query_pairs()
+                    .map(|(k, v)| match k.eq_ignore_ascii_case("counter") {
+                        true => (k.to_string(), counter.to_string()),
+                        false => (k.to_string(), v.to_string()),
+                    })
+                    .collect();
+                uri.query_pairs_mut().clear().extend_pairs(pairs);
+
+                Self::set_field(entry, OTP, uri.as_str());
+            }
+            None => Self::set_field(entry, HMAC_OTP_COUNTER, &counter.to_string()),
+        }
+
+        Ok(true)
+    }
+
+    // RFC 4226
+    fn code(&self, counter: u64) -> String {
+        let hash = match self.algorithm {
+            Algorithm::Sha1 => Self::hmac::<Hmac<Sha1>>(&self.secret, counter),
+            Algorithm::Sha256 => Self::hmac::<Hmac<Sha256>>(&self.secret, counter),
+            Algorithm::Sha512 => Self::hmac::<Hmac<Sha512>>(&self.secret, counter),
+        };
+
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let mut value = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+
+        if self.steam {
+            let mut code = String::with_capacity(self.digits as usize);
+            for _ in 0..self.digits {
+                code.push(STEAM_ALPHABET[value as usize % STEAM_ALPHABET.len()] as char);
+                value /= STEAM_ALPHABET.len() as u32;
+            }
+            return code;
+        }
+
+        let value = value as u64 % 10u64.pow(self.digits);
+        format!("{:0width$}", value, width = self.digits as usize)
+    }
+
+    fn hmac<M: Mac + KeyInit>(secret: &[u8], counter: u64) -> Vec<u8> {
+        let mut mac = <M as KeyInit>::new_from_slice(secret).expect("hmac accepts keys of any length");
+        mac.update(&counter.to_be_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn secret(entry: &Entry, prefix: &str) -> Result<Option<Vec<u8>>> {
+        for suffix in SECRET_SUFFIXES {
+            let value = match entry.get(&format!("{}{}", prefix, suffix)) {
+                Some(v) => v.trim(),
+                None => continue,
+            };
+
+            let secret = match suffix {
+                "-Hex" => HEXLOWER_PERMISSIVE.decode(value.as_bytes())?,
+                "-Base32" => Self::decode_base32(value)?,
+                "-Base64" => general_purpose::STANDARD.decode(value)?,
+                _ => value.as_bytes().to_vec(),
+            };
+            return Ok(Some(secret));
+        }
+
+        Ok(None)
+    }
+
+    fn decode_base32(value: &str) -> Result<Vec<u8>> {
+        let value: String = value.chars()
+            .filter(|c| !c.is_whitespace() && *c != '=' && *c != '-')
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+
+        Ok(BASE32_NOPAD.decode(value.as_bytes())?)
+    }
+
+    fn algorithm(value: &str) -> Result<Algorithm> {
+        match value.trim().to_uppercase().replace('-', "").as_str() {
+            "SHA1" | "HMACSHA1" => Ok(Algorithm::Sha1),
+            "SHA256" | "HMACSHA256" => Ok(Algorithm::Sha256),
+            "SHA512" | "HMACSHA512" => Ok(Algorithm::Sha512),
+            v => bail!("unsupported otp algorithm '{}'", v),
+        }
+    }
+
+    fn set_field(entry: &mut Entry, name: &str, value: &str) {
+        let value = match entry.fields.get(name) {
+            Some(Value::Protected(_)) => Value::Protected(SecStr::new(value.as_bytes().to_vec())),
+            _ => Value::Unprotected(value.to_string()),
+        };
+        entry.fields.insert(name.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        let mut entry = Entry::new();
+        for (k, v) in fields {
+            entry.fields.insert(k.to_string(), Value::Unprotected(v.to_string()));
+        }
+        entry
+    }
+
+    #[test]
+    fn rfc_vectors() {
+        // RFC 6238 appendix B, secret "12345678901234567890"
+        let totp = Otp::from_entry(&entry(&[
+            ("otp", "otpauth://totp/test?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&digits=8"),
+        ])).unwrap();
+        let code = totp.generate_at(59);
+        assert_eq!(code.code, "94287082");
+        assert_eq!(code.remaining, Some(1));
+        assert_eq!(totp.generate_at(1111111109).code, "07081804");
+
+        let totp = Otp::from_entry(&entry(&[
+            ("TimeOtp-Secret", "12345678901234567890123456789012"),
+            ("TimeOtp-Length", "8"),
+            ("TimeOtp-Algorithm", "HMAC-SHA-256"),
+        ])).unwrap();
+        assert_eq!(totp.generate_at(59).code, "46119246");
+
+        // RFC 4226 appendix D
+        let hotp = Otp::from_entry(&entry(&[
+            ("HmacOtp-Secret-Hex", "3132333435363738393031323334353637383930"),
+            ("HmacOtp-Counter", "1"),
+        ])).unwrap();
+        assert_eq!(hotp.generate_at(0).code, "287082");
+    }
+
+    #[test]
+    fn verify() {
+        let totp = Otp::totp(b"12345678901234567890".to_vec()).unwrap();
+        // RFC 6238 appendix B, truncated to 6 digits
+        assert_eq!(totp.verify_at("287082", 59, 0), Some(1));
+        assert_eq!(totp.verify_at(" 287082 ", 89, 1), Some(1));
+        assert_eq!(totp.verify_at("287082", 89, 0), None);
+        assert_eq!(totp.verify_at("000000", 59, 1), None);
+
+        let hotp = Otp::from_entry(&entry(&[("HmacOtp-Secret", "12345678901234567890")])).unwrap();
+        assert_eq!(hotp.verify_at("755224", 0, 1), None);
+    }
+
+    #[test]
+    fn steam() {
+        let steam = Otp::from_entry(&entry(&[
+            ("otp", "otpauth://totp/Steam:user?secret=GEZDGNBVGY3TQOJQ&issuer=Steam&encoder=steam"),
+        ])).unwrap();
+        let code = steam.generate_at(59).code;
+        assert_eq!(code.len(), 5);
+        assert!(code.bytes().all(|c| STEAM_ALPHABET.contains(&c)));
+    }
+
+    #[test]
+    fn hotp_counter() {
+        let mut entry = entry(&[
+            ("otp", "otpauth://hotp/test?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=0"),
+        ]);
+
+        let hotp = Otp::from_entry(&entry).unwrap();
+        assert_eq!(hotp.generate_at(0).code, "755224");
+        assert!(hotp.increment_counter(&mut entry).unwrap());
+
+        let hotp = Otp::from_entry(&entry).unwrap();
+        assert_eq!(hotp.generate_at(0).code, "287082");
+    }
+}
@@ -0,0 +1,320 @@
+use std::cell::Cell;
+
+use anyhow::{anyhow, bail, Result};
+use keepass::db::{Entry, Group, Node, Value};
+use url::Url;
+use uuid::Uuid;
+
+use crate::keepass::keepass::{NOTES, PASSWORD, STANDARD_FIELDS, TITLE, URL, USERNAME};
+
+// references pointing to references pointing to ... give up at some point
+const MAX_DEPTH: usize = 10;
+
+// Resolves KeePass placeholders ({TITLE}, {S:name}, {URL:HOST}, ...) and field references
+// ({REF:<wanted field>@<search in>:<text>}) in field values
+pub(crate) struct Resolver<'a> {
+    root: &'a Group,
+    // fields currently being resolved, to detect reference cycles
+    stack: Vec<(Uuid, String)>,
+    // whether the resolved value contains data of protected fields
+    pub(crate) protected: bool,
+}
+
+impl<'a> Resolver<'a> {
+    pub(crate) fn new(root: &'a Group) -> Self {
+        Self {
+            root,
+            stack: vec![],
+            protected: false,
+        }
+    }
+
+    // Returns the resolved value of a string field, None if the entry has no such field
+    pub(crate) fn resolve_field(&mut self, entry: &Entry, name: &str) -> Result<Option<String>> {
+        let value = match entry.fields.get(name) {
+            Some(Value::Unprotected(v)) => v.clone(),
+            Some(Value::Protected(v)) => {
+                self.protected = true;
+                String::from_utf8_lossy(v.unsecure()).to_string()
+            }
+            Some(Value::Bytes(_)) | None => return Ok(None),
+        };
+
+        if !value.contains('{') {
+            return Ok(Some(value));
+        }
+
+        let key = (entry.uuid, name.to_string());
+        if self.stack.contains(&key) {
+            bail!("reference cycle in field '{}' of entry '{}'", name, entry.uuid);
+        }
+        if self.stack.len() >= MAX_DEPTH {
+            bail!("references nested deeper than {} levels", MAX_DEPTH);
+        }
+
+        self.stack.push(key);
+        let resolved = self.resolve_value(entry, &value);
+        self.stack.pop();
+
+        resolved.map(Some)
+    }
+
+    fn resolve_value(&mut self, entry: &Entry, value: &str) -> Result<String> {
+        let mut resolved = String::with_capacity(value.len());
+        let mut rest = value;
+
+        while let Some(start) = rest.find('{') {
+            resolved.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let end = match rest.find('}') {
+                Some(v) => v,
+                None => break,
+            };
+
+            let placeholder = &rest[1..end];
+            match self.placeholder(entry, placeholder)? {
+                Some(v) => resolved.push_str(&v),
+                // unknown placeholders are kept as they are
+                None => resolved.push_str(&rest[..=end]),
+            }
+            rest = &rest[end + 1..];
+        }
+        resolved.push_str(rest);
+
+        Ok(resolved)
+    }
+
+    fn placeholder(&mut self, entry: &Entry, placeholder: &str) -> Result<Option<String>> {
+        let upper = placeholder.to_uppercase();
+
+        let field = match upper.as_str() {
+            "TITLE" => TITLE,
+            "USERNAME" => USERNAME,
+            "PASSWORD" => PASSWORD,
+            "URL" => URL,
+            "NOTES" => NOTES,
+            _ => {
+                if upper.starts_with("S:") {
+                    return Ok(Some(self.resolve_field(entry, &placeholder[2..])?.unwrap_or_default()));
+                }
+                if let Some(part) = upper.strip_prefix("URL:") {
+                    let url = self.resolve_field(entry, URL)?.unwrap_or_default();
+                    return Ok(Self::url_part(&url, part));
+                }
+                if upper.starts_with("REF:") {
+                    return self.reference(&placeholder[4..]).map(Some);
+                }
+
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(self.resolve_field(entry, field)?.unwrap_or_default()))
+    }
+
+    // <wanted field>@<search in>:<text>
+    fn reference(&mut self, reference: &str) -> Result<String> {
+        let (fields, text) = reference.split_once(':').ok_or(anyhow!("invalid reference '{}'", reference))?;
+        let (wanted, search_in) = fields.split_once('@').ok_or(anyhow!("invalid reference '{}'", reference))?;
+
+        // whether the match reveals something about protected values, even if none of them is handed out
+        let searched_protected = Cell::new(false);
+        let lower = text.to_lowercase();
+        let contains = |value: &Value| {
+            if let Value::Protected(_) = value {
+                searched_protected.set(true);
+            }
+            Self::contains(value, &lower)
+        };
+
+        let search_in = search_in.to_uppercase();
+        let entry = match search_in.as_str() {
+            "I" => {
+                let id = Uuid::parse_str(text)?;
+                Self::find_entry(self.root, &|e: &Entry| e.uuid == id)
+            }
+            "O" => {
+                Self::find_entry(self.root, &|e: &Entry| {
+                    e.fields.iter()
+                        .filter(|(k, _)| !STANDARD_FIELDS.contains(&k.as_str()))
+                        .any(|(_, v)| contains(v))
+                })
+            }
+            code => {
+                let field = Self::field_name(code)?;
+                if field == PASSWORD {
+                    self.protected = true;
+                }
+                Self::find_entry(self.root, &|e: &Entry| e.fields.get(field).is_some_and(contains))
+            }
+        };
+        if searched_protected.get() {
+            self.protected = true;
+        }
+
+        let entry = match entry {
+            Some(v) => v,
+            None => bail!("referenced entry not found: {}", reference),
+        };
+
+        match wanted.to_uppercase().as_str() {
+            // KeePass writes uuids in references as upper case hex
+            "I" => Ok(entry.uuid.simple().to_string().to_uppercase()),
+            code => Ok(self.resolve_field(entry, Self::field_name(code)?)?.unwrap_or_default()),
+        }
+    }
+
+    fn field_name(code: &str) -> Result<&'static str> {
+        match code {
+            "T" => Ok(TITLE),
+            "U" => Ok(USERNAME),
+            "P" => Ok(PASSWORD),
+            "A" => Ok(URL),
+            "N" => Ok(NOTES),
+            v => bail!("unknown reference field '{}'", v),
+        }
+    }
+
+    fn url_part(url: &str, part: &str) -> Option<String> {
+        let parsed = match Url::parse(url) {
+            Ok(v) => v,
+            Err(_) => return Some(String::new()),
+        };
+
+        let value = match part {
+            "HOST" => parsed.host_str().unwrap_or_default().to_string(),
+            "SCM" => parsed.scheme().to_string(),
+            "PORT" => parsed.port_or_known_default().map(|v| v.to_string()).unwrap_or_default(),
+            "PATH" => parsed.path().to_string(),
+            "QUERY" => parsed.query().map(|v| format!("?{}", v)).unwrap_or_default(),
+            "USERINFO" => match parsed.password() {
+                Some(password) => format!("{}:{}", parsed.username(), password),
+                None => parsed.username().to_string(),
+            },
+            "USERNAME" => parsed.username().to_string(),
+            "PASSWORD" => parsed.password().unwrap_or_default().to_string(),
+            "RMVSCM" => url.split_once("://").map_or(url, |(_, rest)| rest).to_string(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    fn contains(value: &Value, text: &str) -> bool {
+        match value {
+            Value::Unprotected(v) => v.to_lowercase().contains(text),
+            Value::Protected(v) => String::from_utf8_lossy(v.unsecure()).to_lowercase().contains(text),
+            Value::Bytes(_) => false,
+        }
+    }
+
+    fn find_entry<'b>(group: &'b Group, matches: &dyn Fn(&Entry) -> bool) -> Option<&'b Entry> {
+        for node in &group.children {
+            match node {
+                Node::Group(group) => {
+                    let found = Self::find_entry(group, matches);
+                    if found.is_some() {
+                        return found;
+                    }
+                }
+                Node::Entry(entry) => {
+                    if matches(entry) {
+                        return Some(entry);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secstr::SecStr;
+
+    use super::*;
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        let mut entry = Entry::new();
+        for (k, v) in fields {
+            entry.fields.insert(k.to_string(), Value::Unprotected(v.to_string()));
+        }
+        entry
+    }
+
+    #[test]
+    fn resolve() {
+        let mut target = entry(&[
+            ("Title", "target"),
+            ("UserName", "user"),
+            ("URL", "https://example.com:8443/login?next=1"),
+        ]);
+        target.fields.insert("Password".to_string(), Value::Protected(SecStr::new(b"secret".to_vec())));
+        let id = target.uuid.simple().to_string().to_uppercase();
+        let source = entry(&[
+            ("Title", "source"),
+            ("UserName", "{REF:U@T:targ}"),
+            ("Password", &format!("{{REF:P@I:{}}}", id)),
+            ("URL", "{REF:A@U:user}"),
+            ("Notes", "{TITLE} at {URL:HOST}:{url:port}, {S:custom} {unknown}"),
+            ("custom", "{REF:I@O:value}"),
+            ("other", "value"),
+        ]);
+
+        let mut root = Group::new("root");
+        root.children.push(Node::Entry(target));
+        root.children.push(Node::Entry(source.clone()));
+
+        let mut resolver = Resolver::new(&root);
+        assert_eq!(resolver.resolve_field(&source, "UserName").unwrap().as_deref(), Some("user"));
+        assert!(!resolver.protected);
+        assert_eq!(resolver.resolve_field(&source, "Password").unwrap().as_deref(), Some("secret"));
+        assert!(resolver.protected);
+        assert_eq!(
+            resolver.resolve_field(&source, "Notes").unwrap(),
+            Some(format!("source at example.com:8443, {} {{unknown}}", source.uuid.simple().to_string().to_uppercase())),
+        );
+    }
+
+    #[test]
+    fn protected_search() {
+        let mut target = entry(&[("Title", "target")]);
+        target.fields.insert("pin".to_string(), Value::Protected(SecStr::new(b"1234".to_vec())));
+        target.fields.insert("UserName".to_string(), Value::Protected(SecStr::new(b"admin".to_vec())));
+        let source = entry(&[
+            ("Title", "{REF:T@O:12}"),
+            ("Notes", "{REF:T@U:adm}"),
+            ("URL", "{REF:T@T:targ}"),
+        ]);
+
+        let mut root = Group::new("root");
+        root.children.push(Node::Entry(target));
+        root.children.push(Node::Entry(source.clone()));
+
+        let mut resolver = Resolver::new(&root);
+        assert_eq!(resolver.resolve_field(&source, "URL").unwrap().as_deref(), Some("target"));
+        assert!(!resolver.protected);
+
+        for field in ["Title", "Notes"] {
+            let mut resolver = Resolver::new(&root);
+            assert_eq!(resolver.resolve_field(&source, field).unwrap().as_deref(), Some("target"));
+            assert!(resolver.protected, "{}", field);
+        }
+    }
+
+    #[test]
+    fn cycle() {
+        let source = entry(&[
+            ("Title", "{USERNAME}"),
+            ("UserName", "{S:custom}"),
+            ("custom", "{TITLE}"),
+        ]);
+        let mut root = Group::new("root");
+        root.children.push(Node::Entry(source.clone()));
+
+        let mut resolver = Resolver::new(&root);
+        assert!(resolver.resolve_field(&source, "Title").is_err());
+    }
+}
@@ -0,0 +1,503 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use chrono::NaiveDateTime;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use url::Url;
+
+use crate::config::search::{Field, Search};
+use crate::keepass::entry::Entry;
+
+// Search query as typed by the user, e.g. `user:alice url:github -tag:old "exact phrase" /^web-\d+$/`.
+// All clauses have to match, `-` negates a clause
+#[derive(Debug)]
+pub struct SearchQuery {
+    pub clauses: Vec<Clause>,
+}
+
+#[derive(Debug)]
+pub struct Clause {
+    pub negated: bool,
+    pub condition: Condition,
+}
+
+#[derive(Debug)]
+pub enum Condition {
+    // no qualifier, matches the fields enabled in the search config
+    Any(Pattern),
+    Field(Field, Pattern),
+    // name or value of user supplied fields
+    Attribute(Pattern),
+    // path of the parent group, e.g. `Root/Infra/Servers`
+    Group(Pattern),
+    Expired,
+}
+
+#[derive(Debug)]
+pub enum Pattern {
+    // case insensitive, fuzzy if enabled, stored lowercase
+    Text(String),
+    // quoted, case insensitive substring, stored lowercase
+    Phrase(String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum QueryError {
+    UnknownQualifier { qualifier: String, offset: usize },
+    UnknownState { state: String, offset: usize },
+    MissingValue { qualifier: String, offset: usize },
+    UnterminatedQuote { offset: usize },
+    UnterminatedRegex { offset: usize },
+    InvalidRegex { message: String, offset: usize },
+    RegexDisabled { offset: usize },
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::UnknownQualifier { qualifier, offset } => write!(f, "unknown qualifier '{}' at offset {}", qualifier, offset),
+            QueryError::UnknownState { state, offset } => write!(f, "unknown state 'is:{}' at offset {}", state, offset),
+            QueryError::MissingValue { qualifier, offset } => write!(f, "missing value for '{}' at offset {}", qualifier, offset),
+            QueryError::UnterminatedQuote { offset } => write!(f, "unterminated quote at offset {}", offset),
+            QueryError::UnterminatedRegex { offset } => write!(f, "unterminated regex at offset {}", offset),
+            QueryError::InvalidRegex { message, offset } => write!(f, "invalid regex at offset {}: {}", offset, message),
+            QueryError::RegexDisabled { offset } => write!(f, "regex search is disabled, at offset {}", offset),
+        }
+    }
+}
+
+impl Error for QueryError {}
+
+pub fn parse(query: &str, allow_regex: bool) -> Result<SearchQuery, QueryError> {
+    Parser {
+        query,
+        pos: 0,
+        allow_regex,
+    }.parse()
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    // byte offset into the query
+    pos: usize,
+    allow_regex: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(mut self) -> Result<SearchQuery, QueryError> {
+        let mut clauses = vec![];
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().is_empty() {
+                break;
+            }
+            clauses.push(self.clause()?);
+        }
+
+        Ok(SearchQuery { clauses })
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.query[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn clause(&mut self) -> Result<Clause, QueryError> {
+        // a lone `-` is searched for as is
+        let negated = self.rest().starts_with('-') && self.rest()[1..].starts_with(|c: char| !c.is_whitespace());
+        if negated {
+            self.pos += 1;
+        }
+        let start = self.pos;
+
+        // `qualifier:value`, anything else containing a colon (e.g. `https://...`) is plain text
+        let word = self.rest().split(char::is_whitespace).next().unwrap_or_default();
+        let condition = match word.split_once(':') {
+            Some((qualifier, value)) if !qualifier.is_empty() && qualifier.chars().all(|c| c.is_ascii_alphabetic()) && !value.starts_with("//") => {
+                self.pos += qualifier.len() + 1;
+                self.qualified(qualifier, start)?
+            }
+            _ => Condition::Any(self.pattern()?),
+        };
+
+        Ok(Clause { negated, condition })
+    }
+
+    fn qualified(&mut self, qualifier: &str, start: usize) -> Result<Condition, QueryError> {
+        if self.rest().is_empty() || self.rest().starts_with(char::is_whitespace) {
+            return Err(QueryError::MissingValue { qualifier: qualifier.to_string(), offset: start });
+        }
+
+        let condition = match qualifier.to_lowercase().as_str() {
+            "title" | "t" => Condition::Field(Field::Title, self.pattern()?),
+            "user" | "username" | "u" => Condition::Field(Field::Username, self.pattern()?),
+            "url" => Condition::Field(Field::Url, self.pattern()?),
+            "notes" | "n" => Condition::Field(Field::Notes, self.pattern()?),
+            "tag" | "tags" => Condition::Field(Field::Tags, self.pattern()?),
+            "attr" | "a" => Condition::Attribute(self.pattern()?),
+            "group" | "g" => Condition::Group(self.pattern()?),
+            "is" => {
+                let state = self.word();
+                match state.to_lowercase().as_str() {
+                    "expired" => Condition::Expired,
+                    _ => return Err(QueryError::UnknownState { state: state.to_string(), offset: start }),
+                }
+            }
+            _ => return Err(QueryError::UnknownQualifier { qualifier: qualifier.to_string(), offset: start }),
+        };
+
+        Ok(condition)
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, QueryError> {
+        let start = self.pos;
+
+        if let Some(rest) = self.rest().strip_prefix('"') {
+            let end = rest.find('"').ok_or(QueryError::UnterminatedQuote { offset: start })?;
+            let phrase = rest[..end].to_lowercase();
+            self.pos += end + 2;
+
+            return Ok(Pattern::Phrase(phrase));
+        }
+
+        if let Some(rest) = self.rest().strip_prefix('/') {
+            if !self.allow_regex {
+                return Err(QueryError::RegexDisabled { offset: start });
+            }
+
+            // `\/` escapes the delimiter
+            let mut end = None;
+            let mut escaped = false;
+            for (i, c) in rest.char_indices() {
+                match c {
+                    '/' if !escaped => {
+                        end = Some(i);
+                        break;
+                    }
+                    '\\' => escaped = !escaped,
+                    _ => escaped = false,
+                }
+            }
+            let end = end.ok_or(QueryError::UnterminatedRegex { offset: start })?;
+            let regex = RegexBuilder::new(&rest[..end].replace("\\/", "/"))
+                .case_insensitive(true)
+                .build()
+                .map_err(|err| QueryError::InvalidRegex { message: err.to_string(), offset: start })?;
+            self.pos += end + 2;
+
+            return Ok(Pattern::Regex(regex));
+        }
+
+        Ok(Pattern::Text(self.word().to_lowercase()))
+    }
+
+    fn word(&mut self) -> &'a str {
+        let word = self.rest().split(char::is_whitespace).next().unwrap_or_default();
+        self.pos += word.len();
+        word
+    }
+}
+
+// Relevance of a matching entry and the fields that matched, e.g. to highlight them
+#[derive(Debug, Default, Serialize)]
+pub struct SearchMatch {
+    pub score: u32,
+    // field names, user supplied fields by their own name
+    pub fields: Vec<String>,
+}
+
+// How well a pattern matches a value, multiplied by the weight of the field
+const EXACT: u32 = 4;
+const SUBSTRING: u32 = 3;
+const SUBSEQUENCE: u32 = 2;
+const TYPO: u32 = 1;
+
+// title and url host matches rank above everything else, notes and user supplied fields below
+const TITLE_WEIGHT: u32 = 5;
+const HOST_WEIGHT: u32 = 5;
+const FIELD_WEIGHT: u32 = 2;
+const NOTES_WEIGHT: u32 = 1;
+
+// shorter terms match too much when fuzzy
+const FUZZY_MIN_LENGTH: usize = 3;
+
+impl SearchQuery {
+    // None if the entry doesn't match, all clauses have to match.
+    // Negated clauses only exclude exact matches, a fuzzy one would hide entries that merely look alike
+    pub fn evaluate(&self, entry: &Entry, group_path: &str, config: &Search, now: NaiveDateTime) -> Option<SearchMatch> {
+        let mut search_match = SearchMatch::default();
+
+        for clause in &self.clauses {
+            let fuzzy = config.fuzzy && !clause.negated;
+            match (clause.condition.hits(entry, group_path, config, fuzzy, now), clause.negated) {
+                (Some(hits), false) => {
+                    for (field, score) in hits {
+                        search_match.score += score;
+                        if !search_match.fields.contains(&field) {
+                            search_match.fields.push(field);
+                        }
+                    }
+                }
+                (None, true) => {}
+                _ => return None,
+            }
+        }
+
+        Some(search_match)
+    }
+}
+
+impl Condition {
+    // Matching fields with their score, None if the condition doesn't match
+    fn hits(&self, entry: &Entry, group_path: &str, config: &Search, fuzzy: bool, now: NaiveDateTime) -> Option<Vec<(String, u32)>> {
+        let hits = match self {
+            Condition::Any(pattern) => {
+                let mut hits: Vec<(String, u32)> = config.fields.iter().filter_map(|field| field_hit(entry, field, pattern, fuzzy)).collect();
+                if config.extra_fields {
+                    hits.extend(attribute_hits(entry, pattern, fuzzy));
+                }
+                hits
+            }
+            Condition::Field(field, pattern) => field_hit(entry, field, pattern, fuzzy).into_iter().collect(),
+            Condition::Attribute(pattern) => attribute_hits(entry, pattern, fuzzy),
+            // no field to report
+            Condition::Group(pattern) => return pattern.quality(group_path, fuzzy).map(|_| vec![]),
+            Condition::Expired => return (entry.times.expires && entry.times.expiry.is_some_and(|expiry| expiry <= now)).then(Vec::new),
+        };
+
+        Some(hits).filter(|hits| !hits.is_empty())
+    }
+}
+
+impl Pattern {
+    // None if not matching at all
+    fn quality(&self, value: &str, fuzzy: bool) -> Option<u32> {
+        let text = match self {
+            Pattern::Phrase(phrase) => return value.to_lowercase().contains(phrase.as_str()).then_some(SUBSTRING),
+            Pattern::Regex(regex) => return regex.is_match(value).then_some(SUBSTRING),
+            Pattern::Text(text) => text,
+        };
+
+        let value = value.to_lowercase();
+        if value == *text {
+            return Some(EXACT);
+        }
+        if value.contains(text.as_str()) {
+            return Some(SUBSTRING);
+        }
+        if !fuzzy || text.chars().count() < FUZZY_MIN_LENGTH {
+            return None;
+        }
+
+        let words = || value.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty());
+        if words().any(|word| is_subsequence(text, word)) {
+            return Some(SUBSEQUENCE);
+        }
+        // typos: 1 edit for short terms, 2 for longer ones
+        let max_distance = if text.chars().count() < 6 { 1 } else { 2 };
+        if words().any(|word| distance(text, word) <= max_distance) {
+            return Some(TYPO);
+        }
+
+        None
+    }
+}
+
+fn field_name(field: &Field) -> &'static str {
+    match field {
+        Field::Title => "title",
+        Field::Username => "username",
+        Field::Tags => "tags",
+        Field::Notes => "notes",
+        Field::Url => "url",
+    }
+}
+
+fn field_hit(entry: &Entry, field: &Field, pattern: &Pattern, fuzzy: bool) -> Option<(String, u32)> {
+    let score = match field {
+        Field::Title => pattern.quality(entry.title.as_deref()?, fuzzy)? * TITLE_WEIGHT,
+        Field::Username => pattern.quality(entry.username.as_deref()?, fuzzy)? * FIELD_WEIGHT,
+        Field::Notes => pattern.quality(entry.notes.as_deref()?, fuzzy)? * NOTES_WEIGHT,
+        Field::Tags => entry.tags.iter().flatten().filter_map(|tag| pattern.quality(tag, fuzzy)).max()? * FIELD_WEIGHT,
+        Field::Url => {
+            let url = entry.url.as_deref()?;
+            let host = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string));
+            let host_score = host.and_then(|host| pattern.quality(&host, fuzzy)).map(|quality| quality * HOST_WEIGHT);
+
+            host_score.or(pattern.quality(url, fuzzy).map(|quality| quality * FIELD_WEIGHT))?
+        }
+    };
+
+    Some((field_name(field).to_string(), score))
+}
+
+fn attribute_hits(entry: &Entry, pattern: &Pattern, fuzzy: bool) -> Vec<(String, u32)> {
+    entry.strings.iter().flatten()
+        .filter_map(|(k, v)| {
+            let quality = pattern.quality(k, fuzzy).max(pattern.quality(v.as_deref().unwrap_or_default(), fuzzy))?;
+            Some((k.clone(), quality * NOTES_WEIGHT))
+        })
+        .collect()
+}
+
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|c| haystack.any(|h| h == c))
+}
+
+// Optimal string alignment distance: insertions, deletions, substitutions and transpositions of adjacent characters
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // can't be within any threshold we use
+    if a.len().abs_diff(b.len()) > 2 {
+        return usize::MAX;
+    }
+
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            rows[i][j] = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                rows[i][j] = rows[i][j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(pattern: &Pattern) -> &str {
+        match pattern {
+            Pattern::Text(v) | Pattern::Phrase(v) => v,
+            Pattern::Regex(v) => v.as_str(),
+        }
+    }
+
+    #[test]
+    fn parse_query() {
+        let query = parse(r#"user:alice url:github -tag:old "Exact Phrase" group:"Infra Team" is:expired /^web-\d+\/$/ https://example.com -"#, true).unwrap();
+        let clauses: Vec<(bool, String)> = query.clauses.iter().map(|clause| {
+            let condition = match &clause.condition {
+                Condition::Any(p) => format!("any {}", text(p)),
+                Condition::Field(field, p) => format!("{:?} {}", field, text(p)),
+                Condition::Attribute(p) => format!("attr {}", text(p)),
+                Condition::Group(p) => format!("group {}", text(p)),
+                Condition::Expired => "expired".to_string(),
+            };
+            (clause.negated, condition)
+        }).collect();
+
+        assert_eq!(clauses, vec![
+            (false, "Username alice".to_string()),
+            (false, "Url github".to_string()),
+            (true, "Tags old".to_string()),
+            (false, "any exact phrase".to_string()),
+            (false, "group infra team".to_string()),
+            (false, "expired".to_string()),
+            (false, r"any ^web-\d+/$".to_string()),
+            (false, "any https://example.com".to_string()),
+            (false, "any -".to_string()),
+        ]);
+        assert!(parse("  ", false).unwrap().clauses.is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |query: &str| serde_json::to_value(parse(query, true).unwrap_err()).unwrap();
+
+        assert_eq!(error("foo pass:secret"), serde_json::json!({"error": "unknown_qualifier", "qualifier": "pass", "offset": 4}));
+        assert_eq!(error("is:weak")["error"], "unknown_state");
+        assert_eq!(error("title: foo")["error"], "missing_value");
+        assert_eq!(error("\"foo")["error"], "unterminated_quote");
+        assert_eq!(error("/foo")["error"], "unterminated_regex");
+        assert_eq!(error("-/(/")["offset"], 1);
+        assert!(matches!(parse("/foo/", false), Err(QueryError::RegexDisabled { offset: 0 })));
+    }
+
+    #[test]
+    fn match_entry() {
+        let mut entry = keepass::db::Entry::new();
+        for (k, v) in [("Title", "GitHub"), ("UserName", "alice"), ("URL", "https://github.com"), ("Notes", "2FA enabled"), ("Host", "web-01")] {
+            entry.fields.insert(k.to_string(), keepass::db::Value::Unprotected(v.to_string()));
+        }
+        entry.tags = vec!["work".to_string(), "old".to_string()];
+        let now = keepass::db::Times::now();
+        entry.times.expires = true;
+        entry.times.set_expiry(now - chrono::Duration::days(1));
+        let entry: Entry = (&entry).into();
+
+        let config = Search::default();
+        let matches = |query: &str| parse(query, true).unwrap().evaluate(&entry, "Root/Infra", &config, now).is_some();
+
+        assert!(matches(""));
+        assert!(matches("user:ALICE url:github"));
+        assert!(matches("\"2fa enabled\" group:infra is:expired"));
+        assert!(matches("attr:host /^web-\\d+$/"));
+        assert!(!matches("web-01 -tag:old"));
+        assert!(!matches("title:alice"));
+        assert!(!matches("group:/^infra/"));
+
+        let config = Search {
+            extra_fields: false,
+            ..Default::default()
+        };
+        assert!(parse("web-01", false).unwrap().evaluate(&entry, "Root", &config, now).is_none());
+    }
+
+    #[test]
+    fn rank_entry() {
+        let mut entry = keepass::db::Entry::new();
+        for (k, v) in [("Title", "GitHub"), ("URL", "https://github.com/login"), ("Notes", "2FA enabled"), ("Host", "web-01")] {
+            entry.fields.insert(k.to_string(), keepass::db::Value::Unprotected(v.to_string()));
+        }
+        let entry: Entry = (&entry).into();
+        let now = keepass::db::Times::now();
+
+        let evaluate = |query: &str, fuzzy: bool| {
+            let config = Search {
+                fuzzy,
+                ..Default::default()
+            };
+            parse(query, false).unwrap().evaluate(&entry, "Root", &config, now).map(|m| (m.score, m.fields))
+        };
+        let fields = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(evaluate("git", false), Some((15 + 15, fields(&["title", "url"]))));
+        assert_eq!(evaluate("GitHub", false), Some((20 + 15, fields(&["title", "url"]))));
+        assert_eq!(evaluate("login", false), Some((6, fields(&["url"]))));
+        assert_eq!(evaluate("2fa", false), Some((3, fields(&["notes"]))));
+        assert_eq!(evaluate("-gitlab web", false), Some((3, fields(&["Host"]))));
+
+        // transposition
+        assert_eq!(evaluate("gihtub", false), None);
+        assert_eq!(evaluate("gihtub", true), Some((5 + 5, fields(&["title", "url"]))));
+        assert_eq!(evaluate("ghb", true), Some((10 + 10, fields(&["title", "url"]))));
+        assert_eq!(evaluate("enabld", true), Some((2, fields(&["notes"]))));
+        // a title typo ranks above an exact match in the notes
+        assert!(evaluate("enabled", true).unwrap().0 < evaluate("gihtub", true).unwrap().0);
+        assert_eq!(evaluate("\"gihtub\"", true), None);
+        // negation stays exact
+        assert_eq!(evaluate("-gihtub", true), Some((0, fields(&[]))));
+        assert_eq!(evaluate("-github", true), None);
+        assert_eq!(evaluate("gb", true), None);
+    }
+}
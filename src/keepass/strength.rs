@@ -0,0 +1,63 @@
+use anyhow::Result;
+use serde::Serialize;
+use zxcvbn::zxcvbn;
+
+#[derive(Serialize)]
+pub struct CrackTimes {
+    pub online_throttled: String,
+    pub online_unthrottled: String,
+    pub offline_slow_hash: String,
+    pub offline_fast_hash: String,
+}
+
+// Strength estimate of a value, never contains the value itself
+#[derive(Serialize)]
+pub struct Strength {
+    // 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    pub guesses: u64,
+    pub guesses_log10: f64,
+    pub crack_times: CrackTimes,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+// Estimates the strength of a password with zxcvbn (dictionary, keyboard pattern, repeat, date, ... matching).
+// `user_inputs` are other values of the entry an attacker likely knows, e.g. title or username
+pub(crate) fn estimate(password: &str, user_inputs: &[&str]) -> Result<Strength> {
+    let entropy = zxcvbn(password, user_inputs)?;
+    let times = entropy.crack_times();
+    let feedback = entropy.feedback().as_ref();
+
+    Ok(
+        Strength {
+            score: entropy.score(),
+            guesses: entropy.guesses(),
+            guesses_log10: entropy.guesses_log10(),
+            crack_times: CrackTimes {
+                online_throttled: times.online_throttling_100_per_hour().to_string(),
+                online_unthrottled: times.online_no_throttling_10_per_second().to_string(),
+                offline_slow_hash: times.offline_slow_hashing_1e4_per_second().to_string(),
+                offline_fast_hash: times.offline_fast_hashing_1e10_per_second().to_string(),
+            },
+            warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+            suggestions: feedback.map(|f| f.suggestions().iter().map(|s| s.to_string()).collect()).unwrap_or_default(),
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strength() {
+        let weak = estimate("password", &[]).unwrap();
+        assert_eq!(weak.score, 0);
+        assert!(weak.warning.is_some());
+
+        assert_eq!(estimate("johndoe", &["johndoe"]).unwrap().score, 0);
+        assert_eq!(estimate("x7#Lq9!vR2@mZp4$wK8&", &[]).unwrap().score, 4);
+        assert!(estimate("", &[]).is_err());
+    }
+}
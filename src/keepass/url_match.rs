@@ -0,0 +1,154 @@
+use std::net::IpAddr;
+use std::sync::LazyLock;
+
+use anyhow::{anyhow, Result};
+use keepass::db::{Entry, Group, Node};
+use publicsuffix::{List, Psl};
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::url_match::Strictness;
+use crate::keepass::keepass::URL;
+
+// https://publicsuffix.org/list/public_suffix_list.dat, including private domains like github.io
+static PUBLIC_SUFFIX_LIST: LazyLock<List> = LazyLock::new(|| {
+    include_str!("public_suffix_list.dat").parse().expect("bundled public suffix list is invalid")
+});
+
+// additional URLs as stored by KeePass2Android/KeePassXC and the legacy KeePassHttp fields
+const ADDITIONAL_URL_PREFIXES: [&str; 2] = ["KP2A_URL", "KPH:"];
+
+// Entries outside the recycle bin with a URL matching the host, best matches first
+pub(crate) fn find_entries<'a>(root: &'a Group, url: &str, strictness: Strictness, recycle_bin: Option<Uuid>) -> Result<Vec<&'a Entry>> {
+    let host = host(url).ok_or(anyhow!("no host in url"))?;
+
+    let mut found = vec![];
+    find_in_group(root, &host, strictness, recycle_bin, &mut found);
+    // stable, ties keep the database order
+    found.sort_by_key(|(level, _)| *level);
+
+    Ok(found.into_iter().map(|(_, entry)| entry).collect())
+}
+
+fn find_in_group<'a>(group: &'a Group, host: &str, strictness: Strictness, recycle_bin: Option<Uuid>, found: &mut Vec<(Strictness, &'a Entry)>) {
+    for node in &group.children {
+        match node {
+            Node::Group(child) if Some(child.uuid) == recycle_bin => {}
+            Node::Group(child) => find_in_group(child, host, strictness, recycle_bin, found),
+            Node::Entry(entry) => {
+                let level = entry_urls(entry)
+                    .filter_map(self::host)
+                    .filter_map(|entry_host| match_level(host, &entry_host))
+                    .min();
+                if let Some(level) = level.filter(|level| level <= &strictness) {
+                    found.push((level, entry));
+                }
+            }
+        }
+    }
+}
+
+fn entry_urls(entry: &Entry) -> impl Iterator<Item=&str> {
+    entry.fields.keys()
+        .filter(|name| name.as_str() == URL || ADDITIONAL_URL_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+        .filter_map(|name| entry.get(name))
+        .filter(|url| !url.trim().is_empty())
+}
+
+// Lowercase host of the url, urls without a scheme (e.g. `example.com/login`) are accepted as well
+pub(crate) fn host(url: &str) -> Option<String> {
+    let url = url.trim();
+    let parsed = Url::parse(url).ok()
+        .filter(|url| url.has_host())
+        .or_else(|| Url::parse(&format!("https://{}", url)).ok())?;
+
+    Some(parsed.host_str()?.trim_end_matches('.').to_lowercase()).filter(|host| !host.is_empty())
+}
+
+// The strictest level at which the hosts match, None if they don't match at all
+fn match_level(host: &str, entry_host: &str) -> Option<Strictness> {
+    if host == entry_host {
+        return Some(Strictness::Exact);
+    }
+    if is_ip(host) || is_ip(entry_host) {
+        return None;
+    }
+
+    // the entry host has to be a registrable domain at least, so an entry for `co.uk` doesn't match every site below
+    let entry_domain = registrable_domain(entry_host)?;
+    if host.strip_suffix(entry_host).is_some_and(|rest| rest.ends_with('.')) {
+        return Some(Strictness::Subdomain);
+    }
+    if registrable_domain(host) == Some(entry_domain) {
+        return Some(Strictness::Domain);
+    }
+
+    None
+}
+
+fn is_ip(host: &str) -> bool {
+    host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().is_ok()
+}
+
+fn registrable_domain(host: &str) -> Option<&str> {
+    let domain = PUBLIC_SUFFIX_LIST.domain(host.as_bytes())?;
+    // the domain is a suffix of the host
+    Some(&host[host.len() - domain.as_bytes().len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts() {
+        assert_eq!(host("https://Login.Example.com:8443/path?q=1").as_deref(), Some("login.example.com"));
+        assert_eq!(host("example.com/login").as_deref(), Some("example.com"));
+        assert_eq!(host("localhost:8080").as_deref(), Some("localhost"));
+        assert_eq!(host(" "), None);
+
+        assert_eq!(registrable_domain("www.example.co.uk"), Some("example.co.uk"));
+        assert_eq!(registrable_domain("alice.github.io"), Some("alice.github.io"));
+        assert_eq!(registrable_domain("co.uk"), None);
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(match_level("example.com", "example.com"), Some(Strictness::Exact));
+        assert_eq!(match_level("login.example.com", "example.com"), Some(Strictness::Subdomain));
+        assert_eq!(match_level("example.com", "login.example.com"), Some(Strictness::Domain));
+        assert_eq!(match_level("www.example.com", "login.example.com"), Some(Strictness::Domain));
+        assert_eq!(match_level("badexample.com", "example.com"), None);
+        assert_eq!(match_level("example.co.uk", "other.co.uk"), None);
+        assert_eq!(match_level("example.co.uk", "co.uk"), None);
+        assert_eq!(match_level("bob.github.io", "alice.github.io"), None);
+        assert_eq!(match_level("192.168.0.1", "192.168.0.1"), Some(Strictness::Exact));
+        assert_eq!(match_level("10.0.0.1", "0.0.1"), None);
+    }
+
+    #[test]
+    fn entries() {
+        let mut root = Group::new("Root");
+        let mut add = |fields: &[(&str, &str)]| {
+            let mut entry = Entry::new();
+            for (k, v) in fields {
+                entry.fields.insert(k.to_string(), keepass::db::Value::Unprotected(v.to_string()));
+            }
+            let id = entry.uuid;
+            root.children.push(Node::Entry(entry));
+            id
+        };
+        let domain = add(&[(URL, "https://www.example.com")]);
+        let exact = add(&[(URL, "https://other.org"), ("KP2A_URL_1", "login.example.com")]);
+        let legacy = add(&[("KPH: url", "example.com")]);
+        add(&[(URL, "https://example.org"), ("Notes", "login.example.com")]);
+
+        let find = |url: &str, strictness: Strictness| -> Vec<Uuid> {
+            find_entries(&root, url, strictness, None).unwrap().iter().map(|entry| entry.uuid).collect()
+        };
+        assert_eq!(find("https://login.example.com/auth", Strictness::Domain), vec![exact, legacy, domain]);
+        assert_eq!(find("https://login.example.com/auth", Strictness::Subdomain), vec![exact, legacy]);
+        assert_eq!(find("https://login.example.com/auth", Strictness::Exact), vec![exact]);
+        assert!(find_entries(&root, "", Strictness::Domain, None).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use clap::Parser;
 
+use crate::cli::Command;
 use crate::config::config::Config;
 use crate::server::server::Server;
 
@@ -9,7 +10,13 @@ mod config;
 mod server;
 mod auth;
 mod keepass;
+mod generator;
 mod session;
+mod token;
+mod file_store;
+mod two_factor;
+mod webauthn;
+mod cli;
 
 const CONFIG_FILE: &str = "config.yml";
 
@@ -19,6 +26,8 @@ const CONFIG_FILE: &str = "config.yml";
 struct Args {
     #[arg(short, long, default_value = CONFIG_FILE)]
     config: std::path::PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
 #[actix_web::main]
@@ -26,6 +35,14 @@ async fn main() {
     let args = Args::parse();
     let config = Config::from_file(args.config).expect("Failed to parse config");
 
+    if let Some(command) = args.command {
+        if let Err(err) = cli::run(&config, command) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     Server::new(config)
         .await.expect("Failed to start server")
 }
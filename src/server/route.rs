@@ -9,20 +9,70 @@ use crate::server::route::auth::{
     close_db,
     db_login,
     logout,
+    two_factor_login,
     user_login,
 };
+use crate::server::route::generator::{
+    generate_passphrase,
+    generate_password,
+    generator_profiles,
+};
 use crate::server::route::keepass::{
+    breach_report,
+    create_entry,
+    create_group,
+    delete_entry,
+    delete_group,
+    diff_entry_revisions,
+    empty_recycle_bin,
+    entries_for_url,
+    expiring_entries,
+    get_breach_count,
     get_entry,
+    get_entry_history,
+    get_entry_revision,
     get_file,
     get_group_entries,
     get_groups,
     get_icon,
+    get_password_strength,
     get_protected,
+    get_totp,
+    health_report,
+    move_node,
+    restore_entry_revision,
+    restore_node,
     search_entries,
+    update_entry,
+    update_group,
+};
+use crate::server::route::passkey::{
+    passkey_login_finish,
+    passkey_login_start,
+    passkey_register_finish,
+    passkey_register_start,
+    passkey_remove,
+    passkey_verify_start,
+    passkeys,
+};
+use crate::server::route::token::{
+    create_token,
+    revoke_token,
+    tokens,
+};
+use crate::server::route::two_factor::{
+    two_factor_confirm,
+    two_factor_disable,
+    two_factor_enroll,
+    two_factor_status,
 };
 
 pub mod auth;
+pub mod generator;
 pub mod keepass;
+pub mod passkey;
+pub mod token;
+pub mod two_factor;
 pub mod util;
 
 pub const API_PATH: &str = "/api/v1";
@@ -35,6 +85,9 @@ pub fn setup_routes(cfg: &mut web::ServiceConfig) {
             // auth
             .service(authenticated)
             .service(user_login)
+            .service(two_factor_login)
+            .service(passkey_login_start)
+            .service(passkey_login_finish)
             .service(backend_login)
             .service(db_login)
             .service(close_db)
@@ -43,11 +96,54 @@ pub fn setup_routes(cfg: &mut web::ServiceConfig) {
             // keepass
             .service(get_groups)
             .service(get_group_entries)
+            .service(expiring_entries)
+            .service(entries_for_url)
+            .service(health_report)
+            .service(breach_report)
             .service(get_entry)
+            .service(get_entry_history)
+            .service(get_entry_revision)
+            .service(diff_entry_revisions)
             .service(get_protected)
+            .service(get_password_strength)
+            .service(get_breach_count)
+            .service(get_totp)
             .service(get_file)
             .service(search_entries)
             .service(get_icon)
+            .service(create_entry)
+            .service(update_entry)
+            .service(delete_entry)
+            .service(restore_entry_revision)
+            .service(create_group)
+            .service(update_group)
+            .service(move_node)
+            .service(delete_group)
+            .service(restore_node)
+            .service(empty_recycle_bin)
+
+            // generator
+            .service(generator_profiles)
+            .service(generate_password)
+            .service(generate_passphrase)
+
+            // tokens
+            .service(tokens)
+            .service(create_token)
+            .service(revoke_token)
+
+            // second factor
+            .service(two_factor_status)
+            .service(two_factor_enroll)
+            .service(two_factor_confirm)
+            .service(two_factor_disable)
+
+            // passkeys
+            .service(passkeys)
+            .service(passkey_register_start)
+            .service(passkey_register_finish)
+            .service(passkey_verify_start)
+            .service(passkey_remove)
         )
 
         .service(callback_user_auth)
@@ -58,6 +154,10 @@ pub fn setup_routes(cfg: &mut web::ServiceConfig) {
         .route("/user_login", web::get().to(index))
         .route("/backend_login", web::get().to(index))
         .route("/db_login", web::get().to(index))
+        .route("/two_factor_login", web::get().to(index))
+        .route("/tokens", web::get().to(index))
+        .route("/two_factor", web::get().to(index))
+        .route("/passkeys", web::get().to(index))
         .service(fs::Files::new(STATIC_PATH, "public"))
     ;
 }
@@ -7,14 +7,17 @@ use serde::Serialize;
 use serde_json::json;
 
 use crate::{auth_backend, db_backend};
-use crate::auth::{BackendLogin, DbLogin, SESSION_KEY_USER, UserLogin};
-use crate::auth_backend::{AuthCache, SESSION_KEY_AUTH_STATE, UserInfo};
+use crate::auth::{BackendLogin, DbLogin, SESSION_KEY_TWO_FACTOR, UserLogin};
+use crate::auth_backend::{AuthCache, LoginType, SESSION_KEY_AUTH_STATE, UserInfo};
+use crate::auth_backend::header::UntrustedProxyError;
 use crate::config::config::Config;
 use crate::keepass::db_cache::DbCache;
 use crate::keepass::keepass::KeePass;
 use crate::server::route::INDEX_FILE;
-use crate::server::route::util::{_close_db, check_user_session, db_is_open, revoke_key, set_user_session, store_key};
+use crate::server::route::util::{_close_db, check_user_session, db_is_open, get_user_info, revoke_key, set_user_session, store_key};
 use crate::session::AuthSession;
+use crate::two_factor::{InvalidCodeError, PendingLogin, TooManyAttemptsError, TwoFactorCode, TwoFactorStore};
+use crate::webauthn::PasskeyStore;
 
 #[derive(Serialize)]
 struct Settings {
@@ -27,6 +30,23 @@ struct Settings {
 struct SessionData {
     csrf_token: String,
     settings: Settings,
+    // only returned once, when the second factor was enrolled on login
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recovery_codes: Option<Vec<String>>,
+}
+
+impl SessionData {
+    fn new(csrf_token: String, user_info: UserInfo, config: &Config) -> Self {
+        Self {
+            csrf_token,
+            settings: Settings {
+                cn: user_info.name,
+                timeout: config.db_session_timeout.as_secs(),
+                interval: config.auth_check_interval.as_secs(),
+            },
+            recovery_codes: None,
+        }
+    }
 }
 
 #[get("/authenticated")]
@@ -54,15 +74,37 @@ async fn authenticated(session: Session, config: Data<Config>, db_cache: Data<Db
 
 
 #[post("/user_login")]
-async fn user_login(session: Session, config: Data<Config>, params: web::Form<UserLogin>) -> impl Responder {
+async fn user_login(
+    request: HttpRequest,
+    session: Session,
+    config: Data<Config>,
+    auth_cache: Data<AuthCache>,
+    two_factor_store: Data<TwoFactorStore>,
+    passkey_store: Data<PasskeyStore>,
+    params: web::Form<UserLogin>,
+) -> impl Responder {
     if let Err(err) = check_user_session(&session, &params.username) {
         return err;
     }
 
     let auth_backend = auth_backend::new(&config);
     // TODO: differentiate between real error and login failed
-    let user_info = match auth_backend.login(params.username.as_str(), params.password.as_str()).await {
+    let result = match auth_backend.request_login(&request) {
+        Ok(Some(user_info)) => Ok(user_info),
+        Ok(None) => auth_backend.login(params.username.as_str(), params.password.as_str()).await,
+        Err(err) => Err(err),
+    };
+    let user_info = match result {
         Ok(user_info) => user_info,
+        Err(err) if err.downcast_ref::<UntrustedProxyError>().is_some() => {
+            info!("user login from '{}': {}: {:?}", params.username, err, request.peer_addr());
+            return HttpResponse::Forbidden().json(json!(
+                {
+                    "success": false,
+                    "message": "request not from a trusted proxy",
+                }
+            ));
+        }
         Err(err) => {
             info!("user login from '{}': {}", params.username, err);
             return HttpResponse::Unauthorized().json(json!(
@@ -74,6 +116,151 @@ async fn user_login(session: Session, config: Data<Config>, params: web::Form<Us
         }
     };
 
+    // the second factor only applies to backends that check a password
+    let host = format!("{}://{}", request.connection_info().scheme(), request.connection_info().host());
+    let second_factor = config.two_factor.enabled() || config.webauthn.enabled();
+    if second_factor && matches!(auth_backend.get_login_type(&host, &auth_cache), Ok(LoginType::Mask)) {
+        if let Some(resp) = start_two_factor(&session, &config, &two_factor_store, &passkey_store, &user_info).await {
+            return resp;
+        }
+    }
+
+    finish_user_login(session, &config, user_info, None)
+}
+
+
+#[post("/two_factor_login")]
+async fn two_factor_login(session: Session, config: Data<Config>, two_factor_store: Data<TwoFactorStore>, params: web::Form<TwoFactorCode>) -> impl Responder {
+    let pending = match session.get_key::<PendingLogin>(SESSION_KEY_TWO_FACTOR) {
+        Some(v) if !v.is_expired() => v,
+        _ => {
+            session.remove(SESSION_KEY_TWO_FACTOR);
+            return HttpResponse::Unauthorized().json(json!(
+                {
+                    "success": false,
+                    "message": "login expired, please log in again",
+                }
+            ));
+        }
+    };
+    let username = pending.user.id.clone();
+    if !pending.totp && pending.enrollment.is_none() {
+        return HttpResponse::BadRequest().json(json!(
+            {
+                "success": false,
+                "message": "no authenticator app enrolled, use a passkey",
+            }
+        ));
+    }
+
+    let result = match &pending.enrollment {
+        Some(enrollment) => two_factor_store.confirm(&config.two_factor, &username, enrollment, &params.code).await.map(Some),
+        None => two_factor_store.verify(&config.two_factor, &username, &params.code).await.map(|_| None),
+    };
+    let recovery_codes = match result {
+        Ok(v) => v,
+        Err(err) => {
+            info!("user login from '{}': second factor: {}", username, err);
+            if err.downcast_ref::<TooManyAttemptsError>().is_some() {
+                // start over with the password
+                session.remove(SESSION_KEY_TWO_FACTOR);
+                return HttpResponse::TooManyRequests().json(json!(
+                    {
+                        "success": false,
+                        "message": err.to_string(),
+                    }
+                ));
+            }
+            if err.downcast_ref::<InvalidCodeError>().is_some() {
+                return HttpResponse::Unauthorized().json(json!(
+                    {
+                        "success": false,
+                        "message": "invalid code",
+                    }
+                ));
+            }
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to verify code",
+                }
+            ));
+        }
+    };
+
+    session.remove(SESSION_KEY_TWO_FACTOR);
+    finish_user_login(session, &config, pending.user, recovery_codes)
+}
+
+// Keeps the user as pending if a second factor (TOTP or passkey) is enrolled or required.
+// Returns the response to send, None if the user can be logged in right away
+async fn start_two_factor(
+    session: &Session,
+    config: &Config,
+    store: &TwoFactorStore,
+    passkey_store: &PasskeyStore,
+    user_info: &UserInfo,
+) -> Option<HttpResponse> {
+    let err_resp = |msg: &str| HttpResponse::InternalServerError().json(json!(
+        {
+            "success": false,
+            "message": msg,
+        }
+    ));
+
+    let totp = match config.two_factor.enabled() {
+        true => store.is_enrolled(&config.two_factor, &user_info.id).await,
+        false => Ok(false),
+    };
+    let passkey = match config.webauthn.enabled() {
+        true => passkey_store.list(&config.webauthn, &user_info.id).await.map(|passkeys| !passkeys.is_empty()),
+        false => Ok(false),
+    };
+    let (totp, passkey) = match (totp, passkey) {
+        (Ok(totp), Ok(passkey)) => (totp, passkey),
+        (Err(err), _) | (_, Err(err)) => {
+            error!("user login from '{}': {}", user_info.id, err);
+            return Some(err_resp("failed to retrieve second factor"));
+        }
+    };
+    if !totp && !passkey && !config.two_factor.required {
+        return None;
+    }
+
+    let enrollment = match totp || passkey {
+        true => None,
+        false => match store.enroll(&config.two_factor, &user_info.id) {
+            Ok(v) => Some(v),
+            Err(err) => {
+                error!("user login from '{}': {}", user_info.id, err);
+                return Some(err_resp("failed to enroll second factor"));
+            }
+        },
+    };
+
+    let pending = PendingLogin::new(user_info.clone(), totp, passkey, enrollment);
+    if let Err(err) = session.insert(SESSION_KEY_TWO_FACTOR, &pending) {
+        error!("user login from '{}': {}", user_info.id, err);
+        return Some(err_resp("failed to set user session"));
+    }
+
+    info!("user login from '{}': second factor required", user_info.id);
+    Some(HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": {
+                "two_factor": {
+                    "enroll": pending.enrollment,
+                    "totp": pending.totp,
+                    "passkey": pending.passkey,
+                },
+            },
+        }
+    )))
+}
+
+pub(crate) fn finish_user_login(session: Session, config: &Config, user_info: UserInfo, recovery_codes: Option<Vec<String>>) -> HttpResponse {
+    let username = user_info.id.clone();
     let csrf_token = match set_user_session(session, &user_info) {
         Ok(v) => v,
         Err(err) => return HttpResponse::InternalServerError().json(json!(
@@ -84,24 +271,18 @@ async fn user_login(session: Session, config: Data<Config>, params: web::Form<Us
         )),
     };
 
-
-    info!("user login from '{}': successful", params.username);
+    info!("user login from '{}': successful", username);
     HttpResponse::Ok().json(json!(
         {
             "success": true,
             "data": SessionData {
-                csrf_token,
-                settings: Settings {
-                    cn: user_info.name,
-                    timeout: config.db_session_timeout.as_secs(),
-                    interval: config.auth_check_interval.as_secs(),
-                }
-            }
+                recovery_codes,
+                ..SessionData::new(csrf_token, user_info, config)
+            },
         }
     ))
 }
 
-
 #[post("/backend_login")]
 async fn backend_login(session: Session, config: Data<Config>, params: web::Form<BackendLogin>) -> impl Responder {
     let username = session.get_user_id();
@@ -218,24 +399,6 @@ async fn db_login(session: Session, config: Data<Config>, db_cache: Data<DbCache
     ))
 }
 
-fn get_user_info(session: &Session) -> Result<UserInfo, HttpResponse> {
-    let resp = HttpResponse::InternalServerError().json(json!(
-        {
-            "success": true,
-            "message": "failed to retrieve session",
-        }
-    ));
-    let user_info = match session.get::<UserInfo>(SESSION_KEY_USER) {
-        Err(err) => {
-            error!("failed to retrieve session: {}", err);
-            return Err(resp);
-        }
-        Ok(Some(v)) => v,
-        Ok(None) => return Err(resp),
-    };
-    Ok(user_info)
-}
-
 #[post("/close_db")]
 async fn close_db(session: Session, config: Data<Config>, db_cache: Data<DbCache>) -> impl Responder {
     if let Err(err) = _close_db(&session, &config, &db_cache).await {
@@ -327,16 +490,7 @@ async fn callback_user_auth(
 
     info!("user login from '{}': successful", &user_info.id);
 
-    embed_in_index(true, None, Some(
-        SessionData {
-            csrf_token,
-            settings: Settings {
-                cn: user_info.name,
-                timeout: config.db_session_timeout.as_secs(),
-                interval: config.auth_check_interval.as_secs(),
-            },
-        }
-    )).await
+    embed_in_index(true, None, Some(SessionData::new(csrf_token, user_info, &config))).await
 }
 
 // TODO: fix this:w
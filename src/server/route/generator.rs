@@ -0,0 +1,73 @@
+use actix_session::Session;
+use actix_web::{get, HttpResponse, post, Responder, web};
+use actix_web::web::Data;
+use log::info;
+use serde_json::json;
+
+use crate::config::config::Config;
+use crate::generator::{passphrase, password, PassphraseOptions, PasswordOptions};
+use crate::session::AuthSession;
+
+#[get("/generator_profiles")]
+async fn generator_profiles(config: Data<Config>) -> impl Responder {
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": {
+                "default": config.generator.default,
+                "profiles": config.generator.profiles,
+                "passphrase": config.generator.passphrase,
+                "wordlists": passphrase::wordlists(&config.generator),
+            },
+        }
+    ))
+}
+
+#[post("/generate_password")]
+async fn generate_password(session: Session, config: Data<Config>, params: web::Json<PasswordOptions>) -> impl Responder {
+    let username = session.get_user_id();
+    let generated = match params.profile(&config.generator).and_then(|profile| password::generate(&profile)) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to generate password: {}", username, err);
+            return HttpResponse::BadRequest().json(json!(
+                {
+                    "success": false,
+                    "message": format!("failed to generate password: {}", err),
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": generated,
+        }
+    ))
+}
+
+#[post("/generate_passphrase")]
+async fn generate_passphrase(session: Session, config: Data<Config>, params: web::Json<PassphraseOptions>) -> impl Responder {
+    let username = session.get_user_id();
+    let profile = params.profile(&config.generator);
+    let generated = match passphrase::generate(&profile, &config.generator).await {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to generate passphrase: {}", username, err);
+            return HttpResponse::BadRequest().json(json!(
+                {
+                    "success": false,
+                    "message": format!("failed to generate passphrase: {}", err),
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": generated,
+        }
+    ))
+}
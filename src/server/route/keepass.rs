@@ -1,14 +1,16 @@
 use actix_session::Session;
-use actix_web::{get, HttpResponse, Responder, web};
+use actix_web::{get, HttpResponse, post, Responder, web};
+use actix_web::http::header::ContentDisposition;
 use actix_web::web::Data;
 use log::info;
-use mime::IMAGE_PNG;
+use mime::{APPLICATION_OCTET_STREAM, IMAGE_PNG};
 use serde_json::json;
 use secrecy::ExposeSecret;
 
 use crate::config::config::Config;
 use crate::keepass::db_cache::DbCache;
-use crate::keepass::keepass::{File, Id, Protected, SearchTerm};
+use crate::keepass::keepass::{EntryUpdate, File, GroupEntries, GroupUpdate, Id, Move, NewEntry, NewGroup, NotFoundError, Protected, Restore, RevisionDiff, RevisionId, SearchTerm, UrlLookup, Within};
+use crate::keepass::query::QueryError;
 use crate::server::route::util;
 use crate::session::AuthSession;
 
@@ -45,7 +47,7 @@ async fn get_groups(session: Session, config: Data<Config>, db_cache: Data<DbCac
 }
 
 #[get("/get_group_entries")]
-async fn get_group_entries(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<Id>) -> impl Responder {
+async fn get_group_entries(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<GroupEntries>) -> impl Responder {
     let keepass = match util::get_db(&session, &config, &db_cache).await {
         Ok(v) => v,
         Err(err) => return err,
@@ -73,6 +75,122 @@ async fn get_group_entries(session: Session, config: Data<Config>, db_cache: Dat
     ))
 }
 
+#[get("/entries_for_url")]
+async fn entries_for_url(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<UrlLookup>) -> impl Responder {
+    let keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let entries = match keepass.entries_for_url(&params) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to get entries for url '{}': {}", username, params.url, err);
+            return HttpResponse::BadRequest().json(json!(
+                {
+                    "success": false,
+                    "message": format!("failed to get entries for url: {}", err),
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": entries,
+        }
+    ))
+}
+
+#[get("/expiring_entries")]
+async fn expiring_entries(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<Within>) -> impl Responder {
+    let keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let groups = match keepass.expiring_entries(&params) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to get expiring entries: {}", username, err);
+            return HttpResponse::BadRequest().json(json!(
+                {
+                    "success": false,
+                    "message": format!("failed to get expiring entries: {}", err),
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": groups,
+        }
+    ))
+}
+
+#[get("/health_report")]
+async fn health_report(session: Session, config: Data<Config>, db_cache: Data<DbCache>) -> impl Responder {
+    let keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let report = match keepass.health_report().await {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to create health report: {}", username, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to create health report",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": report,
+        }
+    ))
+}
+
+#[get("/breach_report")]
+async fn breach_report(session: Session, config: Data<Config>, db_cache: Data<DbCache>) -> impl Responder {
+    let keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let report = match keepass.breach_report().await {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to create breach report: {}", username, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to create breach report",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": report,
+        }
+    ))
+}
+
 #[get("/get_entry")]
 async fn get_entry(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<Id>) -> impl Responder {
     let keepass = match util::get_db(&session, &config, &db_cache).await {
@@ -102,6 +220,103 @@ async fn get_entry(session: Session, config: Data<Config>, db_cache: Data<DbCach
     ))
 }
 
+#[get("/get_entry_history")]
+async fn get_entry_history(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<Id>) -> impl Responder {
+    let keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let revisions = match keepass.get_entry_history(&params) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to get history of entry '{}': {}", username, params.id, err);
+            return modify_error(err, "failed to get entry history");
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": revisions,
+        }
+    ))
+}
+
+#[get("/get_entry_revision")]
+async fn get_entry_revision(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<RevisionId>) -> impl Responder {
+    let keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let entry = match keepass.get_entry_revision(&params) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to get revision {} of entry '{}': {}", username, params.revision, params.id, err);
+            return modify_error(err, "failed to get entry revision");
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": entry,
+        }
+    ))
+}
+
+#[get("/diff_entry_revisions")]
+async fn diff_entry_revisions(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<RevisionDiff>) -> impl Responder {
+    let keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let diff = match keepass.diff_entry_revisions(&params) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to diff revisions of entry '{}': {}", username, params.id, err);
+            return modify_error(err, "failed to diff entry revisions");
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": diff,
+        }
+    ))
+}
+
+#[post("/restore_entry_revision")]
+async fn restore_entry_revision(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<RevisionId>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    if let Err(err) = keepass.restore_entry_revision(&params) {
+        info!("{}: failed to restore revision {} of entry '{}': {}", username, params.revision, params.id, err);
+        return modify_error(err, "failed to restore entry revision");
+    }
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: restored revision {} of entry '{}'", username, params.revision, params.id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
 #[get("/get_protected")]
 async fn get_protected(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<Protected>) -> impl Responder {
     let keepass = match util::get_db(&session, &config, &db_cache).await {
@@ -110,7 +325,7 @@ async fn get_protected(session: Session, config: Data<Config>, db_cache: Data<Db
     };
 
     let username = session.get_user_id();
-    let protected = match keepass.get_protected(&params) {
+    let (protected, resolved) = match keepass.get_protected(&params) {
         Ok(v) => v,
         Err(err) => {
             info!("{}: failed to get protected '{}' of entry '{}': {}", username, params.name, params.entry_id, err);
@@ -126,7 +341,102 @@ async fn get_protected(session: Session, config: Data<Config>, db_cache: Data<Db
     HttpResponse::Ok().json(json!(
         {
             "success": true,
-            "data": protected.expose_secret(),
+            "data": {
+                "value": protected.expose_secret(),
+                "resolved": resolved.as_ref().map(ExposeSecret::expose_secret),
+            },
+        }
+    ))
+}
+
+#[get("/get_password_strength")]
+async fn get_password_strength(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<Protected>) -> impl Responder {
+    let keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let strength = match keepass.get_password_strength(&params) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to estimate strength of '{}' of entry '{}': {}", username, params.name, params.entry_id, err);
+            return HttpResponse::BadRequest().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to estimate password strength",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": strength,
+        }
+    ))
+}
+
+#[get("/get_breach_count")]
+async fn get_breach_count(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Query<Protected>) -> impl Responder {
+    let keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let count = match keepass.get_breach_count(&params).await {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to check '{}' of entry '{}' for breaches: {}", username, params.name, params.entry_id, err);
+            return HttpResponse::BadRequest().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to check for breaches",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": {
+                "breached": count > 0,
+                "count": count,
+            },
+        }
+    ))
+}
+
+#[post("/get_totp")]
+async fn get_totp(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<Id>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let (code, modified) = match keepass.get_totp(&params) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to get otp code of entry '{}': {}", username, params.id, err);
+            return modify_error(err, "failed to get otp code");
+        }
+    };
+
+    // don't hand out a HOTP code before the counter was saved, it would be generated again
+    if modified {
+        if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+            return err;
+        }
+    }
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": code,
         }
     ))
 }
@@ -143,16 +453,28 @@ async fn get_file(session: Session, config: Data<Config>, db_cache: Data<DbCache
         Ok(v) => v,
         Err(err) => {
             info!("{}: failed to get file '{}' of entry '{}': {}", username, params.filename, params.entry_id, err);
-            return HttpResponse::InternalServerError().json(json!(
+            let resp = json!(
                 {
                     "success": false,
                     "message": "failed to get file",
                 }
-            ));
+            );
+            return match err.downcast_ref::<NotFoundError>() {
+                Some(_) => HttpResponse::NotFound().json(resp),
+                None => HttpResponse::InternalServerError().json(resp),
+            };
         }
     };
 
-    HttpResponse::Ok().body(file)
+    let content_type = match infer::get(&file) {
+        Some(v) => v.mime_type(),
+        None => APPLICATION_OCTET_STREAM.as_ref(),
+    };
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition::attachment(params.filename.as_str()))
+        .body(file)
 }
 
 #[get("/search_entries")]
@@ -168,17 +490,30 @@ async fn search_entries(session: Session, config: Data<Config>, db_cache: Data<D
         Err(err) => {
             info!("{}: failed to search entries for term '{}': {}", username, params.term, err);
 
-            let mut msg = "failed to search entries".to_string();
-            if err.downcast_ref::<regex::Error>().is_some() {
-                msg = format!("failed to search entries: {}", err);
+            if let Some(not_found) = err.downcast_ref::<NotFoundError>() {
+                return HttpResponse::NotFound().json(json!(
+                    {
+                        "success": false,
+                        "message": format!("failed to search entries: {}", not_found),
+                    }
+                ));
             }
 
-            return HttpResponse::InternalServerError().json(json!(
-                {
-                    "success": false,
-                    "message": msg,
-                }
-            ));
+            return match err.downcast_ref::<QueryError>() {
+                Some(query_err) => HttpResponse::BadRequest().json(json!(
+                    {
+                        "success": false,
+                        "message": format!("invalid search query: {}", query_err),
+                        "error": query_err,
+                    }
+                )),
+                None => HttpResponse::InternalServerError().json(json!(
+                    {
+                        "success": false,
+                        "message": "failed to search entries",
+                    }
+                )),
+            };
         }
     };
 
@@ -190,6 +525,261 @@ async fn search_entries(session: Session, config: Data<Config>, db_cache: Data<D
     ))
 }
 
+#[post("/create_entry")]
+async fn create_entry(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<NewEntry>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let id = match keepass.create_entry(&params) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to create entry in group '{}': {}", username, params.group_id, err);
+            return modify_error(err, "failed to create entry");
+        }
+    };
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: created entry '{}'", username, id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": {
+                "id": id,
+            },
+        }
+    ))
+}
+
+#[post("/update_entry")]
+async fn update_entry(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<EntryUpdate>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    if let Err(err) = keepass.update_entry(&params) {
+        info!("{}: failed to update entry '{}': {}", username, params.id, err);
+        return modify_error(err, "failed to update entry");
+    }
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: updated entry '{}'", username, params.id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
+#[post("/delete_entry")]
+async fn delete_entry(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<Id>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    if let Err(err) = keepass.delete_entry(&params) {
+        info!("{}: failed to delete entry '{}': {}", username, params.id, err);
+        return modify_error(err, "failed to delete entry");
+    }
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: deleted entry '{}'", username, params.id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
+#[post("/create_group")]
+async fn create_group(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<NewGroup>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    let id = match keepass.create_group(&params) {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to create group in group '{}': {}", username, params.parent_id, err);
+            return modify_error(err, "failed to create group");
+        }
+    };
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: created group '{}'", username, id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": {
+                "id": id,
+            },
+        }
+    ))
+}
+
+#[post("/update_group")]
+async fn update_group(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<GroupUpdate>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    if let Err(err) = keepass.update_group(&params) {
+        info!("{}: failed to update group '{}': {}", username, params.id, err);
+        return modify_error(err, "failed to update group");
+    }
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: updated group '{}'", username, params.id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
+#[post("/move_node")]
+async fn move_node(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<Move>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    if let Err(err) = keepass.move_node(&params) {
+        info!("{}: failed to move '{}' to group '{}': {}", username, params.id, params.parent_id, err);
+        return modify_error(err, "failed to move");
+    }
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: moved '{}' to group '{}'", username, params.id, params.parent_id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
+#[post("/delete_group")]
+async fn delete_group(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<Id>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    if let Err(err) = keepass.delete_group(&params) {
+        info!("{}: failed to delete group '{}': {}", username, params.id, err);
+        return modify_error(err, "failed to delete group");
+    }
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: deleted group '{}'", username, params.id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
+#[post("/restore_node")]
+async fn restore_node(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Json<Restore>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    if let Err(err) = keepass.restore_node(&params) {
+        info!("{}: failed to restore node '{}' from recycle bin: {}", username, params.id, err);
+        return modify_error(err, "failed to restore from recycle bin");
+    }
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: restored node '{}' from recycle bin", username, params.id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
+#[post("/empty_recycle_bin")]
+async fn empty_recycle_bin(session: Session, config: Data<Config>, db_cache: Data<DbCache>) -> impl Responder {
+    let mut keepass = match util::get_db(&session, &config, &db_cache).await {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let username = session.get_user_id();
+    if let Err(err) = keepass.empty_recycle_bin() {
+        info!("{}: failed to empty recycle bin: {}", username, err);
+        return modify_error(err, "failed to empty recycle bin");
+    }
+
+    if let Err(err) = util::save_db(&session, &config, &db_cache, keepass).await {
+        return err;
+    }
+
+    info!("{}: emptied recycle bin", username);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
+fn modify_error(err: anyhow::Error, message: &str) -> HttpResponse {
+    if err.downcast_ref::<NotFoundError>().is_some() {
+        return HttpResponse::NotFound().json(json!(
+            {
+                "success": false,
+                "message": format!("{}: {}", message, err),
+            }
+        ));
+    }
+
+    HttpResponse::BadRequest().json(json!(
+        {
+            "success": false,
+            "message": format!("{}: {}", message, err),
+        }
+    ))
+}
+
 #[get("/icon/{id}")]
 async fn get_icon(session: Session, config: Data<Config>, db_cache: Data<DbCache>, params: web::Path<Id>) -> impl Responder {
     let keepass = match util::get_db(&session, &config, &db_cache).await {
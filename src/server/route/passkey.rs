@@ -0,0 +1,377 @@
+use actix_session::Session;
+use actix_web::{get, HttpResponse, post, Responder, web};
+use actix_web::web::Data;
+use anyhow::anyhow;
+use log::{error, info};
+use serde_json::json;
+
+use crate::auth::SESSION_KEY_TWO_FACTOR;
+use crate::auth_backend;
+use crate::config::config::Config;
+use crate::keepass::keepass::NotFoundError;
+use crate::server::route::auth::finish_user_login;
+use crate::server::route::util::{check_user_session, get_user_info};
+use crate::session::AuthSession;
+use crate::two_factor::{PendingLogin, TooManyAttemptsError, TwoFactorStore};
+use crate::webauthn::{Ceremony, LoginResponse, PasskeyRemove, PasskeyStore, RegistrationResponse};
+
+// challenges of the registration or login in progress
+const SESSION_KEY_PASSKEY_REGISTRATION: &str = "passkey_registration";
+const SESSION_KEY_PASSKEY_LOGIN: &str = "passkey_login";
+const SESSION_KEY_PASSKEY_VERIFY: &str = "passkey_verify";
+
+#[get("/passkeys")]
+async fn passkeys(session: Session, config: Data<Config>, store: Data<PasskeyStore>) -> impl Responder {
+    let username = session.get_user_id();
+    let passkeys = match config.webauthn.enabled() {
+        true => store.list(&config.webauthn, &username).await,
+        false => Ok(vec![]),
+    };
+    let passkeys = match passkeys {
+        Ok(v) => v,
+        Err(err) => {
+            error!("{}: failed to list passkeys: {}", username, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to list passkeys",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": {
+                "enabled": config.webauthn.enabled(),
+                "passwordless": config.webauthn.passwordless,
+                "passkeys": passkeys,
+            },
+        }
+    ))
+}
+
+#[post("/passkey_register_start")]
+async fn passkey_register_start(session: Session, config: Data<Config>, store: Data<PasskeyStore>) -> impl Responder {
+    let user_info = match get_user_info(&session) {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+    if !config.webauthn.enabled() {
+        return HttpResponse::BadRequest().json(json!(
+            {
+                "success": false,
+                "message": "passkeys are not enabled",
+            }
+        ));
+    }
+
+    let result = store.start_registration(&config.webauthn, &user_info).await
+        .and_then(|(ceremony, options)| {
+            session.insert(SESSION_KEY_PASSKEY_REGISTRATION, &ceremony)?;
+            Ok(options)
+        });
+    let options = match result {
+        Ok(v) => v,
+        Err(err) => {
+            error!("{}: failed to start passkey registration: {}", user_info.id, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to start passkey registration",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": options,
+        }
+    ))
+}
+
+#[post("/passkey_register_finish")]
+async fn passkey_register_finish(session: Session, config: Data<Config>, store: Data<PasskeyStore>, params: web::Json<RegistrationResponse>) -> impl Responder {
+    let user_info = match get_user_info(&session) {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+    // challenges are single use, the store rejects reused ones as the session cookie can be replayed
+    let ceremony = match session.remove_as::<Ceremony>(SESSION_KEY_PASSKEY_REGISTRATION) {
+        Some(Ok(v)) => v,
+        _ => return HttpResponse::BadRequest().json(json!(
+            {
+                "success": false,
+                "message": "no passkey registration in progress",
+            }
+        )),
+    };
+
+    let passkey = match store.finish_registration(&config.webauthn, &user_info, &ceremony, &params).await {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to register passkey: {}", user_info.id, err);
+            return HttpResponse::BadRequest().json(json!(
+                {
+                    "success": false,
+                    "message": format!("failed to register passkey: {}", err),
+                }
+            ));
+        }
+    };
+
+    info!("{}: registered passkey '{}'", user_info.id, passkey.id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": passkey,
+        }
+    ))
+}
+
+// Challenge to confirm removing a passkey with another passkey
+#[post("/passkey_verify_start")]
+async fn passkey_verify_start(session: Session, config: Data<Config>, store: Data<PasskeyStore>) -> impl Responder {
+    let username = session.get_user_id();
+    let result = match config.webauthn.enabled() {
+        true => store.start_login(&config.webauthn, Some(&username)).await,
+        false => Err(anyhow!("passkeys are not enabled")),
+    }.and_then(|(ceremony, options)| {
+        session.insert(SESSION_KEY_PASSKEY_VERIFY, &ceremony)?;
+        Ok(options)
+    });
+    let options = match result {
+        Ok(v) => v,
+        Err(err) => {
+            error!("{}: failed to start passkey verification: {}", username, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to start passkey verification",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": options,
+        }
+    ))
+}
+
+#[post("/passkey_remove")]
+async fn passkey_remove(
+    session: Session,
+    config: Data<Config>,
+    store: Data<PasskeyStore>,
+    two_factor_store: Data<TwoFactorStore>,
+    params: web::Json<PasskeyRemove>,
+) -> impl Responder {
+    let username = session.get_user_id();
+    let totp = match config.two_factor.enabled() {
+        true => two_factor_store.is_enrolled(&config.two_factor, &username).await,
+        false => Ok(false),
+    };
+    let last = store.list(&config.webauthn, &username).await
+        .map(|list| list.iter().all(|passkey| passkey.id == params.id));
+    let (totp, last) = match (totp, last) {
+        (Ok(totp), Ok(last)) => (totp, last),
+        (Err(err), _) | (_, Err(err)) => {
+            error!("{}: failed to remove passkey '{}': {}", username, params.id, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to remove passkey",
+                }
+            ));
+        }
+    };
+    if config.two_factor.required && last && !totp {
+        return HttpResponse::Forbidden().json(json!(
+            {
+                "success": false,
+                "message": "second factor is required",
+            }
+        ));
+    }
+
+    // a stolen session alone isn't enough to remove a second factor
+    let verified = match (&params.assertion, &params.code) {
+        (Some(assertion), _) => match session.remove_as::<Ceremony>(SESSION_KEY_PASSKEY_VERIFY) {
+            Some(Ok(ceremony)) => store.finish_login(&config.webauthn, Some(&username), &ceremony, assertion).await.map(|_| ()),
+            _ => Err(anyhow!("no passkey verification in progress")),
+        },
+        (None, Some(code)) => two_factor_store.verify(&config.two_factor, &username, code).await,
+        (None, None) => Err(anyhow!("passkey or code missing")),
+    };
+    if let Err(err) = verified {
+        info!("{}: failed to remove passkey '{}': {}", username, params.id, err);
+        let resp = json!(
+            {
+                "success": false,
+                "message": "failed to verify passkey or code",
+            }
+        );
+        return match err.downcast_ref::<TooManyAttemptsError>() {
+            Some(_) => HttpResponse::TooManyRequests().json(resp),
+            // unauthorized would end the session in the web interface
+            None => HttpResponse::BadRequest().json(resp),
+        };
+    }
+
+    if let Err(err) = store.remove(&config.webauthn, &username, &params.id).await {
+        info!("{}: failed to remove passkey '{}': {}", username, params.id, err);
+        let resp = json!(
+            {
+                "success": false,
+                "message": "failed to remove passkey",
+            }
+        );
+        return match err.downcast_ref::<NotFoundError>() {
+            Some(_) => HttpResponse::NotFound().json(resp),
+            None => HttpResponse::InternalServerError().json(resp),
+        };
+    }
+
+    info!("{}: removed passkey '{}'", username, params.id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
+#[post("/passkey_login_start")]
+async fn passkey_login_start(session: Session, config: Data<Config>, store: Data<PasskeyStore>) -> impl Responder {
+    let user_id = match passkey_login_user(&session, &config) {
+        Ok(v) => v,
+        Err(message) => return HttpResponse::Unauthorized().json(json!(
+            {
+                "success": false,
+                "message": message,
+            }
+        )),
+    };
+    if user_id.is_none() {
+        if let Err(err) = check_user_session(&session, &session.get_user_id()) {
+            return err;
+        }
+    }
+    let username = user_id.clone().unwrap_or_else(|| session.get_user_id());
+
+    let result = store.start_login(&config.webauthn, user_id.as_deref()).await
+        .and_then(|(ceremony, options)| {
+            session.insert(SESSION_KEY_PASSKEY_LOGIN, &ceremony)?;
+            Ok(options)
+        });
+    let options = match result {
+        Ok(v) => v,
+        Err(err) => {
+            error!("user login from '{}': failed to start passkey login: {}", username, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to start passkey login",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": options,
+        }
+    ))
+}
+
+#[post("/passkey_login_finish")]
+async fn passkey_login_finish(session: Session, config: Data<Config>, store: Data<PasskeyStore>, params: web::Json<LoginResponse>) -> impl Responder {
+    let user_id = match passkey_login_user(&session, &config) {
+        Ok(v) => v,
+        Err(message) => return HttpResponse::Unauthorized().json(json!(
+            {
+                "success": false,
+                "message": message,
+            }
+        )),
+    };
+    if user_id.is_none() {
+        if let Err(err) = check_user_session(&session, &session.get_user_id()) {
+            return err;
+        }
+    }
+    let username = user_id.clone().unwrap_or_else(|| session.get_user_id());
+    let ceremony = match session.remove_as::<Ceremony>(SESSION_KEY_PASSKEY_LOGIN) {
+        Some(Ok(v)) => v,
+        _ => return HttpResponse::BadRequest().json(json!(
+            {
+                "success": false,
+                "message": "no passkey login in progress",
+            }
+        )),
+    };
+
+    let user_info = match store.finish_login(&config.webauthn, user_id.as_deref(), &ceremony, &params).await {
+        Ok(v) => v,
+        Err(err) => {
+            info!("user login from '{}': passkey: {}", username, err);
+            return HttpResponse::Unauthorized().json(json!(
+                {
+                    "success": false,
+                    "message": "passkey login failed",
+                }
+            ));
+        }
+    };
+
+    // the user as returned by the auth backend, if the password was given
+    let user_info = match session.get_key::<PendingLogin>(SESSION_KEY_TWO_FACTOR) {
+        Some(pending) if user_id.is_some() => pending.user,
+        _ => {
+            // passkeys keep the user as of their registration, the user might have been removed or disabled since
+            match auth_backend::new(&config).user_enabled(&user_info.id).await {
+                Ok(true) => user_info,
+                Ok(false) => {
+                    info!("user login from '{}': passkey of disabled user", user_info.id);
+                    return HttpResponse::Unauthorized().json(json!(
+                        {
+                            "success": false,
+                            "message": "passkey login failed",
+                        }
+                    ));
+                }
+                Err(err) => {
+                    error!("user login from '{}': {}", user_info.id, err);
+                    return HttpResponse::InternalServerError().json(json!(
+                        {
+                            "success": false,
+                            "message": "failed to check user",
+                        }
+                    ));
+                }
+            }
+        }
+    };
+    session.remove(SESSION_KEY_TWO_FACTOR);
+    finish_user_login(session, &config, user_info, None)
+}
+
+// The user whose second factor is pending, None for passwordless logins
+fn passkey_login_user(session: &Session, config: &Config) -> Result<Option<String>, &'static str> {
+    if !config.webauthn.enabled() {
+        return Err("passkeys are not enabled");
+    }
+
+    match session.get_key::<PendingLogin>(SESSION_KEY_TWO_FACTOR) {
+        Some(pending) if !pending.is_expired() && pending.passkey => Ok(Some(pending.user.id)),
+        Some(pending) if !pending.is_expired() => Err("no passkey registered"),
+        _ if config.webauthn.passwordless => Ok(None),
+        _ => Err("login expired, please log in again"),
+    }
+}
@@ -0,0 +1,93 @@
+use actix_session::Session;
+use actix_web::{get, HttpResponse, post, Responder, web};
+use actix_web::web::Data;
+use log::{error, info};
+use serde_json::json;
+
+use crate::config::config::Config;
+use crate::keepass::keepass::{Id, NotFoundError};
+use crate::server::route::util;
+use crate::session::AuthSession;
+use crate::token::{NewToken, TokenStore};
+
+#[get("/tokens")]
+async fn tokens(session: Session, config: Data<Config>, store: Data<TokenStore>) -> impl Responder {
+    let username = session.get_user_id();
+    let tokens = match store.list(&config.tokens, &username).await {
+        Ok(v) => v,
+        Err(err) => {
+            error!("{}: failed to list tokens: {}", username, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to list tokens",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": tokens,
+        }
+    ))
+}
+
+#[post("/create_token")]
+async fn create_token(session: Session, config: Data<Config>, store: Data<TokenStore>, params: web::Form<NewToken>) -> impl Responder {
+    let user_info = match util::get_user_info(&session) {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let (secret, token) = match store.create(&config.tokens, &user_info, &params).await {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to create token: {}", user_info.id, err);
+            return HttpResponse::BadRequest().json(json!(
+                {
+                    "success": false,
+                    "message": format!("failed to create token: {}", err),
+                }
+            ));
+        }
+    };
+
+    info!("{}: created token '{}'", user_info.id, token.id);
+    // the token can't be retrieved again, only its hash is kept
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": {
+                "token": secret.as_str(),
+                "info": token,
+            },
+        }
+    ))
+}
+
+#[post("/revoke_token")]
+async fn revoke_token(session: Session, config: Data<Config>, store: Data<TokenStore>, params: web::Form<Id>) -> impl Responder {
+    let username = session.get_user_id();
+    if let Err(err) = store.revoke(&config.tokens, &username, &params.id).await {
+        info!("{}: failed to revoke token '{}': {}", username, params.id, err);
+        let resp = json!(
+            {
+                "success": false,
+                "message": "failed to revoke token",
+            }
+        );
+        return match err.downcast_ref::<NotFoundError>() {
+            Some(_) => HttpResponse::NotFound().json(resp),
+            None => HttpResponse::InternalServerError().json(resp),
+        };
+    }
+
+    info!("{}: revoked token '{}'", username, params.id);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
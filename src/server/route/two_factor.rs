@@ -0,0 +1,179 @@
+use actix_session::Session;
+use actix_web::{get, HttpResponse, post, Responder, web};
+use actix_web::web::Data;
+use log::{error, info};
+use serde_json::json;
+
+use crate::config::config::Config;
+use crate::session::AuthSession;
+use crate::two_factor::{Enrollment, InvalidCodeError, TooManyAttemptsError, TwoFactorCode, TwoFactorStore};
+
+// secret of an enrollment started by a logged in user
+const SESSION_KEY_TWO_FACTOR_ENROLLMENT: &str = "two_factor_enrollment";
+
+#[get("/two_factor")]
+async fn two_factor_status(session: Session, config: Data<Config>, store: Data<TwoFactorStore>) -> impl Responder {
+    let username = session.get_user_id();
+    let status = match store.status(&config.two_factor, &username).await {
+        Ok(v) => v,
+        Err(err) => {
+            error!("{}: failed to get second factor status: {}", username, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to get second factor status",
+                }
+            ));
+        }
+    };
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": status,
+        }
+    ))
+}
+
+#[post("/two_factor_enroll")]
+async fn two_factor_enroll(session: Session, config: Data<Config>, store: Data<TwoFactorStore>) -> impl Responder {
+    let username = session.get_user_id();
+    let enrolled = match config.two_factor.enabled() {
+        true => store.is_enrolled(&config.two_factor, &username).await,
+        false => Err(anyhow::anyhow!("second factor is not enabled")),
+    };
+    match enrolled {
+        Ok(false) => {}
+        Ok(true) => return HttpResponse::BadRequest().json(json!(
+            {
+                "success": false,
+                "message": "second factor already enrolled",
+            }
+        )),
+        Err(err) => {
+            info!("{}: failed to enroll second factor: {}", username, err);
+            return HttpResponse::BadRequest().json(json!(
+                {
+                    "success": false,
+                    "message": format!("failed to enroll second factor: {}", err),
+                }
+            ));
+        }
+    }
+
+    let enrollment = match store.enroll(&config.two_factor, &username) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("{}: failed to enroll second factor: {}", username, err);
+            return HttpResponse::InternalServerError().json(json!(
+                {
+                    "success": false,
+                    "message": "failed to enroll second factor",
+                }
+            ));
+        }
+    };
+    if let Err(err) = session.insert(SESSION_KEY_TWO_FACTOR_ENROLLMENT, &enrollment) {
+        error!("{}: failed to store second factor enrollment: {}", username, err);
+        return HttpResponse::InternalServerError().json(json!(
+            {
+                "success": false,
+                "message": "failed to enroll second factor",
+            }
+        ));
+    }
+
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": enrollment,
+        }
+    ))
+}
+
+#[post("/two_factor_confirm")]
+async fn two_factor_confirm(session: Session, config: Data<Config>, store: Data<TwoFactorStore>, params: web::Form<TwoFactorCode>) -> impl Responder {
+    let username = session.get_user_id();
+    let enrollment = match session.get_key::<Enrollment>(SESSION_KEY_TWO_FACTOR_ENROLLMENT) {
+        Some(v) => v,
+        None => return HttpResponse::BadRequest().json(json!(
+            {
+                "success": false,
+                "message": "no enrollment in progress",
+            }
+        )),
+    };
+
+    let recovery_codes = match store.confirm(&config.two_factor, &username, &enrollment, &params.code).await {
+        Ok(v) => v,
+        Err(err) => {
+            info!("{}: failed to confirm second factor: {}", username, err);
+            return code_error(err, "failed to confirm second factor");
+        }
+    };
+    session.remove(SESSION_KEY_TWO_FACTOR_ENROLLMENT);
+
+    info!("{}: enrolled second factor", username);
+    // the recovery codes can't be retrieved again, only their hashes are kept
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+            "data": {
+                "recovery_codes": recovery_codes,
+            },
+        }
+    ))
+}
+
+#[post("/two_factor_disable")]
+async fn two_factor_disable(session: Session, config: Data<Config>, store: Data<TwoFactorStore>, params: web::Form<TwoFactorCode>) -> impl Responder {
+    let username = session.get_user_id();
+    if config.two_factor.required {
+        return HttpResponse::Forbidden().json(json!(
+            {
+                "success": false,
+                "message": "second factor is required",
+            }
+        ));
+    }
+
+    // a stolen session alone isn't enough to remove the second factor
+    if let Err(err) = store.verify(&config.two_factor, &username, &params.code).await {
+        info!("{}: failed to disable second factor: {}", username, err);
+        return code_error(err, "failed to disable second factor");
+    }
+    if let Err(err) = store.remove(&config.two_factor, &username).await {
+        error!("{}: failed to disable second factor: {}", username, err);
+        return HttpResponse::InternalServerError().json(json!(
+            {
+                "success": false,
+                "message": "failed to disable second factor",
+            }
+        ));
+    }
+
+    info!("{}: disabled second factor", username);
+    HttpResponse::Ok().json(json!(
+        {
+            "success": true,
+        }
+    ))
+}
+
+// Wrong codes are a bad request here, unauthorized would end the session in the web interface
+fn code_error(err: anyhow::Error, message: &str) -> HttpResponse {
+    let resp = |message: &str| json!(
+        {
+            "success": false,
+            "message": message,
+        }
+    );
+
+    if err.downcast_ref::<TooManyAttemptsError>().is_some() {
+        return HttpResponse::TooManyRequests().json(resp(&err.to_string()));
+    }
+    if err.downcast_ref::<InvalidCodeError>().is_some() {
+        return HttpResponse::BadRequest().json(resp("invalid code"));
+    }
+    HttpResponse::InternalServerError().json(resp(message))
+}
@@ -5,7 +5,9 @@ use linux_keyutils::KeyError;
 use log::{error, info};
 use serde_json::json;
 
-use crate::auth::{gen_token, SESSION_KEY_CSRF, SESSION_KEY_USER};
+use crate::auth::{gen_token, SESSION_KEY_CSRF, SESSION_KEY_READ_ONLY, SESSION_KEY_USER};
+use crate::db_backend;
+use crate::db_backend::ConflictError;
 use crate::auth_backend::UserInfo;
 use crate::config::config::Config;
 use crate::keepass::db_cache::{CacheExpiredError, DbCache};
@@ -149,6 +151,105 @@ pub(crate) async fn get_db(session: &Session, config: &Config, db_cache: &DbCach
     }
 }
 
+pub(crate) async fn save_db(session: &Session, config: &Config, db_cache: &DbCache, mut keepass: KeePass) -> anyhow::Result<(), HttpResponse> {
+    let username = session.get_user_id();
+    let err_resp = |msg: &str| HttpResponse::InternalServerError().json(json!(
+        {
+            "success": false,
+            "message": msg,
+        }
+    ));
+
+    // independent of the route, read-only tokens must not modify the database
+    match session.get::<bool>(SESSION_KEY_READ_ONLY) {
+        Ok(Some(true)) => {
+            info!("save db from '{}': refused for read-only token", username);
+            return Err(HttpResponse::Forbidden().json(json!(
+                {
+                    "success": false,
+                    "message": "token is read-only",
+                }
+            )));
+        }
+        Ok(_) => {}
+        Err(err) => {
+            error!("save db from '{}': {}", username, err);
+            return Err(err_resp("failed to retrieve session"));
+        }
+    }
+
+    let user_info = get_user_info(session)?;
+
+    let mut db_backend = db_backend::new(config);
+    if let Err(err) = keepass.save(db_backend.as_mut(), &user_info).await {
+        if err.downcast_ref::<ConflictError>().is_some() {
+            info!("save db from '{}': {}", username, err);
+            return Err(HttpResponse::Conflict().json(json!(
+                {
+                    "success": false,
+                    "message": "database is being modified concurrently, please try again",
+                }
+            )));
+        }
+        error!("save db from '{}': {}", username, err);
+        return Err(err_resp("failed to save database"));
+    }
+
+    // the cached copy is re-encrypted with a new key, the old one is revoked once the new one is in place
+    let mut old_key = match retrieve_key(config, session) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("save db from '{}': failed to retrieve key: {}", username, err);
+            return Err(err_resp("failed to update cached database"));
+        }
+    };
+
+    let (key, enc_db) = match keepass.to_enc() {
+        Ok(v) => v,
+        Err(err) => {
+            error!("save db from '{}': {}", username, err);
+            return Err(err_resp("failed to encrypt database"));
+        }
+    };
+
+    if let Err(err) = store_key(config, session, key) {
+        error!("save db from '{}': failed to store key: {}", username, err);
+        return Err(err_resp("failed to update cached database"));
+    }
+
+    if let Err(err) = db_cache.store(session, enc_db).await {
+        error!("save db from '{}': failed to store db: {}", username, err);
+        // the cached database can't be decrypted anymore without the old key
+        let _ = _close_db(session, config, db_cache).await;
+        return Err(err_resp("failed to update cached database"));
+    }
+
+    if let Err(err) = old_key.revoke() {
+        error!("save db from '{}': failed to revoke old key: {}", username, err);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn get_user_info(session: &Session) -> Result<UserInfo, HttpResponse> {
+    let resp = HttpResponse::InternalServerError().json(json!(
+        {
+            "success": false,
+            "message": "failed to retrieve session",
+        }
+    ));
+    let user_info = match session.get::<UserInfo>(SESSION_KEY_USER) {
+        Err(err) => {
+            error!("failed to retrieve session: {}", err);
+            return Err(resp);
+        }
+        Ok(Some(v)) => v,
+        Ok(None) => return Err(resp),
+    };
+    Ok(user_info)
+}
+
+
 pub(crate) async fn db_is_open(session: &Session, config: &Config, db_cache: &DbCache) -> anyhow::Result<bool, HttpResponse> {
     // TODO: distinguish real errors from non-existent db/key etc (= actually closed db)
     // The current behavior may suggest that the database is closed, while in reality it could be
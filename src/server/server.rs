@@ -9,6 +9,10 @@ use crate::{auth, auth_backend};
 use crate::config::config::Config;
 use crate::keepass::db_cache::DbCache;
 use crate::server::route::setup_routes;
+use crate::server::tls;
+use crate::token::TokenStore;
+use crate::two_factor::TwoFactorStore;
+use crate::webauthn::PasskeyStore;
 
 pub struct Server;
 
@@ -18,15 +22,26 @@ impl Server {
         let port = config.port;
         env_logger::init_from_env(Env::default().default_filter_or("info"));
 
+        let tls_config = match config.tls.enabled() {
+            true => Some(tls::server_config(&config.tls)?),
+            false => None,
+        };
+
         let secret_key = config.session_secret_key.0.clone();
         let config_data = web::Data::new(config);
         let auth_cache = web::Data::new(auth_backend::new(&config_data).init().await?);
         let db_cache = web::Data::new(DbCache::default());
+        let token_store = web::Data::new(TokenStore::default());
+        let two_factor_store = web::Data::new(TwoFactorStore::default());
+        let passkey_store = web::Data::new(PasskeyStore::default());
 
-        HttpServer::new(move || {
+        let http_server = HttpServer::new(move || {
             App::new()
                 .app_data(db_cache.clone())
                 .app_data(auth_cache.clone())
+                .app_data(token_store.clone())
+                .app_data(two_factor_store.clone())
+                .app_data(passkey_store.clone())
                 .app_data(config_data.clone())
                 .wrap(auth::CheckAuth)
                 .wrap(
@@ -46,7 +61,12 @@ impl Server {
                 )
                 .wrap(Logger::default())
                 .configure(setup_routes)
-        }).bind((server, port))?
+        }).on_connect(tls::on_connect);
+
+        match tls_config {
+            Some(tls_config) => http_server.bind_rustls_0_23((server, port), tls_config)?,
+            None => http_server.bind((server, port))?,
+        }
             .run()
             .await
             .map_err(anyhow::Error::new)
@@ -0,0 +1,83 @@
+use std::any::Any;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use anyhow::{anyhow, bail, Result};
+use rustls::crypto::ring;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::RootCertStore;
+use rustls::server::WebPkiClientVerifier;
+use rustls::ServerConfig;
+
+use crate::config::tls::Tls;
+
+// Verified certificate of the client, DER encoded. Attached to every request of the connection
+#[derive(Clone)]
+pub struct ClientCertificate(pub CertificateDer<'static>);
+
+pub(crate) fn server_config(config: &Tls) -> Result<ServerConfig> {
+    let (Some(cert), Some(key)) = (&config.cert, &config.key) else {
+        bail!("TLS is not enabled");
+    };
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match &config.client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(client_ca)? {
+                roots.add(cert).map_err(|err| anyhow!("invalid CA certificate in '{}': {}", client_ca.display(), err))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = match config.client_cert_optional {
+                true => verifier.allow_unauthenticated().build()?,
+                false => verifier.build()?,
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder.with_single_cert(load_certs(cert)?, load_key(key)?)?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(server_config)
+}
+
+// Makes the client certificate available to the requests of a connection
+pub(crate) fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    // the end entity certificate comes first, already verified during the handshake
+    if let Some(cert) = stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()) {
+        data.insert(ClientCertificate(cert.clone().into_owned()));
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).map_err(|err| anyhow!("failed to open '{}': {}", path.display(), err))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| anyhow!("failed to read certificates from '{}': {}", path.display(), err))?;
+    if certs.is_empty() {
+        bail!("no certificates found in '{}'", path.display());
+    }
+
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).map_err(|err| anyhow!("failed to open '{}': {}", path.display(), err))?;
+
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|err| anyhow!("failed to read private key from '{}': {}", path.display(), err))?
+        .ok_or(anyhow!("no private key found in '{}'", path.display()))
+}
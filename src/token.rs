@@ -0,0 +1,213 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDateTime;
+use constant_time_eq::constant_time_eq;
+use data_encoding::HEXLOWER;
+use keepass::db::Times;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::auth::gen_token;
+use crate::auth_backend::UserInfo;
+use crate::config::tokens::Tokens;
+use crate::file_store::{load, save};
+use crate::keepass::keepass::NotFoundError;
+
+// makes tokens recognizable, e.g. for secret scanners
+const TOKEN_PREFIX: &str = "k4w_";
+const TOKEN_LENGTH: usize = 40;
+const MAX_NAME_LENGTH: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct InvalidTokenError;
+
+impl Display for InvalidTokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid or expired token")
+    }
+}
+
+impl Error for InvalidTokenError {}
+
+#[derive(Deserialize)]
+pub struct NewToken {
+    pub name: String,
+    #[serde(default)]
+    pub read_only: bool,
+    // None for tokens that don't expire
+    #[serde(default, with = "humantime_serde")]
+    pub expires_in: Option<Duration>,
+}
+
+// Token as shown to the user, the token itself is only returned once on creation
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Token {
+    pub id: Uuid,
+    pub name: String,
+    // only GET requests and session handling are allowed
+    pub read_only: bool,
+    pub created: NaiveDateTime,
+    pub expires: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    #[serde(flatten)]
+    token: Token,
+    user: UserInfo,
+    // hex encoded SHA-256 of the token, tokens are random enough to not need a slow hash
+    hash: String,
+}
+
+// Personal access tokens of all users, kept in a file.
+// The lock serializes modifications of the file
+#[derive(Default)]
+pub struct TokenStore {
+    lock: Mutex<()>,
+}
+
+impl TokenStore {
+    // Returns the token itself and its description
+    pub async fn create(&self, config: &Tokens, user_info: &UserInfo, params: &NewToken) -> Result<(Zeroizing<String>, Token)> {
+        let name = params.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            bail!("token name must be between 1 and {} characters", MAX_NAME_LENGTH);
+        }
+        let now = Times::now();
+        let expires = match params.expires_in {
+            Some(v) => Some(now + chrono::Duration::from_std(v)?),
+            None => None,
+        };
+
+        let _guard = self.lock.lock().await;
+        let path = file(config)?;
+        let mut tokens: Vec<StoredToken> = load(path).await?;
+        // expired tokens are useless
+        tokens.retain(|stored| !is_expired(&stored.token, now));
+
+        let secret = Zeroizing::new(format!("{}{}", TOKEN_PREFIX, gen_token(TOKEN_LENGTH)));
+        let token = Token {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            read_only: params.read_only,
+            created: now,
+            expires,
+        };
+        tokens.push(StoredToken {
+            token: token.clone(),
+            user: user_info.clone(),
+            hash: hash(&secret),
+        });
+        save(path, &tokens).await?;
+
+        Ok((secret, token))
+    }
+
+    pub async fn list(&self, config: &Tokens, user_id: &str) -> Result<Vec<Token>> {
+        Ok(
+            load::<StoredToken>(file(config)?).await?
+                .into_iter()
+                .filter(|stored| stored.user.id == user_id)
+                .map(|stored| stored.token)
+                .collect()
+        )
+    }
+
+    pub async fn revoke(&self, config: &Tokens, user_id: &str, id: &Uuid) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let path = file(config)?;
+        let mut tokens: Vec<StoredToken> = load(path).await?;
+
+        let count = tokens.len();
+        tokens.retain(|stored| !(stored.token.id == *id && stored.user.id == user_id));
+        if tokens.len() == count {
+            return Err(NotFoundError("token").into());
+        }
+
+        save(path, &tokens).await
+    }
+
+    // The token and the user it belongs to, as of its creation
+    pub async fn verify(&self, config: &Tokens, secret: &str) -> Result<(Token, UserInfo)> {
+        let hash = hash(secret);
+        let stored = load::<StoredToken>(file(config)?).await?
+            .into_iter()
+            .find(|stored| constant_time_eq(stored.hash.as_bytes(), hash.as_bytes()))
+            .ok_or(InvalidTokenError)?;
+
+        if is_expired(&stored.token, Times::now()) {
+            return Err(InvalidTokenError.into());
+        }
+
+        Ok((stored.token, stored.user))
+    }
+}
+
+fn file(config: &Tokens) -> Result<&Path> {
+    config.file.as_deref().ok_or(anyhow!("personal access tokens are not enabled"))
+}
+
+fn hash(secret: &str) -> String {
+    HEXLOWER.encode(&Sha256::digest(secret.as_bytes()))
+}
+
+fn is_expired(token: &Token, now: NaiveDateTime) -> bool {
+    token.expires.is_some_and(|expires| expires <= now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn tokens() {
+        let path = std::env::temp_dir().join(format!("keepass4web-test-{}.json", gen_token(8)));
+        let config = Tokens {
+            file: Some(path.clone()),
+        };
+        let store = TokenStore::default();
+        let alice = UserInfo {
+            id: "alice".to_string(),
+            db_location: Some("alice.kdbx".to_string()),
+            ..Default::default()
+        };
+        let new_token = |name: &str, read_only: bool, expires_in: Option<Duration>| NewToken {
+            name: name.to_string(),
+            read_only,
+            expires_in,
+        };
+
+        let (secret, token) = store.create(&config, &alice, &new_token("script", true, None)).await.unwrap();
+        let (expired, _) = store.create(&config, &alice, &new_token("expired", false, Some(Duration::ZERO))).await.unwrap();
+        assert!(secret.starts_with(TOKEN_PREFIX));
+        assert!(store.create(&config, &alice, &new_token(" ", false, None)).await.is_err());
+
+        let content = tokio::fs::read_to_string(&path).await.unwrap();
+        assert!(!content.contains(secret.as_str()));
+
+        let (verified, user_info) = store.verify(&config, &secret).await.unwrap();
+        assert_eq!(verified.id, token.id);
+        assert!(verified.read_only);
+        assert_eq!(user_info.db_location.as_deref(), Some("alice.kdbx"));
+        assert!(store.verify(&config, &expired).await.is_err_and(|err| err.downcast_ref::<InvalidTokenError>().is_some()));
+        assert!(store.verify(&config, "k4w_unknown").await.is_err());
+
+        assert_eq!(store.list(&config, "alice").await.unwrap().len(), 2);
+        assert!(store.list(&config, "bob").await.unwrap().is_empty());
+
+        assert!(store.revoke(&config, "bob", &token.id).await.unwrap_err().downcast_ref::<NotFoundError>().is_some());
+        store.revoke(&config, "alice", &token.id).await.unwrap();
+        let result = store.verify(&config, &secret).await;
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        assert!(result.is_err());
+        assert!(store.verify(&Tokens::default(), &secret).await.is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDateTime;
+use constant_time_eq::constant_time_eq;
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use keepass::db::Times;
+use rand::RngCore;
+use rand::thread_rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use url::Url;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::auth::gen_token;
+use crate::auth_backend::UserInfo;
+use crate::config::two_factor::TwoFactor;
+use crate::file_store::{load, save};
+use crate::keepass::otp::Otp;
+
+// 160 bits, as recommended by RFC 4226
+const SECRET_LENGTH: usize = 20;
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+// codes of the previous and the next period are accepted as well
+const SKEW: u64 = 1;
+// time to enter the code after the password was accepted
+const PENDING_TIMEOUT: chrono::Duration = chrono::Duration::minutes(5);
+const MAX_FAILURES: u32 = 5;
+const LOCKOUT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone)]
+pub struct InvalidCodeError;
+
+impl Display for InvalidCodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid code")
+    }
+}
+
+impl Error for InvalidCodeError {}
+
+#[derive(Debug, Clone)]
+pub struct TooManyAttemptsError;
+
+impl Display for TooManyAttemptsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "too many failed attempts, try again later")
+    }
+}
+
+impl Error for TooManyAttemptsError {}
+
+#[derive(Deserialize, ZeroizeOnDrop)]
+pub struct TwoFactorCode {
+    // TOTP or recovery code
+    pub code: String,
+}
+
+// Secret of an enrollment in progress, kept in the session until confirmed with a code
+#[derive(Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct Enrollment {
+    // base32, as entered into authenticator apps
+    pub secret: String,
+    pub uri: String,
+}
+
+// User who passed the auth backend, but not the second factor yet
+#[derive(Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub user: UserInfo,
+    pub created: NaiveDateTime,
+    // second factors the user can log in with
+    pub totp: bool,
+    pub passkey: bool,
+    // set if the user has to enroll before logging in
+    pub enrollment: Option<Enrollment>,
+}
+
+impl PendingLogin {
+    pub fn new(user: UserInfo, totp: bool, passkey: bool, enrollment: Option<Enrollment>) -> Self {
+        Self {
+            user,
+            created: Times::now(),
+            totp,
+            passkey,
+            enrollment,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.created + PENDING_TIMEOUT <= Times::now()
+    }
+}
+
+#[derive(Serialize)]
+pub struct Status {
+    pub enabled: bool,
+    pub required: bool,
+    pub enrolled: bool,
+    pub recovery_codes: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredSecret {
+    user: String,
+    secret: String,
+    // hex encoded SHA-256 of the unused recovery codes
+    recovery_codes: Vec<String>,
+    // time step of the last accepted code, so codes can't be replayed
+    last_step: u64,
+    created: NaiveDateTime,
+}
+
+// TOTP secrets and recovery codes of all users, kept in a file.
+// The lock serializes modifications of the file, failed attempts are only tracked in memory
+#[derive(Default)]
+pub struct TwoFactorStore {
+    lock: Mutex<()>,
+    failures: std::sync::Mutex<HashMap<String, (u32, Instant)>>,
+}
+
+impl TwoFactorStore {
+    // New secret for the user, only stored once confirmed
+    pub fn enroll(&self, config: &TwoFactor, user_id: &str) -> Result<Enrollment> {
+        let mut secret = Zeroizing::new([0u8; SECRET_LENGTH]);
+        thread_rng().fill_bytes(secret.as_mut());
+        let secret = BASE32_NOPAD.encode(secret.as_ref());
+
+        let mut uri = Url::parse("otpauth://totp")?;
+        uri.set_path(&format!("{}:{}", config.issuer, user_id));
+        uri.query_pairs_mut()
+            .append_pair("secret", &secret)
+            .append_pair("issuer", &config.issuer);
+
+        Ok(
+            Enrollment {
+                uri: uri.to_string(),
+                secret,
+            }
+        )
+    }
+
+    // Stores the enrolled secret if the code matches, returns the recovery codes
+    pub async fn confirm(&self, config: &TwoFactor, user_id: &str, enrollment: &Enrollment, code: &str) -> Result<Vec<String>> {
+        self.check_failures(user_id)?;
+        let otp = Otp::totp(BASE32_NOPAD.decode(enrollment.secret.as_bytes())?)?;
+        let step = match otp.verify_at(code, now()?, SKEW) {
+            Some(v) => v,
+            None => return Err(self.failed(user_id)),
+        };
+
+        let _guard = self.lock.lock().await;
+        let path = file(config)?;
+        let mut secrets: Vec<StoredSecret> = load(path).await?;
+        if secrets.iter().any(|stored| stored.user == user_id) {
+            bail!("second factor already enrolled");
+        }
+
+        let recovery_codes: Vec<String> = (0..RECOVERY_CODES).map(|_| gen_token(RECOVERY_CODE_LENGTH)).collect();
+        secrets.push(StoredSecret {
+            user: user_id.to_string(),
+            secret: enrollment.secret.clone(),
+            recovery_codes: recovery_codes.iter().map(|code| hash(code)).collect(),
+            last_step: step,
+            created: Times::now(),
+        });
+        save(path, &secrets).await?;
+        self.clear_failures(user_id);
+
+        Ok(recovery_codes)
+    }
+
+    // Accepts a TOTP code or an unused recovery code, recovery codes are used up
+    pub async fn verify(&self, config: &TwoFactor, user_id: &str, code: &str) -> Result<()> {
+        self.check_failures(user_id)?;
+
+        let _guard = self.lock.lock().await;
+        let path = file(config)?;
+        let mut secrets: Vec<StoredSecret> = load(path).await?;
+        let stored = secrets.iter_mut()
+            .find(|stored| stored.user == user_id)
+            .ok_or(anyhow!("no second factor enrolled"))?;
+
+        let otp = Otp::totp(BASE32_NOPAD.decode(stored.secret.as_bytes())?)?;
+        match otp.verify_at(code, now()?, SKEW) {
+            Some(step) if step > stored.last_step => stored.last_step = step,
+            // already used
+            Some(_) => return Err(self.failed(user_id)),
+            None => {
+                let hash = hash(code);
+                let count = stored.recovery_codes.len();
+                stored.recovery_codes.retain(|v| !constant_time_eq(v.as_bytes(), hash.as_bytes()));
+                if stored.recovery_codes.len() == count {
+                    return Err(self.failed(user_id));
+                }
+            }
+        }
+
+        save(path, &secrets).await?;
+        self.clear_failures(user_id);
+
+        Ok(())
+    }
+
+    pub async fn is_enrolled(&self, config: &TwoFactor, user_id: &str) -> Result<bool> {
+        Ok(
+            load::<StoredSecret>(file(config)?).await?
+                .iter()
+                .any(|stored| stored.user == user_id)
+        )
+    }
+
+    pub async fn status(&self, config: &TwoFactor, user_id: &str) -> Result<Status> {
+        let stored = match config.enabled() {
+            true => load::<StoredSecret>(file(config)?).await?.into_iter().find(|stored| stored.user == user_id),
+            false => None,
+        };
+
+        Ok(
+            Status {
+                enabled: config.enabled(),
+                required: config.required,
+                enrolled: stored.is_some(),
+                recovery_codes: stored.map(|stored| stored.recovery_codes.len()).unwrap_or_default(),
+            }
+        )
+    }
+
+    pub async fn remove(&self, config: &TwoFactor, user_id: &str) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let path = file(config)?;
+        let mut secrets: Vec<StoredSecret> = load(path).await?;
+        secrets.retain(|stored| stored.user != user_id);
+
+        save(path, &secrets).await
+    }
+
+    fn check_failures(&self, user_id: &str) -> Result<()> {
+        let mut failures = self.failures.lock().map_err(|_| anyhow!("failed attempts lock poisoned"))?;
+        failures.retain(|_, (_, since)| since.elapsed() < LOCKOUT);
+
+        match failures.get(user_id) {
+            Some((count, _)) if *count >= MAX_FAILURES => Err(TooManyAttemptsError.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn failed(&self, user_id: &str) -> anyhow::Error {
+        if let Ok(mut failures) = self.failures.lock() {
+            // the lockout starts with the first failure and isn't extended by further ones
+            failures.entry(user_id.to_string()).or_insert((0, Instant::now())).0 += 1;
+        }
+
+        InvalidCodeError.into()
+    }
+
+    fn clear_failures(&self, user_id: &str) {
+        if let Ok(mut failures) = self.failures.lock() {
+            failures.remove(user_id);
+        }
+    }
+}
+
+fn file(config: &TwoFactor) -> Result<&Path> {
+    config.file.as_deref().ok_or(anyhow!("second factor is not enabled"))
+}
+
+// recovery codes are random enough to not need a slow hash
+fn hash(code: &str) -> String {
+    HEXLOWER.encode(&Sha256::digest(code.trim().as_bytes()))
+}
+
+fn now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn current_code(secret: &str) -> String {
+        Otp::totp(BASE32_NOPAD.decode(secret.as_bytes()).unwrap()).unwrap().generate().unwrap().code
+    }
+
+    #[tokio::test]
+    async fn two_factor() {
+        let path = std::env::temp_dir().join(format!("keepass4web-test-{}.json", gen_token(8)));
+        let config = TwoFactor {
+            file: Some(path.clone()),
+            ..Default::default()
+        };
+        let store = TwoFactorStore::default();
+
+        let enrollment = store.enroll(&config, "alice").unwrap();
+        assert!(enrollment.uri.starts_with("otpauth://totp/KeePass4Web:alice?secret="));
+        assert!(!store.is_enrolled(&config, "alice").await.unwrap());
+        assert!(store.confirm(&config, "alice", &enrollment, "000000x").await.is_err());
+
+        let code = current_code(&enrollment.secret);
+        let recovery_codes = store.confirm(&config, "alice", &enrollment, &code).await.unwrap();
+        assert_eq!(recovery_codes.len(), RECOVERY_CODES);
+        assert!(store.is_enrolled(&config, "alice").await.unwrap());
+        assert!(store.confirm(&config, "alice", &enrollment, &code).await.is_err());
+
+        // the code was used for the enrollment already
+        assert!(store.verify(&config, "alice", &code).await.is_err_and(|err| err.downcast_ref::<InvalidCodeError>().is_some()));
+        store.verify(&config, "alice", &recovery_codes[0]).await.unwrap();
+        assert!(store.verify(&config, "alice", &recovery_codes[0]).await.is_err());
+        assert_eq!(store.status(&config, "alice").await.unwrap().recovery_codes, RECOVERY_CODES - 1);
+        assert!(store.verify(&config, "bob", &recovery_codes[1]).await.is_err());
+
+        // locked out, even with a valid code
+        for _ in 0..MAX_FAILURES {
+            let _ = store.verify(&config, "alice", "invalid").await;
+        }
+        let result = store.verify(&config, "alice", &recovery_codes[1]).await;
+        assert!(result.is_err_and(|err| err.downcast_ref::<TooManyAttemptsError>().is_some()));
+
+        store.remove(&config, "alice").await.unwrap();
+        let enrolled = store.is_enrolled(&config, "alice").await;
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        assert!(!enrolled.unwrap());
+        assert!(!store.status(&TwoFactor::default(), "alice").await.unwrap().enabled);
+    }
+}
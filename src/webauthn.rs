@@ -0,0 +1,643 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDateTime;
+use ciborium::Value;
+use data_encoding::BASE64URL_NOPAD;
+use keepass::db::Times;
+use p256::ecdsa::signature::Verifier;
+use rand::RngCore;
+use rand::thread_rng;
+use rsa::{BigUint, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::auth_backend::UserInfo;
+use crate::config::webauthn::Webauthn;
+use crate::file_store::{load, save};
+use crate::keepass::keepass::NotFoundError;
+
+const CHALLENGE_LENGTH: usize = 32;
+// milliseconds, as passed to the browser
+const CEREMONY_TIMEOUT: u64 = 5 * 60 * 1000;
+const MAX_NAME_LENGTH: usize = 64;
+
+// COSE algorithms, https://www.iana.org/assignments/cose/cose.xhtml#algorithms
+const ES256: i64 = -7;
+const RS256: i64 = -257;
+
+// authenticator data flags
+const USER_PRESENT: u8 = 0x01;
+const USER_VERIFIED: u8 = 0x04;
+const ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+// Challenge of a registration or login in progress, kept in the session
+#[derive(Serialize, Deserialize)]
+pub struct Ceremony {
+    challenge: String,
+    created: NaiveDateTime,
+    // the user has to be verified by the authenticator (PIN, biometrics), not only be present
+    user_verification: bool,
+}
+
+impl Ceremony {
+    fn new(user_verification: bool) -> Self {
+        let mut challenge = [0u8; CHALLENGE_LENGTH];
+        thread_rng().fill_bytes(&mut challenge);
+
+        Self {
+            challenge: BASE64URL_NOPAD.encode(&challenge),
+            created: Times::now(),
+            user_verification,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        expired(&self.created)
+    }
+}
+
+// Response of navigator.credentials.create(), binary fields base64url encoded
+#[derive(Deserialize)]
+pub struct RegistrationResponse {
+    pub name: String,
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+// Response of navigator.credentials.get(), binary fields base64url encoded
+#[derive(Deserialize)]
+pub struct LoginResponse {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+}
+
+// Passkey to remove, confirmed with an assertion of one of the user's passkeys or a TOTP/recovery code
+#[derive(Deserialize)]
+pub struct PasskeyRemove {
+    pub id: String,
+    pub assertion: Option<LoginResponse>,
+    pub code: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Passkey {
+    // credential id, base64url
+    pub id: String,
+    pub name: String,
+    pub created: NaiveDateTime,
+    pub last_used: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredPasskey {
+    #[serde(flatten)]
+    passkey: Passkey,
+    // user as of the registration, used for passwordless logins
+    user: UserInfo,
+    // COSE_Key, base64url
+    public_key: String,
+    sign_count: u32,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    // credential id and COSE_Key, registration only
+    credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+enum PublicKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    Rs256(RsaPublicKey),
+}
+
+// Registered passkeys of all users, kept in a file.
+// The lock serializes modifications of the file
+#[derive(Default)]
+pub struct PasskeyStore {
+    lock: Mutex<()>,
+    // challenges of finished ceremonies by creation time, until they expire.
+    // Removing the ceremony from the session doesn't prevent reuse, a client can send the previous session cookie again
+    used: std::sync::Mutex<HashMap<String, NaiveDateTime>>,
+}
+
+impl PasskeyStore {
+    // Options for navigator.credentials.create()
+    pub async fn start_registration(&self, config: &Webauthn, user_info: &UserInfo) -> Result<(Ceremony, serde_json::Value)> {
+        let exclude: Vec<serde_json::Value> = self.list(config, &user_info.id).await?
+            .into_iter()
+            .map(|passkey| json!({ "type": "public-key", "id": passkey.id }))
+            .collect();
+        let ceremony = Ceremony::new(false);
+
+        let options = json!({
+            "rp": {
+                "id": config.rp_id,
+                "name": config.rp_name,
+            },
+            "user": {
+                "id": BASE64URL_NOPAD.encode(&user_handle(&user_info.id)),
+                "name": user_info.id,
+                "displayName": user_info.name,
+            },
+            "challenge": ceremony.challenge,
+            "pubKeyCredParams": [
+                { "type": "public-key", "alg": ES256 },
+                { "type": "public-key", "alg": RS256 },
+            ],
+            "timeout": CEREMONY_TIMEOUT,
+            "excludeCredentials": exclude,
+            "authenticatorSelection": {
+                // discoverable credentials allow logins without a username
+                "residentKey": "preferred",
+                "userVerification": "preferred",
+            },
+            "attestation": "none",
+        });
+
+        Ok((ceremony, options))
+    }
+
+    pub async fn finish_registration(&self, config: &Webauthn, user_info: &UserInfo, ceremony: &Ceremony, response: &RegistrationResponse) -> Result<Passkey> {
+        let name = response.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            bail!("passkey name must be between 1 and {} characters", MAX_NAME_LENGTH);
+        }
+        verify_client_data(config, ceremony, &decode(&response.client_data_json)?, "webauthn.create")?;
+
+        let attestation: Value = ciborium::de::from_reader(decode(&response.attestation_object)?.as_slice())?;
+        // attestation statements aren't verified, none was requested
+        let auth_data = map_get_text(&attestation, "authData")
+            .and_then(Value::as_bytes)
+            .ok_or(anyhow!("attestation object without authenticator data"))?;
+        let auth_data = parse_authenticator_data(auth_data)?;
+        verify_authenticator_data(config, ceremony, &auth_data)?;
+
+        let (id, public_key) = auth_data.credential.ok_or(anyhow!("no credential in authenticator data"))?;
+        // fails for unsupported algorithms
+        parse_public_key(&public_key)?;
+        self.use_challenge(ceremony)?;
+
+        let passkey = Passkey {
+            id: BASE64URL_NOPAD.encode(&id),
+            name: name.to_string(),
+            created: Times::now(),
+            last_used: None,
+        };
+
+        let _guard = self.lock.lock().await;
+        let path = file(config)?;
+        let mut passkeys: Vec<StoredPasskey> = load(path).await?;
+        if passkeys.iter().any(|stored| stored.passkey.id == passkey.id) {
+            bail!("passkey already registered");
+        }
+        passkeys.push(StoredPasskey {
+            passkey: passkey.clone(),
+            user: user_info.clone(),
+            public_key: BASE64URL_NOPAD.encode(&public_key),
+            sign_count: auth_data.sign_count,
+        });
+        save(path, &passkeys).await?;
+
+        Ok(passkey)
+    }
+
+    // Options for navigator.credentials.get(). Without a user, any discoverable credential is accepted,
+    // as the passkey is the only factor then, the user has to be verified
+    pub async fn start_login(&self, config: &Webauthn, user_id: Option<&str>) -> Result<(Ceremony, serde_json::Value)> {
+        let allow: Vec<serde_json::Value> = match user_id {
+            Some(user_id) => self.list(config, user_id).await?
+                .into_iter()
+                .map(|passkey| json!({ "type": "public-key", "id": passkey.id }))
+                .collect(),
+            None => vec![],
+        };
+        let ceremony = Ceremony::new(user_id.is_none());
+
+        let options = json!({
+            "rpId": config.rp_id,
+            "challenge": ceremony.challenge,
+            "timeout": CEREMONY_TIMEOUT,
+            "allowCredentials": allow,
+            "userVerification": if ceremony.user_verification { "required" } else { "discouraged" },
+        });
+
+        Ok((ceremony, options))
+    }
+
+    // The user the passkey belongs to. With a user given, the passkey has to belong to that user
+    pub async fn finish_login(&self, config: &Webauthn, user_id: Option<&str>, ceremony: &Ceremony, response: &LoginResponse) -> Result<UserInfo> {
+        let client_data = decode(&response.client_data_json)?;
+        verify_client_data(config, ceremony, &client_data, "webauthn.get")?;
+
+        let raw_auth_data = decode(&response.authenticator_data)?;
+        let auth_data = parse_authenticator_data(&raw_auth_data)?;
+        verify_authenticator_data(config, ceremony, &auth_data)?;
+        // regardless of the ceremony, the passkey is the only factor without a user
+        if user_id.is_none() && auth_data.flags & USER_VERIFIED == 0 {
+            bail!("user not verified");
+        }
+
+        let _guard = self.lock.lock().await;
+        let path = file(config)?;
+        let mut passkeys: Vec<StoredPasskey> = load(path).await?;
+        let stored = passkeys.iter_mut()
+            .find(|stored| stored.passkey.id == response.id && user_id.is_none_or(|user_id| stored.user.id == user_id))
+            .ok_or(anyhow!("unknown passkey"))?;
+
+        let message = [raw_auth_data.as_slice(), Sha256::digest(&client_data).as_slice()].concat();
+        let signature = decode(&response.signature)?;
+        match parse_public_key(&decode(&stored.public_key)?)? {
+            PublicKey::Es256(key) => key.verify(&message, &p256::ecdsa::Signature::from_der(&signature)?)?,
+            PublicKey::Rs256(key) => rsa::pkcs1v15::VerifyingKey::<Sha256>::new(key)
+                .verify(&message, &rsa::pkcs1v15::Signature::try_from(signature.as_slice())?)?,
+        }
+        // the counter doesn't catch replays of authenticators without one, e.g. synced passkeys
+        self.use_challenge(ceremony)?;
+
+        // authenticators without a counter always report 0
+        if (auth_data.sign_count != 0 || stored.sign_count != 0) && auth_data.sign_count <= stored.sign_count {
+            bail!("signature counter of passkey '{}' went backwards, it might be cloned", stored.passkey.id);
+        }
+        stored.sign_count = auth_data.sign_count;
+        stored.passkey.last_used = Some(Times::now());
+        let user_info = stored.user.clone();
+        save(path, &passkeys).await?;
+
+        Ok(user_info)
+    }
+
+    pub async fn list(&self, config: &Webauthn, user_id: &str) -> Result<Vec<Passkey>> {
+        Ok(
+            load::<StoredPasskey>(file(config)?).await?
+                .into_iter()
+                .filter(|stored| stored.user.id == user_id)
+                .map(|stored| stored.passkey)
+                .collect()
+        )
+    }
+
+    pub async fn remove(&self, config: &Webauthn, user_id: &str, id: &str) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let path = file(config)?;
+        let mut passkeys: Vec<StoredPasskey> = load(path).await?;
+
+        let count = passkeys.len();
+        passkeys.retain(|stored| !(stored.passkey.id == id && stored.user.id == user_id));
+        if passkeys.len() == count {
+            return Err(NotFoundError("passkey").into());
+        }
+
+        save(path, &passkeys).await
+    }
+
+    fn use_challenge(&self, ceremony: &Ceremony) -> Result<()> {
+        let mut used = self.used.lock().map_err(|_| anyhow!("used challenges lock poisoned"))?;
+        used.retain(|_, created| !expired(created));
+
+        if used.insert(ceremony.challenge.clone(), ceremony.created).is_some() {
+            bail!("challenge already used");
+        }
+
+        Ok(())
+    }
+}
+
+fn expired(created: &NaiveDateTime) -> bool {
+    *created + chrono::Duration::milliseconds(CEREMONY_TIMEOUT as i64) <= Times::now()
+}
+
+fn file(config: &Webauthn) -> Result<&Path> {
+    config.file.as_deref().ok_or(anyhow!("passkeys are not enabled"))
+}
+
+fn decode(value: &str) -> Result<Vec<u8>> {
+    Ok(BASE64URL_NOPAD.decode(value.trim_end_matches('=').as_bytes())?)
+}
+
+// Opaque user handle, the user id itself shouldn't be stored on authenticators
+fn user_handle(user_id: &str) -> Vec<u8> {
+    Sha256::digest(user_id.as_bytes()).to_vec()
+}
+
+fn verify_client_data(config: &Webauthn, ceremony: &Ceremony, client_data: &[u8], kind: &str) -> Result<()> {
+    if ceremony.is_expired() {
+        bail!("ceremony expired");
+    }
+
+    let client_data: ClientData = serde_json::from_slice(client_data)?;
+    if client_data.kind != kind {
+        bail!("unexpected client data type '{}'", client_data.kind);
+    }
+    if client_data.challenge.trim_end_matches('=') != ceremony.challenge {
+        bail!("challenge mismatch");
+    }
+    let origin = Url::parse(&client_data.origin)?.origin();
+    if !config.origins()?.iter().any(|allowed| allowed.origin() == origin) {
+        bail!("origin '{}' not allowed", client_data.origin);
+    }
+
+    Ok(())
+}
+
+fn verify_authenticator_data(config: &Webauthn, ceremony: &Ceremony, auth_data: &AuthenticatorData) -> Result<()> {
+    if auth_data.rp_id_hash != Sha256::digest(config.rp_id.as_bytes()).as_slice() {
+        bail!("rp id mismatch");
+    }
+    if auth_data.flags & USER_PRESENT == 0 {
+        bail!("user not present");
+    }
+    if ceremony.user_verification && auth_data.flags & USER_VERIFIED == 0 {
+        bail!("user not verified");
+    }
+
+    Ok(())
+}
+
+// https://www.w3.org/TR/webauthn-2/#sctn-authenticator-data
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData> {
+    if data.len() < 37 {
+        bail!("authenticator data too short");
+    }
+    let flags = data[32];
+
+    let mut credential = None;
+    if flags & ATTESTED_CREDENTIAL_DATA != 0 {
+        // 16 bytes aaguid, 2 bytes length
+        let rest = data.get(37 + 16..).ok_or(anyhow!("attested credential data too short"))?;
+        let length = u16::from_be_bytes([
+            *rest.first().ok_or(anyhow!("attested credential data too short"))?,
+            *rest.get(1).ok_or(anyhow!("attested credential data too short"))?,
+        ]) as usize;
+        let id = rest.get(2..2 + length).ok_or(anyhow!("credential id too short"))?;
+
+        // the key is followed by extensions, if any
+        let mut reader = &rest[2 + length..];
+        let _: Value = ciborium::de::from_reader(&mut reader)?;
+        let key = &rest[2 + length..rest.len() - reader.len()];
+
+        credential = Some((id.to_vec(), key.to_vec()));
+    }
+
+    Ok(
+        AuthenticatorData {
+            rp_id_hash: data[..32].to_vec(),
+            flags,
+            sign_count: u32::from_be_bytes([data[33], data[34], data[35], data[36]]),
+            credential,
+        }
+    )
+}
+
+// https://www.rfc-editor.org/rfc/rfc9053, EC2 P-256 and RSA keys only
+fn parse_public_key(cose: &[u8]) -> Result<PublicKey> {
+    let key: Value = ciborium::de::from_reader(cose)?;
+    let bytes = |label: i64| map_get(&key, label).and_then(Value::as_bytes).ok_or(anyhow!("COSE key without parameter {}", label));
+    let alg = map_get(&key, 3).and_then(Value::as_integer).map(i128::from);
+
+    match alg {
+        Some(alg) if alg == ES256 as i128 => {
+            let point = [&[0x04], bytes(-2)?.as_slice(), bytes(-3)?.as_slice()].concat();
+            Ok(PublicKey::Es256(p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)?))
+        }
+        Some(alg) if alg == RS256 as i128 => {
+            let n = BigUint::from_bytes_be(bytes(-1)?);
+            let e = BigUint::from_bytes_be(bytes(-2)?);
+            Ok(PublicKey::Rs256(RsaPublicKey::new(n, e)?))
+        }
+        _ => bail!("unsupported COSE algorithm"),
+    }
+}
+
+fn map_get(map: &Value, label: i64) -> Option<&Value> {
+    map.as_map()?.iter()
+        .find(|(k, _)| k.as_integer().map(i128::from) == Some(label as i128))
+        .map(|(_, v)| v)
+}
+
+fn map_get_text<'a>(map: &'a Value, label: &str) -> Option<&'a Value> {
+    map.as_map()?.iter()
+        .find(|(k, _)| k.as_text() == Some(label))
+        .map(|(_, v)| v)
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::{SigningKey, signature::Signer};
+
+    use super::*;
+    use crate::auth::gen_token;
+
+    const ORIGIN: &str = "https://keepass.example.com";
+
+    // Software authenticator with a single ES256 credential
+    struct Authenticator {
+        key: SigningKey,
+        id: Vec<u8>,
+        rp_id: String,
+        sign_count: u32,
+        // synced passkeys don't count signatures
+        counter: bool,
+        flags: u8,
+    }
+
+    impl Authenticator {
+        fn new(rp_id: &str) -> Self {
+            let mut id = vec![0u8; 16];
+            thread_rng().fill_bytes(&mut id);
+
+            Self {
+                key: SigningKey::random(&mut p256::elliptic_curve::rand_core::OsRng),
+                id,
+                rp_id: rp_id.to_string(),
+                sign_count: 0,
+                counter: true,
+                flags: USER_PRESENT | USER_VERIFIED,
+            }
+        }
+
+        fn client_data(kind: &str, options: &serde_json::Value, origin: &str) -> Vec<u8> {
+            serde_json::to_vec(&json!({
+                "type": kind,
+                "challenge": options["challenge"],
+                "origin": origin,
+            })).unwrap()
+        }
+
+        fn auth_data(&self, flags: u8) -> Vec<u8> {
+            [Sha256::digest(self.rp_id.as_bytes()).as_slice(), &[flags], &self.sign_count.to_be_bytes()].concat()
+        }
+
+        fn create(&self, options: &serde_json::Value, origin: &str) -> RegistrationResponse {
+            let point = self.key.verifying_key().to_encoded_point(false);
+            let cose = Value::Map(vec![
+                (Value::from(1), Value::from(2)),
+                (Value::from(3), Value::from(ES256)),
+                (Value::from(-1), Value::from(1)),
+                (Value::from(-2), Value::Bytes(point.x().unwrap().to_vec())),
+                (Value::from(-3), Value::Bytes(point.y().unwrap().to_vec())),
+            ]);
+            let mut key = vec![];
+            ciborium::ser::into_writer(&cose, &mut key).unwrap();
+
+            let auth_data = [
+                self.auth_data(self.flags | ATTESTED_CREDENTIAL_DATA).as_slice(),
+                &[0u8; 16],
+                &(self.id.len() as u16).to_be_bytes(),
+                &self.id,
+                &key,
+            ].concat();
+            let attestation = Value::Map(vec![
+                (Value::from("fmt"), Value::from("none")),
+                (Value::from("attStmt"), Value::Map(vec![])),
+                (Value::from("authData"), Value::Bytes(auth_data)),
+            ]);
+            let mut attestation_object = vec![];
+            ciborium::ser::into_writer(&attestation, &mut attestation_object).unwrap();
+
+            RegistrationResponse {
+                name: "software".to_string(),
+                client_data_json: BASE64URL_NOPAD.encode(&Self::client_data("webauthn.create", options, origin)),
+                attestation_object: BASE64URL_NOPAD.encode(&attestation_object),
+            }
+        }
+
+        fn get(&mut self, options: &serde_json::Value, origin: &str) -> LoginResponse {
+            if self.counter {
+                self.sign_count += 1;
+            }
+            let client_data = Self::client_data("webauthn.get", options, origin);
+            let auth_data = self.auth_data(self.flags);
+            let signature: p256::ecdsa::Signature = self.key.sign(&[auth_data.as_slice(), Sha256::digest(&client_data).as_slice()].concat());
+
+            LoginResponse {
+                id: BASE64URL_NOPAD.encode(&self.id),
+                client_data_json: BASE64URL_NOPAD.encode(&client_data),
+                authenticator_data: BASE64URL_NOPAD.encode(&auth_data),
+                signature: BASE64URL_NOPAD.encode(signature.to_der().as_bytes()),
+            }
+        }
+    }
+
+    fn test_config() -> Webauthn {
+        Webauthn {
+            file: Some(std::env::temp_dir().join(format!("keepass4web-test-{}.json", gen_token(8)))),
+            rp_id: "example.com".to_string(),
+            origins: vec![Url::parse(ORIGIN).unwrap()],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn ceremonies() {
+        let config = test_config();
+        let path = config.file.clone().unwrap();
+        let store = PasskeyStore::default();
+        let alice = UserInfo {
+            id: "alice".to_string(),
+            db_location: Some("alice.kdbx".to_string()),
+            ..Default::default()
+        };
+        let mut authenticator = Authenticator::new("example.com");
+
+        // registration
+        let (ceremony, options) = store.start_registration(&config, &alice).await.unwrap();
+        assert_eq!(options["rp"]["id"], "example.com");
+        assert!(store.finish_registration(&config, &alice, &ceremony, &authenticator.create(&options, "https://evil.example.org")).await.is_err());
+        let (_, other) = store.start_registration(&config, &alice).await.unwrap();
+        assert!(store.finish_registration(&config, &alice, &ceremony, &authenticator.create(&other, ORIGIN)).await.is_err());
+        let passkey = store.finish_registration(&config, &alice, &ceremony, &authenticator.create(&options, ORIGIN)).await.unwrap();
+        assert_eq!(passkey.id, BASE64URL_NOPAD.encode(&authenticator.id));
+        assert!(store.finish_registration(&config, &alice, &ceremony, &authenticator.create(&options, ORIGIN)).await.is_err());
+
+        let (_, options) = store.start_registration(&config, &alice).await.unwrap();
+        assert_eq!(options["excludeCredentials"][0]["id"], passkey.id.as_str());
+
+        // second factor
+        let (ceremony, options) = store.start_login(&config, Some("alice")).await.unwrap();
+        assert_eq!(options["allowCredentials"][0]["id"], passkey.id.as_str());
+        assert_eq!(options["userVerification"], "discouraged");
+        let user_info = store.finish_login(&config, Some("alice"), &ceremony, &authenticator.get(&options, ORIGIN)).await.unwrap();
+        assert_eq!(user_info.db_location.as_deref(), Some("alice.kdbx"));
+        assert!(store.finish_login(&config, Some("bob"), &ceremony, &authenticator.get(&options, ORIGIN)).await.is_err());
+
+        // replayed response
+        let (ceremony, options) = store.start_login(&config, Some("alice")).await.unwrap();
+        let response = authenticator.get(&options, ORIGIN);
+        store.finish_login(&config, None, &ceremony, &response).await.unwrap();
+        assert!(store.finish_login(&config, None, &ceremony, &response).await.is_err());
+
+        // passwordless, the user has to be verified
+        let (ceremony, options) = store.start_login(&config, None).await.unwrap();
+        assert_eq!(options["userVerification"], "required");
+        authenticator.flags = USER_PRESENT;
+        assert!(store.finish_login(&config, None, &ceremony, &authenticator.get(&options, ORIGIN)).await.is_err());
+        authenticator.flags = USER_PRESENT | USER_VERIFIED;
+        assert_eq!(store.finish_login(&config, None, &ceremony, &authenticator.get(&options, ORIGIN)).await.unwrap().id, "alice");
+
+        // tampered signature
+        let (ceremony, options) = store.start_login(&config, None).await.unwrap();
+        let mut response = authenticator.get(&options, ORIGIN);
+        response.signature = authenticator.get(&options, ORIGIN).signature;
+        assert!(store.finish_login(&config, None, &ceremony, &response).await.is_err());
+
+        // other relying party
+        let mut other = Authenticator::new("example.org");
+        let (ceremony, options) = store.start_registration(&config, &alice).await.unwrap();
+        assert!(store.finish_registration(&config, &alice, &ceremony, &other.create(&options, ORIGIN)).await.is_err());
+        let (ceremony, options) = store.start_login(&config, None).await.unwrap();
+        assert!(store.finish_login(&config, None, &ceremony, &other.get(&options, ORIGIN)).await.is_err());
+
+        assert!(store.remove(&config, "bob", &passkey.id).await.is_err());
+        store.remove(&config, "alice", &passkey.id).await.unwrap();
+        let passkeys = store.list(&config, "alice").await;
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        assert!(passkeys.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn replay_without_counter() {
+        let config = test_config();
+        let store = PasskeyStore::default();
+        let alice = UserInfo {
+            id: "alice".to_string(),
+            ..Default::default()
+        };
+        let mut authenticator = Authenticator::new("example.com");
+        authenticator.counter = false;
+
+        let (ceremony, options) = store.start_registration(&config, &alice).await.unwrap();
+        store.finish_registration(&config, &alice, &ceremony, &authenticator.create(&options, ORIGIN)).await.unwrap();
+
+        let (ceremony, options) = store.start_login(&config, None).await.unwrap();
+        let response = authenticator.get(&options, ORIGIN);
+        let first = store.finish_login(&config, None, &ceremony, &response).await;
+        // the ceremony from a previous session cookie
+        let replayed = store.finish_login(&config, None, &ceremony, &response).await;
+
+        let (ceremony, options) = store.start_login(&config, None).await.unwrap();
+        let next = store.finish_login(&config, None, &ceremony, &authenticator.get(&options, ORIGIN)).await;
+        tokio::fs::remove_file(config.file.as_ref().unwrap()).await.unwrap();
+
+        assert_eq!(first.unwrap().id, "alice");
+        assert!(replayed.is_err());
+        assert_eq!(next.unwrap().id, "alice");
+    }
+}